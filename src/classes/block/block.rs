@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 
 use crate::classes::block::block_header:: BlockHeader;
-use crate::classes::transaction::tx::Tx;
+use crate::classes::transaction::tx::{CoinbaseData, Tx, TxInput};
 use crate::data_structures::merkle_tree::MerkleTree;
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -11,38 +11,56 @@ pub struct Block {
 }
 
 impl Block {
-    pub fn new(txs: &Vec<Tx>, prev_block_hash: String) -> Block {
+    pub fn new(txs: &[Tx], prev_block_hash: String, target: u32) -> Block {
         let tx_merkle_tree: MerkleTree = MerkleTree::new(txs);
         return Block {
-            block_header: BlockHeader::new(tx_merkle_tree.merkle_root.clone(), prev_block_hash),
+            block_header: BlockHeader::new(tx_merkle_tree.merkle_root.clone(), prev_block_hash, target),
             txs: tx_merkle_tree,
         };
     }
     
+    // the size of a block is the total size of its serialized transactions, in bytes
+    pub fn size(&self) -> usize {
+        self.txs.base.iter().map(|tx| tx.convert_tx_to_bytes().len()).sum()
+    }
+
+    /* Changes the extra nonce committed by the coinbase transaction, which changes the merkle root (and so the block hash) without changing anything else.
+    Returns false if the block has no coinbase transaction to change. */
+    pub fn set_extra_nonce(&mut self, extra_nonce: u64) -> bool {
+        let mut txs: Vec<Tx> = self.txs.base.clone();
+        let coinbase_input: &mut TxInput = match txs.first_mut().and_then(|coinbase_tx| coinbase_tx.inputs.first_mut()) {
+            Some(tx_input) if tx_input.is_coinbase => tx_input,
+            _ => return false,
+        };
+        let coinbase_data: &mut CoinbaseData = match coinbase_input.coinbase_data.as_mut() {
+            Some(coinbase_data) => coinbase_data,
+            None => return false,
+        };
+        coinbase_data.extra_nonce = extra_nonce;
+
+        self.txs = MerkleTree::new(&txs);
+        self.block_header.merkle_root = self.txs.merkle_root.clone();
+        true
+    }
+
+    pub fn extra_nonce(&self) -> Option<u64> {
+        let coinbase_data: CoinbaseData = self.txs.base.first()?.coinbase_data()?;
+        Some(coinbase_data.extra_nonce)
+    }
+
     pub fn mine_block(&mut self) {
-        let target: u8 = self.block_header.target;
         // let mut attempts: u128 = 0;
         loop {
-            let mut leading_zeros: u8 = 0;
-            let block_hash: String = self.block_header.hash_block(); // compute the hash of the block
-
-            // check to see if the block hash has enough leading zeros
-            for char in block_hash.chars() {
-                if char == '0' {
-                    leading_zeros += 1;
-                } else {
-                    break;
-                }
-            }
-
-            // if so, break from the loop and announce the answer (TODO: add a block propagation to announce to other nodes)
-            if leading_zeros >= target {
+            // if the block hash is at or below the target, break from the loop and announce the answer (TODO: add a block propagation to announce to other nodes)
+            if self.block_header.meets_target() {
                 break;
             }
 
-            // update the nonce if still not mined. if at the max, reset back to zero
+            // update the nonce if still not mined. if at the max, reset back to zero and try the next extra nonce, so that the same hashes are not tried again
             if self.block_header.nonce == u128::MAX {
                 self.block_header.nonce = 0;
+                let next_extra_nonce: u64 = self.extra_nonce().unwrap_or(0).wrapping_add(1);
+                self.set_extra_nonce(next_extra_nonce);
             } else {
                 self.block_header.nonce += 1;
             }
@@ -50,6 +68,36 @@ impl Block {
             // attempts += 1;
         }
 
-        println!("\n--- Mined the block! ---\nBlock hash: {0}\nNonce: {1}\nTimestamp: {2}", self.block_header.hash_block(), self.block_header.nonce, self.block_header.timestamp);
+        println!("\n--- Mined the block! ---\nBlock hash: {0}\nNonce: {1}\nTimestamp: {2}", self.block_header.hash_block().unwrap_or_default(), self.block_header.nonce, self.block_header.timestamp);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::classes::lamport_signature::key_pair::initialize_empty_key_blocks;
+    use crate::classes::transaction::tx::{Tx, TxInput, TxOutput};
+    use crate::data_structures::merkle_tree::MerkleTree;
+    use crate::util::test_util::{coinbase_tx, test_keypairs, TestChain};
+
+    use super::Block;
+
+    #[test]
+    fn set_extra_nonce() {
+        let pub_key = &test_keypairs()[0].pub_key;
+        let chain: TestChain = TestChain::new(pub_key);
+        let genesis_hash: String = chain.genesis_hash();
+
+        // changing the extra nonce changes the merkle root (and so the block hash), but not the rest of the header
+        let block: Block = chain.new_block(&[coinbase_tx(1, 0, pub_key.clone(), 100)], &genesis_hash).unwrap();
+        let mut extra_nonce_block: Block = block.clone();
+        assert!(extra_nonce_block.set_extra_nonce(7));
+        assert_eq!(extra_nonce_block.extra_nonce(), Some(7));
+        assert!(extra_nonce_block.block_header.merkle_root != block.block_header.merkle_root);
+        assert_eq!(extra_nonce_block.block_header.merkle_root, MerkleTree::new(&extra_nonce_block.txs.base).merkle_root);
+        assert_eq!(extra_nonce_block.block_header.nonce, block.block_header.nonce);
+
+        let no_height_coinbase: Tx = Tx::new(vec![TxInput::new(initialize_empty_key_blocks(), "".to_string(), true, 0)], vec![TxOutput::new(pub_key.clone(), 100)]);
+        let mut no_height_block: Block = chain.new_block(&[no_height_coinbase], &genesis_hash).unwrap();
+        assert!(!no_height_block.set_extra_nonce(7), "The extra nonce of a coinbase without one was changed");
+    }
+}
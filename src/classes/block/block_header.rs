@@ -1,46 +1,194 @@
 use rand::Rng;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use sha2::{Sha256, Digest};
 use hex;
 use chrono::Utc; 
-use bincode;
 
-#[derive(serde::Serialize, Deserialize, Clone)]
+use crate::classes::consensus::difficulty::{compact_to_target, target_work};
+use crate::data_structures::uint256::U256;
+
+// the version of the genesis block, and of a new header until it is set to signal for deployments
+pub const BLOCK_VERSION: u32 = 1;
+// the size of the canonical encoding of a block header, in bytes
+pub const HEADER_SIZE: usize = 96;
+
+/*
+    The canonical encoding of a block header is what gets hashed, and it is also how headers are serialized (on disk and on the wire), so the block hash
+    does not depend on how a serialization library lays out the struct. It has a fixed width of 96 bytes, with integers in little-endian order:
+
+        bytes  0..4    version (u32)
+        bytes  4..36   previous block hash (the 32 bytes of the hex string, in the same order; all zeros for the genesis block)
+        bytes 36..68   merkle root (the 32 bytes of the hex string, in the same order)
+        bytes 68..76   timestamp (i64)
+        bytes 76..80   target (u32, compact encoding)
+        bytes 80..96   nonce (u128)
+*/
+#[derive(Clone)]
 pub struct BlockHeader {
+    pub version: u32,
     pub prev_block_hash: String,
     pub merkle_root: String,
     pub nonce: u128,
-    pub target: u8,
+    // compact encoding of the 256-bit target that the block hash must not exceed
+    pub target: u32,
     pub timestamp: i64,
 }
 
 impl BlockHeader {
-    pub fn new(merkle_root: String, prev_block_hash: String) -> BlockHeader {
+    pub fn new(merkle_root: String, prev_block_hash: String, target: u32) -> BlockHeader {
         let mut rng: rand::prelude::ThreadRng = rand::thread_rng();
         return BlockHeader {
+            version: BLOCK_VERSION,
             prev_block_hash,
             merkle_root,
             nonce: rng.gen(),
-            // TODO: Target is an arbitrary number right now. develop a mechanism to dynamically change it based on network congestion
-            target: 4,
+            target,
             timestamp: Utc::now().timestamp(),
         }
     }
 
-    pub fn hash_block(&self) -> String {
-        let bytes: Vec<u8> = match bincode::serialize(self) {
-            Ok(value) => value,
-            Err(_e) => {
-                println!("Error! Could not hash block");
-                vec![]
-            }
-        };
+    // the hash of the canonical encoding of the header, or None if the header cannot be encoded (such a block is invalid)
+    pub fn hash_block(&self) -> Option<String> {
+        self.hash_block_bytes().map(hex::encode)
+    }
+
+    pub fn hash_block_bytes(&self) -> Option<[u8; 32]> {
+        let bytes: [u8; HEADER_SIZE] = self.to_bytes()?;
 
         let mut hasher = Sha256::new();
         hasher.update(bytes);
-        let result = hasher.finalize();
-        let hex_result: String = hex::encode(result);
+        Some(hasher.finalize().into())
+    }
+
+    // the canonical encoding of the header, or None if one of its hashes is not a 32-byte hex string
+    pub fn to_bytes(&self) -> Option<[u8; HEADER_SIZE]> {
+        let prev_block_hash: [u8; 32] = if self.prev_block_hash.is_empty() { [0; 32] } else { hex_to_hash_bytes(&self.prev_block_hash)? };
+        let merkle_root: [u8; 32] = hex_to_hash_bytes(&self.merkle_root)?;
+
+        let mut bytes: [u8; HEADER_SIZE] = [0; HEADER_SIZE];
+        bytes[0..4].copy_from_slice(&self.version.to_le_bytes());
+        bytes[4..36].copy_from_slice(&prev_block_hash);
+        bytes[36..68].copy_from_slice(&merkle_root);
+        bytes[68..76].copy_from_slice(&self.timestamp.to_le_bytes());
+        bytes[76..80].copy_from_slice(&self.target.to_le_bytes());
+        bytes[80..96].copy_from_slice(&self.nonce.to_le_bytes());
+        Some(bytes)
+    }
 
-        return hex_result;
+    // decodes a header from its canonical encoding, or returns None if it is not exactly HEADER_SIZE bytes long
+    pub fn from_bytes(bytes: &[u8]) -> Option<BlockHeader> {
+        if bytes.len() != HEADER_SIZE {
+            return None;
+        }
+
+        let prev_block_hash: &[u8] = &bytes[4..36];
+        Some(BlockHeader {
+            version: u32::from_le_bytes(bytes[0..4].try_into().ok()?),
+            prev_block_hash: if prev_block_hash.iter().all(|byte| *byte == 0) { "".to_string() } else { hex::encode(prev_block_hash) },
+            merkle_root: hex::encode(&bytes[36..68]),
+            timestamp: i64::from_le_bytes(bytes[68..76].try_into().ok()?),
+            target: u32::from_le_bytes(bytes[76..80].try_into().ok()?),
+            nonce: u128::from_le_bytes(bytes[80..96].try_into().ok()?),
+        })
+    }
+
+    // check to see if the block hash, read as a 256-bit number, is at or below the target
+    pub fn meets_target(&self) -> bool {
+        let target: U256 = match compact_to_target(self.target) {
+            Some(target) => target,
+            None => return false,
+        };
+
+        match self.hash_block_bytes() {
+            Some(hash) => U256::from_be_bytes(&hash) <= target,
+            None => false,
+        }
     }
-}
\ No newline at end of file
+
+    // the expected number of hashes needed to find a block with this target
+    pub fn work(&self) -> u128 {
+        target_work(self.target)
+    }
+}
+
+// headers are serialized with their canonical encoding, so that they are sent and stored exactly as they are hashed
+impl Serialize for BlockHeader {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.to_bytes() {
+            Some(bytes) => serializer.serialize_bytes(&bytes),
+            None => Err(serde::ser::Error::custom("the block header has an invalid hash and cannot be encoded")),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for BlockHeader {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bytes: Vec<u8> = Vec::deserialize(deserializer)?;
+        BlockHeader::from_bytes(&bytes).ok_or_else(|| serde::de::Error::custom(format!("a block header must be {} bytes long", HEADER_SIZE)))
+    }
+}
+
+fn hex_to_hash_bytes(hex_string: &String) -> Option<[u8; 32]> {
+    hex::decode(hex_string).ok()?.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::classes::block::block::Block;
+    use crate::util::test_util::{test_keypairs, TestChain};
+
+    use super::{BlockHeader, HEADER_SIZE};
+
+    #[test]
+    fn canonical_header_encoding() {
+        // a header with every field set, so that the order, width and byte order of each field is pinned
+        let header: BlockHeader = BlockHeader {
+            version: 2,
+            prev_block_hash: "00000000000000000000000000000000000000000000000000000000000000ff".to_string(),
+            merkle_root: "0102030405060708091011121314151617181920212223242526272829303132".to_string(),
+            timestamp: 1735577085,
+            target: 0x1f00ffff,
+            nonce: 0x0102030405060708090a0b0c0d0e0f10,
+        };
+        let header_bytes: [u8; HEADER_SIZE] = header.to_bytes().unwrap();
+        assert_eq!(hex::encode(header_bytes), "02000000\
+            00000000000000000000000000000000000000000000000000000000000000ff\
+            0102030405060708091011121314151617181920212223242526272829303132\
+            fdcd726700000000\
+            ffff001f\
+            100f0e0d0c0b0a090807060504030201");
+        assert_eq!(header.hash_block().unwrap(), "b0ab8f41c75bdc529b3619db0679de0b478f902f4fa29fb42914641c788dc801");
+
+        // decoding gives the same header back
+        let decoded_header: BlockHeader = BlockHeader::from_bytes(&header_bytes).unwrap();
+        assert_eq!(decoded_header.to_bytes().unwrap(), header_bytes);
+        assert_eq!(decoded_header.prev_block_hash, header.prev_block_hash);
+        assert!(BlockHeader::from_bytes(&header_bytes[..HEADER_SIZE - 1]).is_none(), "A truncated header was decoded");
+
+        // the genesis block has no previous block, which is encoded as 32 zero bytes
+        let chain: TestChain = TestChain::new(&test_keypairs()[0].pub_key);
+        let genesis_header: BlockHeader = chain.blocks[0].block_header.clone();
+        assert_eq!(hex::encode(genesis_header.to_bytes().unwrap()), "01000000\
+            0000000000000000000000000000000000000000000000000000000000000000\
+            edf6efecc93a482534e056aadc370e43fa8426ff41b24e56c325b247e676f84b\
+            fdcd726700000000\
+            ffff001f\
+            68370000000000000000000000000000");
+        assert_eq!(genesis_header.hash_block().unwrap(), "0000bdd62f65bf5178e8749ebf0a6b613ac0d176c7a6497277a99186c0283996");
+        assert_eq!(BlockHeader::from_bytes(&genesis_header.to_bytes().unwrap()).unwrap().prev_block_hash, "");
+
+        // blocks are serialized with the canonical header encoding, so a block read back has the same hash
+        let block_bytes: Vec<u8> = bincode::serialize(&chain.blocks[0]).unwrap();
+        assert!(block_bytes.windows(HEADER_SIZE).any(|window| window == genesis_header.to_bytes().unwrap()), "The block was not serialized with the canonical header encoding");
+        let decoded_block: Block = bincode::deserialize(&block_bytes).unwrap();
+        assert_eq!(decoded_block.block_header.hash_block().unwrap(), genesis_header.hash_block().unwrap());
+
+        // a header whose hashes are not 32-byte hex strings cannot be encoded
+        let mut invalid_header: BlockHeader = header.clone();
+        invalid_header.merkle_root = "not a hash".to_string();
+        assert!(invalid_header.to_bytes().is_none());
+        assert!(invalid_header.hash_block().is_none(), "A header that cannot be encoded was hashed");
+        assert!(!invalid_header.meets_target());
+        assert!(bincode::serialize(&invalid_header).is_err());
+    }
+}
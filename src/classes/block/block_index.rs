@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::classes::block::block::Block;
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum BlockStatus {
+    // the header and the structure of the block are valid, but its transactions have not been validated against the UTXO set of its branch yet
+    HeaderValid,
+    // the block has been fully validated, when it was connected to the valid chain
+    Valid,
+    Invalid,
+}
+
+#[derive(Clone)]
+pub struct BlockIndexEntry {
+    pub block: Block,
+    pub hash: String,
+    pub prev_hash: String,
+    pub height: u64,
+    // total proof-of-work of the branch from the genesis block up to and including this block
+    pub chain_work: u128,
+    pub status: BlockStatus,
+}
+
+/* Every known block (in the valid chain or in any branch) keyed by its header hash. The hash of a block is computed once, when it is inserted,
+and the parent links let any branch be traced back to the genesis block in O(depth) instead of rescanning every block. */
+#[derive(Clone, Default)]
+pub struct BlockIndex {
+    entries: HashMap<String, BlockIndexEntry>,
+    children: HashMap<String, Vec<String>>,
+    // block hashes in the order they were inserted, so that iterating over the index is deterministic
+    insertion_order: Vec<String>,
+}
+
+impl BlockIndex {
+    pub fn new() -> BlockIndex {
+        BlockIndex {
+            entries: HashMap::new(),
+            children: HashMap::new(),
+            insertion_order: vec![],
+        }
+    }
+
+    pub fn get(&self, block_hash: &str) -> Option<&BlockIndexEntry> {
+        self.entries.get(block_hash)
+    }
+
+    /* Adds a block to the index and returns its hash. A block can only be inserted if its header can be hashed and its parent is already indexed
+    (or if it has no parent, i.e. the genesis block). A block whose parent is invalid is invalid as well, and so is a block whose hash does not meet its target. */
+    pub fn insert(&mut self, block: &Block, status: BlockStatus) -> Option<String> {
+        let block_hash: String = block.block_header.hash_block()?;
+        if self.entries.contains_key(&block_hash) {
+            return Some(block_hash);
+        }
+
+        // the work of a block only counts once its hash is checked against the target it claims, otherwise any header could claim the work of the hardest target
+        let meets_target: bool = block.block_header.meets_target();
+        let block_work: u128 = if meets_target { block.block_header.work() } else { 0 };
+        let status: BlockStatus = if meets_target { status } else { BlockStatus::Invalid };
+
+        let prev_hash: String = block.block_header.prev_block_hash.clone();
+        let (height, chain_work, status): (u64, u128, BlockStatus) = if prev_hash.is_empty() {
+            (0, block_work, status)
+        } else {
+            match self.entries.get(&prev_hash) {
+                Some(parent) => {
+                    let inherited_status: BlockStatus = if parent.status == BlockStatus::Invalid { BlockStatus::Invalid } else { status };
+                    (parent.height + 1, parent.chain_work.saturating_add(block_work), inherited_status)
+                },
+                None => return None,
+            }
+        };
+
+        if !prev_hash.is_empty() {
+            self.children.entry(prev_hash.clone()).or_default().push(block_hash.clone());
+        }
+
+        self.entries.insert(block_hash.clone(), BlockIndexEntry {
+            block: block.clone(),
+            hash: block_hash.clone(),
+            prev_hash,
+            height,
+            chain_work,
+            status,
+        });
+        self.insertion_order.push(block_hash.clone());
+
+        Some(block_hash)
+    }
+
+    pub fn set_status(&mut self, block_hash: &str, status: BlockStatus) {
+        if let Some(entry) = self.entries.get_mut(block_hash) {
+            entry.status = status;
+        }
+    }
+
+    // marks the block, and every block built on top of it, as invalid
+    pub fn mark_invalid(&mut self, block_hash: &str) {
+        let mut invalid_hashes: Vec<String> = vec![block_hash.to_string()];
+        while let Some(invalid_hash) = invalid_hashes.pop() {
+            if let Some(entry) = self.entries.get_mut(&invalid_hash) {
+                entry.status = BlockStatus::Invalid;
+            }
+            if let Some(child_hashes) = self.children.get(&invalid_hash) {
+                invalid_hashes.extend(child_hashes.iter().cloned());
+            }
+        }
+    }
+
+    // a tip is a valid block that no other valid block points to, i.e. the final block of a branch
+    pub fn tips(&self) -> Vec<&BlockIndexEntry> {
+        let mut tips: Vec<&BlockIndexEntry> = vec![];
+        for block_hash in &self.insertion_order {
+            let entry: &BlockIndexEntry = &self.entries[block_hash];
+            if entry.status == BlockStatus::Invalid {
+                continue;
+            }
+
+            let has_valid_child: bool = match self.children.get(block_hash) {
+                Some(child_hashes) => child_hashes.iter().any(|child_hash| self.entries[child_hash].status != BlockStatus::Invalid),
+                None => false,
+            };
+
+            if !has_valid_child {
+                tips.push(entry);
+            }
+        }
+
+        tips
+    }
+
+    /* Fork choice: the valid chain is the branch with the most accumulated proof-of-work, not the one with the most blocks.
+    If several tips have the same amount of work, the one that was seen first wins, which keeps the choice deterministic. */
+    pub fn best_tip(&self) -> Option<&BlockIndexEntry> {
+        let mut best_tip: Option<&BlockIndexEntry> = None;
+        for tip in self.tips() {
+            match best_tip {
+                Some(current_best) if tip.chain_work <= current_best.chain_work => {},
+                _ => best_tip = Some(tip),
+            }
+        }
+
+        best_tip
+    }
+
+    // find the block at the given height in the branch that ends with the given block
+    pub fn ancestor(&self, block_hash: &str, height: u64) -> Option<&BlockIndexEntry> {
+        let mut current_entry: Option<&BlockIndexEntry> = self.entries.get(block_hash);
+
+        while let Some(entry) = current_entry {
+            if entry.height == height {
+                return Some(entry);
+            }
+            if entry.height < height {
+                return None;
+            }
+            current_entry = self.entries.get(&entry.prev_hash);
+        }
+
+        None
+    }
+
+    // the last block that the branches ending with the two given blocks have in common
+    pub fn fork_point(&self, first_hash: &str, second_hash: &str) -> Option<&BlockIndexEntry> {
+        let mut first_entry: &BlockIndexEntry = self.entries.get(first_hash)?;
+        let mut second_entry: &BlockIndexEntry = self.entries.get(second_hash)?;
+
+        while first_entry.hash != second_entry.hash {
+            if first_entry.height >= second_entry.height {
+                first_entry = self.entries.get(&first_entry.prev_hash)?;
+            } else {
+                second_entry = self.entries.get(&second_entry.prev_hash)?;
+            }
+        }
+
+        Some(first_entry)
+    }
+
+    // trace the parent links back from the given block to the genesis block, returning the blocks in chronological order
+    pub fn branch(&self, tip_hash: &str) -> Vec<Block> {
+        let mut branch_blocks: Vec<Block> = vec![];
+        let mut current_entry: Option<&BlockIndexEntry> = self.entries.get(tip_hash);
+
+        while let Some(entry) = current_entry {
+            branch_blocks.push(entry.block.clone());
+            current_entry = self.entries.get(&entry.prev_hash);
+        }
+
+        branch_blocks.reverse();
+        branch_blocks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::classes::block::block::Block;
+    use crate::classes::consensus::params::ConsensusParams;
+    use crate::util::test_util::{coinbase_tx, test_keypairs};
+
+    use super::{BlockIndex, BlockStatus};
+
+    #[test]
+    fn work_only_counts_when_target_is_met() {
+        let pub_key = &test_keypairs()[0].pub_key;
+        let params: ConsensusParams = ConsensusParams::new();
+        let mut block_index: BlockIndex = BlockIndex::new();
+
+        let mut parent: Block = Block::new(&[coinbase_tx(0, 0, pub_key.clone(), 100)], "".to_string(), params.pow_limit_bits);
+        parent.mine_block();
+        let parent_hash: String = block_index.insert(&parent, BlockStatus::Valid).unwrap();
+        assert!(block_index.get(&parent_hash).is_some());
+        let parent_work: u128 = block_index.get(&parent_hash).unwrap().chain_work;
+        assert_eq!(parent_work, parent.block_header.work());
+
+        // a header claiming a much harder target than the work behind its hash adds nothing to its branch
+        let unmined_block: Block = Block::new(&[coinbase_tx(1, 0, pub_key.clone(), 100)], parent_hash, 0x1b0404cb);
+        assert!(!unmined_block.block_header.meets_target());
+        let unmined_hash: String = block_index.insert(&unmined_block, BlockStatus::HeaderValid).unwrap();
+        assert_eq!(block_index.get(&unmined_hash).unwrap().chain_work, parent_work, "The work of a block that does not meet its target was counted");
+        assert!(block_index.get(&unmined_hash).unwrap().status == BlockStatus::Invalid);
+    }
+}
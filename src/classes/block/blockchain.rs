@@ -1,332 +1,809 @@
-use std::{collections::HashMap, vec};
+use std::{collections::HashMap, fmt, sync::{mpsc::{self, Receiver, Sender}, Arc}, vec};
 use serde::{Deserialize, Serialize};
-use crate::{classes::{block::block::Block, lamport_signature::key_pair::{initialize_empty_key_blocks, Key}, transaction::tx::{self, Tx, TxInput, TxOutput}}, data_structures::merkle_tree::MerkleTree, util::disk::{load_branches_from_file, save_chain_branches_to_file}};
+use crate::{classes::{block::block::Block, lamport_signature::key_pair::Key, transaction::{tx::Tx, mempool::{Mempool, MempoolError}, utxo_set::{BlockUndo, UtxoSet}}}, util::disk::save_chain_branches_to_file};
 
-use super::block_header::BlockHeader;
+use crate::classes::consensus::{chain_params::{ChainParams, GenesisParams}, clock::{Clock, SystemClock}, difficulty::{is_within_pow_limit, next_target}, params::ConsensusParams, timestamp::{check_block_timestamp, median_time_past, TimestampError}, versionbits::{deployment_state, next_block_version, DeploymentState, VersionBitsCache}};
+
+use super::block_index::{BlockIndex, BlockIndexEntry, BlockStatus};
+use super::chain_events::ChainEvent;
+use super::orphan_pool::{OrphanPool, MAX_ORPHAN_BLOCKS};
+use super::validation::{check_block_structure, check_block_transactions, check_coinbase_height, BlockError};
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Blockchain {
     pub blocks: Vec<Block>,
     last_checked_height: u128,
-    pub utxo: Vec<Tx>
+    #[serde(skip)]
+    pub utxo: UtxoSet,
+    // undo data of every block in the valid chain, keyed by block hash
+    #[serde(skip)]
+    undo_data: HashMap<String, BlockUndo>,
+    // hash of the last block of the valid chain
+    #[serde(skip)]
+    tip_hash: Option<String>,
+    #[serde(skip)]
+    pub block_index: BlockIndex,
+    #[serde(skip)]
+    pub params: ConsensusParams,
+    #[serde(skip)]
+    pub genesis: GenesisParams,
+    #[serde(skip, default = "default_clock")]
+    pub clock: Arc<dyn Clock>,
+    #[serde(skip)]
+    subscribers: Vec<Sender<ChainEvent>>,
+    #[serde(skip, default = "default_orphan_pool")]
+    pub orphan_pool: OrphanPool,
+    #[serde(skip)]
+    pub mempool: Mempool,
+    #[serde(skip)]
+    version_bits_cache: VersionBitsCache,
+}
+
+pub enum BlockAcceptance {
+    Accepted,
+    Orphaned { missing_parent_hash: String },
+    Rejected,
+}
+
+// a branch loaded from disk that does not start with the genesis block of this network
+#[derive(Debug)]
+pub struct GenesisMismatch {
+    pub expected_hash: String,
+    // None if the header of the first block cannot be encoded
+    pub found_hash: Option<String>,
+}
+
+impl fmt::Display for GenesisMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let found_hash: &str = self.found_hash.as_deref().unwrap_or("without a hash");
+        write!(f, "The saved chain starts with block {} instead of the genesis block {} of this network", found_hash, self.expected_hash)
+    }
+}
+
+fn default_clock() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
+
+fn default_orphan_pool() -> OrphanPool {
+    OrphanPool::new(MAX_ORPHAN_BLOCKS)
 }
 
 impl Blockchain {
+    // a blockchain of the main network
     pub fn new() -> Blockchain {
+        Blockchain::with_chain_params(&ChainParams::main())
+    }
+
+    pub fn with_chain_params(chain_params: &ChainParams) -> Blockchain {
         return Blockchain {
             blocks: vec![],
             last_checked_height: 0,
-            utxo: vec![]
+            utxo: UtxoSet::new(),
+            undo_data: HashMap::new(),
+            tip_hash: None,
+            block_index: BlockIndex::new(),
+            params: chain_params.consensus.clone(),
+            genesis: chain_params.genesis.clone(),
+            clock: default_clock(),
+            subscribers: vec![],
+            orphan_pool: default_orphan_pool(),
+            mempool: Mempool::default(),
+            version_bits_cache: VersionBitsCache::default(),
         };
     }
 
-    pub fn accept_new_block(&mut self, block: &Block, branches_filename: &String) {
-        let prev_block_hash = &block.block_header.prev_block_hash;
-        let mut found_prev_block: bool = false;
-
-        if self.blocks.len() == 0 {
-            if block.block_header.hash_block() == "00008e0b7979f791f1901bb55d418f33b9db1c830ae62fd2a4bcd7d8a215a5c3".to_string() {
-                self.blocks.push(block.clone());
-                self.update_utxo();
-                return;
+    /*
+        Accepts a new block into the block index, and then does the same for any orphan blocks that were waiting for it (and for their own orphan children, and so on).
+        A block whose parent is not known yet is kept in the orphan pool, and the hash of the missing parent is returned so that it can be requested from the peer that sent the block.
+    */
+    pub fn accept_new_block(&mut self, block: &Block, branches_filename: &str) -> BlockAcceptance {
+        let block_hash: String = match block.block_header.hash_block() {
+            Some(block_hash) => block_hash,
+            None => {
+                println!("New block header cannot be encoded, so it has no hash...");
+                return BlockAcceptance::Rejected;
             }
+        };
+        let acceptance: BlockAcceptance = self.index_block_and_orphans(block, &block_hash);
+
+        if let BlockAcceptance::Accepted = acceptance {
+            self.choose_valid_chain_and_update_utxo(branches_filename);
         }
 
-        // try to find the new block in the valid chain
-        for block_in_chain in &self.blocks {
-            if &block_in_chain.block_header.hash_block() == prev_block_hash {
-                found_prev_block = true;
+        acceptance
+    }
+
+    fn index_block_and_orphans(&mut self, block: &Block, block_hash: &String) -> BlockAcceptance {
+        let acceptance: BlockAcceptance = self.index_new_block(block, block_hash);
+
+        if let BlockAcceptance::Accepted = acceptance {
+            let mut accepted_hashes: Vec<String> = vec![block_hash.clone()];
+            while let Some(accepted_hash) = accepted_hashes.pop() {
+                for (orphan_hash, orphan_block) in self.orphan_pool.take_children(&accepted_hash) {
+                    println!("Processing orphan block {}...", orphan_hash);
+                    if let BlockAcceptance::Accepted = self.index_new_block(&orphan_block, &orphan_hash) {
+                        accepted_hashes.push(orphan_hash);
+                    }
+                }
             }
         }
 
-        // if not in the valid chain, check in the chains stored in disk
-        if !found_prev_block {
-            let loaded_branch_chains_result: Result<Vec<Blockchain>, ()> = load_branches_from_file(branches_filename);
-            let mut loaded_branch_chains: Vec<Blockchain> = match loaded_branch_chains_result {
-                Ok(val) => val,
-                Err(()) => vec![],
-            };
+        acceptance
+    }
 
-            if loaded_branch_chains.len() == 0 {
-                println!("Could not load branch chains from disk...");
-                return;
+    fn index_new_block(&mut self, block: &Block, block_hash: &String) -> BlockAcceptance {
+        let prev_block_hash = &block.block_header.prev_block_hash;
+
+        // a block without a parent can only be the genesis block of this network, which has no parent to be checked against
+        if prev_block_hash.is_empty() {
+            if *block_hash != self.genesis.hash {
+                println!("New block has no parent but is not the genesis block of this network...");
+                return BlockAcceptance::Rejected;
             }
 
-            let mut branch_index: usize = 0;
-            for branch_chain in loaded_branch_chains.clone() {
-                for block_in_branch in &branch_chain.blocks {
-                    if &block_in_branch.block_header.hash_block() == prev_block_hash {
-                        found_prev_block = true;
-                        loaded_branch_chains[branch_index].blocks.push(block_in_branch.clone());
-                    }
-                }
-                branch_index += 1;
+            if self.block_index.get(block_hash).is_none() {
+                self.block_index.insert(block, BlockStatus::HeaderValid);
+                return BlockAcceptance::Accepted;
             }
+        }
 
-            // if still not found, the block is invalid as it doesn't point to anything
-            if !found_prev_block {
-                println!("New block does not point to any existing block...");
-                return;
+        if let Some(known_entry) = self.block_index.get(block_hash) {
+            if known_entry.status == BlockStatus::Invalid {
+                println!("New block has already been rejected as invalid...");
+            } else {
+                println!("New block is already part of the chain...");
             }
+            return BlockAcceptance::Rejected;
         }
 
-        let mut block_verified: bool = true;
-        // verify each transaction in the block (including checking the signature)
-        let mut tx_index: usize = 0;
-        for block_tx in &block.txs.base {
-            if tx_index == 0 { // if its the coinbase transaction
-                if !block_tx.inputs[0].is_coinbase {
-                    println!("Something's wrong. The first tx in the block is not a coinbase transaction");
-                    block_verified = false;
-                    break;
+        // the new block must point to a block in the valid chain or in one of its branches, otherwise it is kept as an orphan until its parent arrives
+        let parent: &BlockIndexEntry = match self.block_index.get(prev_block_hash) {
+            Some(entry) => entry,
+            None => {
+                // the target of an orphan cannot be checked against its parent yet, but it must carry valid proof-of-work, so that the pool cannot be filled for free
+                if !is_within_pow_limit(block.block_header.target, &self.params) || !block.block_header.meets_target() {
+                    println!("New block does not point to any existing block and does not meet a valid proof-of-work target...");
+                    return BlockAcceptance::Rejected;
                 }
 
-                if block_tx.inputs.len() > 1 || block_tx.outputs.len() > 1 {
-                    println!("The first transaction in the new block has too many inputs and/or outputs.");
-                    block_verified = false;
-                    break;
-                }
+                println!("New block does not point to any existing block, keeping it as an orphan...");
+                self.orphan_pool.add(block, block_hash);
+                return BlockAcceptance::Orphaned { missing_parent_hash: prev_block_hash.clone() };
+            }
+        };
 
-                // TODO: add fees mechanism
-                if block_tx.outputs[0].amount != 100 {
-                    println!("The coinbase transaction does not have the right amount of money...");
-                    block_verified = false;
-                    break;
-                }
+        if next_target(parent, &self.block_index, &self.params) != block.block_header.target {
+            println!("New block does not have the target required by the difficulty schedule...");
+            return BlockAcceptance::Rejected;
+        }
 
-                tx_index += 1;
-                continue;
-            }            
-
-            // check to see if any inputs claim that they are a coinbase transaction
-            let mut correct_coinbase_status = true;
-            for tx_input in &block_tx.inputs {
-                if tx_input.is_coinbase {
-                    correct_coinbase_status = false;
-                    break;
-                }
-            }
+        if !block.block_header.meets_target() {
+            println!("New block does not meet the proof-of-work target...");
+            return BlockAcceptance::Rejected;
+        }
 
-            if !correct_coinbase_status {
-                println!("At least one tx input in the new block has an incorrect coinbase status");
-                block_verified = false;
-                break;
-            }
-            
-            let verified_tx: bool = block_tx.verify_transaction(&self.utxo);
-            if !verified_tx {
-                block_verified = false;
-                break;
-            }
-            tx_index += 1;
+        let timestamp_result: Result<(), TimestampError> = check_block_timestamp(&block.block_header, parent, &self.block_index, &self.params, self.clock.now());
+        if let Err(timestamp_error) = timestamp_result {
+            println!("{}...", timestamp_error);
+            return BlockAcceptance::Rejected;
         }
 
-        let block_hash_chars: Vec<char> = block.block_header.hash_block().chars().collect();
-        for i in 0..4 {
-            if block_hash_chars[i] != '0' {
-                block_verified = false;
-                break;
-            }
+        let height: u64 = parent.height + 1;
+
+        // blocks with valid proof-of-work but an invalid structure are remembered, so that they (and any block built on top of them) are not verified again
+        if let Err(block_error) = check_block_structure(block, &self.params).and_then(|_| check_coinbase_height(block, height)) {
+            println!("{}...", block_error);
+            self.block_index.insert(block, BlockStatus::Invalid);
+            return BlockAcceptance::Rejected;
         }
 
-        /* if the new block is verified within a disk stored branch, then push all of the blocks in all chains, except for the last one in the branch
-            that the new block exists in, of the branch to the valid chain manually. then call add_new_block to add the last block, while also computing the branches within the chain and picking the new valid chain */ 
-        if block_verified {
-            self.insert_disk_blocks(branches_filename);
-            self.blocks.push(block.clone());
-            self.choose_valid_chain_and_update_utxo(branches_filename);
+        /* the transactions of the block are only validated once its branch becomes the valid chain, against the UTXO set of that branch.
+        until then, the block is kept as header-valid (pending) */
+        self.block_index.insert(block, BlockStatus::HeaderValid);
+        BlockAcceptance::Accepted
+    }
+
+    // the hash of the last block of the valid chain
+    pub fn active_tip_hash(&self) -> Option<String> {
+        self.tip_hash.clone()
+    }
+
+    // the height of the next block of the valid chain, found from its tip in the block index (0 while there is no valid chain yet)
+    pub fn next_block_height(&self) -> u64 {
+        match self.tip_hash.as_ref().and_then(|tip_hash| self.block_index.get(tip_hash)) {
+            Some(tip) => tip.height + 1,
+            None => 0,
         }
+    }
+
+    // the state of every deployment for the next block of the valid chain
+    pub fn deployment_states(&self) -> Vec<(String, DeploymentState)> {
+        let tip: &BlockIndexEntry = match self.tip_hash.as_ref().and_then(|tip_hash| self.block_index.get(tip_hash)) {
+            Some(entry) => entry,
+            None => return vec![],
+        };
 
+        self.params.deployments.iter()
+            .map(|deployment| (deployment.name.clone(), deployment_state(tip, &self.block_index, &self.params, deployment, &self.version_bits_cache)))
+            .collect()
     }
 
-    pub fn choose_valid_chain_and_update_utxo(&mut self, branches_filename: &String) {
-        let mut branches_block_hashes: Vec<Vec<String>> = vec![];
-        
-        let reversed_blocks: &Vec<Block> = &self.blocks.iter().rev().cloned().collect();
-        let mut checked_blocks: HashMap<String, bool> = HashMap::new();
-        
-        /*
-            Identify all branches an store them. 
-            Every branch will have a unique final block in the chain.
-            Check for those unique final blocks and trace back to the first block to identify the individual branches
-            Store the already checked blocks so that you won't trace back in the same branch but in a shorter (in height of the branch) block
-        */
-        for block_in_chain in reversed_blocks {
-            let mut branch_block_hashes: Vec<String> = vec![];
-            let mut prev_branch_block_hash: &String = &block_in_chain.block_header.prev_block_hash;
-
-            // if the current block has already been checked, it cannot be a unique final block of a branch, so skip to the next block
-            if checked_blocks.contains_key(&block_in_chain.block_header.hash_block()) {
-                continue;
-            }
+    // a new block built on top of the given block, with the target and a timestamp that the consensus rules require
+    pub fn new_block(&self, txs: &[Tx], prev_block_hash: &str) -> Option<Block> {
+        let parent: &BlockIndexEntry = self.block_index.get(prev_block_hash)?;
+        let mut block: Block = Block::new(txs, prev_block_hash.to_string(), next_target(parent, &self.block_index, &self.params));
+        block.block_header.version = next_block_version(parent, &self.block_index, &self.params, &self.version_bits_cache);
 
-            branch_block_hashes.push(block_in_chain.block_header.hash_block());
-            checked_blocks.insert(block_in_chain.block_header.hash_block(), true);
+        // the timestamp must be after the median time past, even if this node's clock is behind the previous blocks
+        let min_timestamp: i64 = median_time_past(parent, &self.block_index, self.params.median_time_span) + 1;
+        block.block_header.timestamp = self.clock.now().max(min_timestamp);
 
-            // go back through the reversed blockchain, and trace through the blocks to construct the branch
-            for block_in_chain2 in reversed_blocks {
-                if prev_branch_block_hash == &block_in_chain2.block_header.hash_block() {
-                    branch_block_hashes.push(block_in_chain2.block_header.hash_block());
-                    checked_blocks.insert(block_in_chain2.block_header.hash_block(), true);
-                    prev_branch_block_hash = &block_in_chain2.block_header.prev_block_hash;
-                } 
+        Some(block)
+    }
+
+    // the total fees of a block in the valid chain: the outputs it spent (kept in its undo data) minus the outputs of its non-coinbase transactions
+    pub fn block_fees(&self, block_hash: &str) -> Option<u64> {
+        let entry: &BlockIndexEntry = self.block_index.get(block_hash)?;
+        let undo: &BlockUndo = self.undo_data.get(block_hash)?;
+
+        let spent_amount: u64 = undo.spent_outputs.iter().map(|(_outpoint, spent_entry)| spent_entry.output.amount).sum();
+        let mut created_amount: u64 = 0;
+        for block_tx in entry.block.txs.base.iter().skip(1) {
+            created_amount += block_tx.output_amount()?;
+        }
+
+        spent_amount.checked_sub(created_amount)
+    }
+
+    pub fn choose_valid_chain_and_update_utxo(&mut self, branches_filename: &str) {
+        self.activate_best_chain();
+
+        // every branch has a unique final block (a tip) in the block index, so trace back from each tip through the parent links to construct the individual branches
+        let mut branches: Vec<Blockchain> = vec![];
+        for tip in self.block_index.tips() {
+            let mut branch: Blockchain = Blockchain::new();
+            branch.blocks = self.block_index.branch(&tip.hash);
+            branches.push(branch);
+        }
+
+        if save_chain_branches_to_file(&branches, branches_filename).is_ok() {
+            println!("Saved branches to disk...");
+        }
+    }
+
+    pub fn subscribe(&mut self) -> Receiver<ChainEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    fn emit(&mut self, event: ChainEvent) {
+        // subscribers that dropped their receiver are forgotten
+        self.subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+
+    /* Adds the branches loaded from disk to the block index, as long as every one of them starts with the genesis block of this network. Branches saved
+    by a node of another network, or before the genesis hash changed (the canonical header encoding changed the hash of every block), are not indexed at all. */
+    pub fn insert_branches(&mut self, branches: &[Blockchain]) -> Result<(), GenesisMismatch> {
+        for branch in branches {
+            if let Some(first_block) = branch.blocks.first() {
+                let first_block_hash: Option<String> = first_block.block_header.hash_block();
+                if first_block_hash.as_ref() != Some(&self.genesis.hash) {
+                    return Err(GenesisMismatch { expected_hash: self.genesis.hash.clone(), found_hash: first_block_hash });
+                }
             }
+        }
 
-            // reverse the block hashes to be in chronological order once again
-            branch_block_hashes.reverse();
-            branches_block_hashes.push(branch_block_hashes);
+        for branch in branches {
+            self.insert_blocks(&branch.blocks);
         }
 
-        if branches_block_hashes.len() > 1 {
-            let mut i: usize = 0;
-            let mut biggest_branch_block_hashes_index: usize = 0;
-            let mut biggest_branch_block_hashes_num: usize = 0;
+        Ok(())
+    }
 
-            for branch_block_hashes in &branches_block_hashes {
-                if branch_block_hashes.len() > biggest_branch_block_hashes_num {
-                    biggest_branch_block_hashes_num = branch_block_hashes.len();
-                    biggest_branch_block_hashes_index = i;
+    /* Adds blocks that were loaded from disk or retrieved from a peer to the block index, skipping the ones that are already indexed. They go through the
+    same header checks as a new block, parents first, and a block that fails them is remembered as invalid (along with anything built on top of it). */
+    pub fn insert_blocks(&mut self, blocks: &[Block]) {
+        for block in blocks {
+            let block_hash: String = match block.block_header.hash_block() {
+                Some(block_hash) => block_hash,
+                None => {
+                    println!("Could not index a block whose header cannot be encoded...");
+                    continue;
                 }
-                i += 1;
+            };
+
+            // the branches loaded from disk share their first blocks
+            if self.block_index.get(&block_hash).is_some() {
+                continue;
             }
 
-            let mut branches: Vec<Blockchain> = vec![];
-            for branch_block_hashes in &branches_block_hashes {
-                let mut branch: Blockchain = Blockchain::new();
-                for branch_block_hash in branch_block_hashes {
-                    for block in &self.blocks {
-                        if branch_block_hash == &block.block_header.hash_block() {
-                            branch.blocks.push(block.clone());
-                        }
-                    }
+            if let BlockAcceptance::Rejected = self.index_block_and_orphans(block, &block_hash) {
+                if self.block_index.get(&block_hash).is_none() {
+                    self.block_index.insert(block, BlockStatus::Invalid);
                 }
-                branches.push(branch);
             }
+        }
+    }
 
-            match save_chain_branches_to_file(&branches, branches_filename) {
-                Ok(()) => {
-                    println!("Saved branches to disk...");
-                },
-                Err(()) => {}
+    /*
+        Makes the branch with the most accumulated work the valid chain. If a block of that branch turns out to be invalid while it is being connected,
+        the block (and every block built on top of it) is marked as invalid and the next best branch is tried instead.
+    */
+    fn activate_best_chain(&mut self) {
+        loop {
+            let best_tip_hash: String = match self.block_index.best_tip() {
+                Some(best_tip) => best_tip.hash.clone(),
+                None => return,
             };
 
-            self.blocks = branches[biggest_branch_block_hashes_index].blocks.clone();
-        } else {
-            match save_chain_branches_to_file(&vec![self.clone()], branches_filename) {
-                Ok(()) => {
-                    println!("Saved branches to disk...");
-                },
-                Err(()) => {}
-            };
+            if self.tip_hash.as_ref() == Some(&best_tip_hash) {
+                return;
+            }
+
+            match self.reorganize(&best_tip_hash) {
+                Ok(()) => return,
+                Err(invalid_block_hash) => self.block_index.mark_invalid(&invalid_block_hash),
+            }
+        }
+    }
+
+    /*
+        Switches the valid chain to the branch ending with the given block, updating the UTXO set incrementally instead of rebuilding it from the genesis block:
+        the blocks of the old valid chain after the fork point are disconnected (putting back the outputs they spent using their undo data), and then the blocks
+        of the new branch are validated and connected one by one. If one of them is invalid, the new blocks are disconnected again, the old valid chain is reconnected,
+        and the hash of the invalid block is returned.
+    */
+    fn reorganize(&mut self, new_tip_hash: &str) -> Result<(), String> {
+        let fork_hash: Option<String> = match &self.tip_hash {
+            Some(old_tip_hash) => self.block_index.fork_point(old_tip_hash, new_tip_hash).map(|fork_entry| fork_entry.hash.clone()),
+            None => None,
+        };
+
+        let mut disconnected_hashes: Vec<String> = vec![];
+        while self.tip_hash.is_some() && self.tip_hash != fork_hash {
+            disconnected_hashes.push(self.disconnect_tip());
+        }
+
+        let mut connect_hashes: Vec<String> = vec![];
+        let mut current_entry: Option<&BlockIndexEntry> = self.block_index.get(new_tip_hash);
+        while let Some(entry) = current_entry {
+            if Some(&entry.hash) == fork_hash.as_ref() {
+                break;
+            }
+            connect_hashes.push(entry.hash.clone());
+            current_entry = self.block_index.get(&entry.prev_hash);
         }
+        connect_hashes.reverse();
+
+        for block_hash in &connect_hashes {
+            if let Err(block_error) = self.connect_block(block_hash) {
+                println!("Block {} is invalid: {}...", block_hash, block_error);
 
-        self.update_utxo();
-    }
-
-    pub fn update_utxo(&mut self){
-        let mut new_utxo: Vec<Tx> = vec![];
-
-        for block in &self.blocks {
-            // go through each transaction in the new block
-            for tx in &block.txs.base {
-                // if no previous transaction to point to (coinbase transaction)
-                if tx.inputs[0].is_coinbase { 
-                    new_utxo.push(tx.clone());
-                } else {
-                    // iterate through each input for every transaction in the block
-                    for new_tx_input in &tx.inputs {
-                        if new_tx_input.is_coinbase {
-                            continue;
-                        }
-
-                        let prev_tx_id: String = new_tx_input.prev_tx_id.clone();
-                        let prev_tx_index: usize = new_tx_input.index;
-
-                        let mut utxo_tx_index: usize = 0;
-                        let mut found_matching_output = false;
-                        for utxo_tx in new_utxo.clone() {
-                            let utxo_tx_id: String = utxo_tx.get_tx_id();
-                            let mut deleted_utxo_tx = false;
-                            if prev_tx_id == utxo_tx_id { // if found the match, remove the output from the consumed utxo transaction
-                                found_matching_output = true;
-                                new_utxo[utxo_tx_index].outputs.remove(prev_tx_index);
-
-                                // if there are no outputs left in the consumed utxo transaction, delete the utxo transaction
-                                if new_utxo[utxo_tx_index].outputs.len() == 0 {
-                                    new_utxo.remove(utxo_tx_index.clone());
-                                    deleted_utxo_tx = true;
-                                }
-                            } 
-                            if !deleted_utxo_tx {
-                                utxo_tx_index += 1;
-                            }
-                        }
-
-                        if !found_matching_output {
-                            println!("Could not find the matching output for a new transaction.");
-                            return;
-                        }
+                while self.tip_hash.is_some() && self.tip_hash != fork_hash {
+                    self.disconnect_tip();
+                }
+                for disconnected_hash in disconnected_hashes.iter().rev() {
+                    if let Err(reconnect_error) = self.connect_block(disconnected_hash) {
+                        println!("Could not reconnect block {} of the previous valid chain: {}...", disconnected_hash, reconnect_error);
+                        break;
                     }
+                }
+
+                return Err(block_hash.clone());
+            }
+        }
+
+        self.update_mempool(&disconnected_hashes, &connect_hashes);
+
+        let tip_entry: &BlockIndexEntry = self.block_index.get(new_tip_hash).unwrap();
+        let tip_changed_event: ChainEvent = ChainEvent::TipChanged { tip_hash: tip_entry.hash.clone(), height: tip_entry.height };
+        self.emit(tip_changed_event);
+
+        Ok(())
+    }
+
+    /* Keeps the mempool in line with the new valid chain: the transactions included in the connected blocks (and the ones conflicting with them) are removed,
+    and the transactions of the disconnected blocks are put back so that they can be included in a block of the new valid chain. */
+    fn update_mempool(&mut self, disconnected_hashes: &[String], connected_hashes: &[String]) {
+        let now: i64 = self.clock.now();
+
+        for connected_hash in connected_hashes {
+            if let Some(connected_entry) = self.block_index.get(connected_hash) {
+                self.mempool.remove_for_block(&connected_entry.block);
+            }
+        }
 
-                    new_utxo.push(tx.clone());
+        if !disconnected_hashes.is_empty() {
+            // the blocks were disconnected from the tip down, so their transactions are put back from the oldest block to the newest one
+            let mut disconnected_txs: Vec<Tx> = vec![];
+            for disconnected_hash in disconnected_hashes.iter().rev() {
+                if let Some(disconnected_entry) = self.block_index.get(disconnected_hash) {
+                    disconnected_txs.extend(disconnected_entry.block.txs.base.iter().skip(1).cloned());
                 }
             }
+
+            let next_block_height: u64 = self.next_block_height();
+            self.mempool.readd_after_reorg(&disconnected_txs, &self.utxo, next_block_height, &self.params, now);
         }
 
-        self.utxo = new_utxo;
+        self.mempool.expire(now);
     }
 
-    pub fn load_genesis_block(&mut self, pub_key: &Key, branches_filename: &String){
-        let tx_inputs: Vec<TxInput> = vec![TxInput::new(initialize_empty_key_blocks(), "".to_string(), true, 0)];
-        let tx_outputs: Vec<TxOutput> = vec![TxOutput::new(pub_key.clone(), 100)];
-        let tx: Tx = Tx::new(tx_inputs, tx_outputs);
-        let tx_merkle_tree: MerkleTree = MerkleTree::new(&vec![tx]);
-
-        let block: Block = Block {
-            block_header: BlockHeader {
-                prev_block_hash: "".to_string(),
-                target: 4,
-                merkle_root: tx_merkle_tree.merkle_root.clone(),
-                nonce: 56974,
-                timestamp: 1735577085,
-            },
-            txs: tx_merkle_tree
+    // validates a new transaction against the UTXO set of the valid chain's tip and adds it to the mempool, returning its id
+    pub fn add_to_mempool(&mut self, tx: &Tx) -> Result<String, MempoolError> {
+        let now: i64 = self.clock.now();
+        self.mempool.expire(now);
+        let next_block_height: u64 = self.next_block_height();
+        self.mempool.add_tx(tx, &self.utxo, next_block_height, &self.params, now)
+    }
+
+    // whether the key with the given hash has already signed a transaction, in the valid chain or in the mempool (a one-time key must never sign twice)
+    pub fn has_key_signed(&self, pub_key_hash: &str) -> bool {
+        self.utxo.signed_message(pub_key_hash).is_some() || self.mempool.signed_message(pub_key_hash).is_some()
+    }
+
+    // fully validates the block against the UTXO set of the valid chain and, if it is valid, adds it to the top of the valid chain
+    fn connect_block(&mut self, block_hash: &str) -> Result<(), BlockError> {
+        let entry: &BlockIndexEntry = self.block_index.get(block_hash).unwrap();
+        check_block_transactions(&entry.block, &self.utxo, entry.height, &self.params)?;
+
+        let undo: BlockUndo = self.utxo.apply_block(&entry.block, entry.height).map_err(BlockError::MissingSpentOutput)?;
+        let connected_event: ChainEvent = ChainEvent::BlockConnected { block_hash: block_hash.to_string(), height: entry.height, block: entry.block.clone() };
+
+        self.undo_data.insert(block_hash.to_string(), undo);
+        self.blocks.push(entry.block.clone());
+        self.block_index.set_status(block_hash, BlockStatus::Valid);
+        self.tip_hash = Some(block_hash.to_string());
+        self.emit(connected_event);
+
+        Ok(())
+    }
+
+    // removes the last block of the valid chain, putting back the outputs it spent, and returns its hash
+    fn disconnect_tip(&mut self) -> String {
+        let block_hash: String = self.tip_hash.clone().unwrap();
+        let entry: &BlockIndexEntry = self.block_index.get(&block_hash).unwrap();
+
+        match self.undo_data.remove(&block_hash) {
+            Some(undo) => self.utxo.undo_block(&entry.block, &undo),
+            None => println!("Could not find the undo data of a disconnected block..."),
         };
+        let disconnected_event: ChainEvent = ChainEvent::BlockDisconnected { block_hash: block_hash.clone(), height: entry.height, block: entry.block.clone() };
+
+        self.blocks.pop();
+        self.tip_hash = if entry.prev_hash.is_empty() { None } else { Some(entry.prev_hash.clone()) };
+        self.emit(disconnected_event);
+
+        block_hash
+    }
 
-        self.blocks.push(block);
+    pub fn load_genesis_block(&mut self, pub_key: &Key, branches_filename: &String){
+        let block: Block = self.genesis.block(pub_key, &self.params);
+
+        // the genesis block pays a fixed key, so any other key gives a block that is not the genesis block of this network
+        let block_hash: String = block.block_header.hash_block().unwrap_or_default();
+        if let BlockAcceptance::Rejected = self.index_new_block(&block, &block_hash) {
+            println!("Could not load the genesis block of this network...");
+            return;
+        }
+        self.activate_best_chain();
 
         match save_chain_branches_to_file(&vec![self.clone()], branches_filename) {
             Ok(()) => {},
             Err(()) => {println!("Could not save genesis block to disk...")}
         };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{mpsc::Receiver, Arc};
+
+    use crate::classes::block::block::Block;
+    use crate::classes::block::block_index::BlockStatus;
+    use crate::classes::block::chain_events::ChainEvent;
+    use crate::classes::block::orphan_pool::OrphanPool;
+    use crate::classes::block::validation::{check_block_transactions, BlockError};
+    use crate::classes::consensus::chain_params::ChainParams;
+    use crate::classes::consensus::clock::FixedClock;
+    use crate::classes::lamport_signature::key_pair::{initialize_empty_key_blocks, KeyPair};
+    use crate::classes::transaction::tx::{Tx, TxInput, TxOutput};
+    use crate::classes::transaction::utxo_set::OutPoint;
+    use crate::util::disk::load_branches_from_file;
+    use crate::util::test_util::{coinbase_tx, spend_tx, test_keypairs, TestChain};
+
+    use super::{BlockAcceptance, Blockchain};
+
+    #[test]
+    fn fork_detection() {
+        let keypairs: &[KeyPair] = test_keypairs();
+        let pub_key = &keypairs[0].pub_key;
+        let mut chain: TestChain = TestChain::new(pub_key);
+        let block_1_hash: String = chain.genesis_hash();
+
+        let block_2_hash: String = chain.mine(vec![coinbase_tx(1, 0, pub_key.clone(), 100)], &block_1_hash).block_header.hash_block().unwrap();
+        let block_3_hash: String = chain.mine(vec![coinbase_tx(1, 0, pub_key.clone(), 100)], &block_1_hash).block_header.hash_block().unwrap();
+        let block_4_hash: String = chain.mine(vec![coinbase_tx(2, 0, pub_key.clone(), 100)], &block_3_hash).block_header.hash_block().unwrap();
+        let block_5_hash: String = chain.mine(vec![coinbase_tx(2, 0, pub_key.clone(), 100)], &block_2_hash).block_header.hash_block().unwrap();
+        let block_6_hash: String = chain.mine(vec![coinbase_tx(3, 0, pub_key.clone(), 100)], &block_4_hash).block_header.hash_block().unwrap();
+        let block_7_hash: String = chain.mine(vec![coinbase_tx(2, 0, pub_key.clone(), 100)], &block_3_hash).block_header.hash_block().unwrap();
+        let block_8_hash: String = chain.mine(vec![coinbase_tx(3, 0, pub_key.clone(), 100)], &block_7_hash).block_header.hash_block().unwrap();
+
+        // the branches ending with 6 and 8 have the same work, so the one seen first is the valid chain
+        let valid_chain_hashes: Vec<String> = chain.blocks.iter().map(|block| block.block_header.hash_block().unwrap()).collect();
+        assert_eq!(valid_chain_hashes, vec![block_1_hash.clone(), block_3_hash.clone(), block_4_hash, block_6_hash.clone()]);
+
+        // every branch is saved to disk, from the genesis block up to its tip
+        let loaded_chains: Vec<Blockchain> = load_branches_from_file(&chain.branches_filename).expect("Could not load the saved branches");
+        let loaded_tip_hashes: Vec<String> = loaded_chains.iter().map(|loaded_chain| loaded_chain.blocks.last().unwrap().block_header.hash_block().unwrap()).collect();
+        assert_eq!(loaded_tip_hashes, vec![block_5_hash, block_6_hash, block_8_hash]);
+        assert!(loaded_chains.iter().all(|loaded_chain| loaded_chain.blocks.len() == 3 || loaded_chain.blocks.len() == 4));
+        assert!(loaded_chains.iter().all(|loaded_chain| loaded_chain.blocks[0].block_header.hash_block().unwrap() == block_1_hash));
+    }
+
+    #[test]
+    fn chain_reorganization() {
+        let keypairs: &[KeyPair] = test_keypairs();
+        let mut chain: TestChain = TestChain::new(&keypairs[0].pub_key);
+        let genesis_hash: String = chain.genesis_hash();
+        let chain_events: Receiver<ChainEvent> = chain.subscribe();
+
+        // the valid chain: genesis -> a1
+        let a1_coinbase: Tx = coinbase_tx(1, 0, keypairs[0].pub_key.clone(), 100);
+        let block_a1_hash: String = chain.mine(vec![a1_coinbase.clone()], &genesis_hash).block_header.hash_block().unwrap();
+
+        // a competing branch: genesis -> b1 -> b2, where b2 spends the coinbase of a1, which does not exist in its own branch
+        let block_b1_hash: String = chain.mine(vec![coinbase_tx(1, 1, keypairs[0].pub_key.clone(), 100)], &genesis_hash).block_header.hash_block().unwrap();
+        assert_eq!(chain.blocks.len(), 2);
+        assert_eq!(chain.blocks[1].block_header.hash_block().unwrap(), block_a1_hash, "A branch with the same work replaced the first seen one");
+
+        let mut spend_tx: Tx = Tx::new(
+            vec![TxInput::new(initialize_empty_key_blocks(), a1_coinbase.get_tx_id(), false, 0)],
+            vec![TxOutput::new(keypairs[1].pub_key.clone(), 100)],
+        );
+        spend_tx.inputs[0].signature = keypairs[0].create_signature(&spend_tx).into();
+        chain.mine(vec![coinbase_tx(2, 1, keypairs[0].pub_key.clone(), 100), spend_tx], &block_b1_hash);
+
+        // the reorganization to b2 fails, so the valid chain is rolled back to a1
+        assert_eq!(chain.blocks.len(), 2);
+        assert_eq!(chain.blocks[1].block_header.hash_block().unwrap(), block_a1_hash, "The valid chain was not rolled back after an invalid block");
+        assert!(chain.utxo.get(&OutPoint::new(a1_coinbase.get_tx_id(), 0)).is_some(), "The UTXO set was not rolled back after an invalid block");
+
+        let event_descriptions: Vec<String> = chain_events.try_iter().map(|chain_event| match chain_event {
+            ChainEvent::BlockConnected { block_hash, .. } => format!("connect {}", block_hash),
+            ChainEvent::BlockDisconnected { block_hash, .. } => format!("disconnect {}", block_hash),
+            ChainEvent::TipChanged { tip_hash, .. } => format!("tip {}", tip_hash),
+        }).collect();
+
+        let expected_event_descriptions: Vec<String> = vec![
+            format!("connect {}", block_a1_hash),
+            format!("tip {}", block_a1_hash),
+            format!("disconnect {}", block_a1_hash),
+            format!("connect {}", block_b1_hash),
+            format!("disconnect {}", block_b1_hash),
+            format!("connect {}", block_a1_hash),
+        ];
+        assert_eq!(event_descriptions, expected_event_descriptions);
+    }
 
-        self.update_utxo();
+    #[test]
+    fn side_branch_validation() {
+        let keypairs: &[KeyPair] = test_keypairs();
+        // coinbase outputs are spent right away in this test
+        let mut chain: TestChain = TestChain::with_params(&keypairs[0].pub_key, |params| params.coinbase_maturity = 0);
+        let genesis_hash: String = chain.genesis_hash();
+
+        // the valid chain: genesis -> a1 -> a2
+        let block_a1_hash: String = chain.mine(vec![coinbase_tx(1, 0, keypairs[0].pub_key.clone(), 100)], &genesis_hash).block_header.hash_block().unwrap();
+        chain.mine(vec![coinbase_tx(2, 0, keypairs[0].pub_key.clone(), 100)], &block_a1_hash);
+
+        // a side branch: genesis -> b1 -> b2, where b2 spends the coinbase of b1, which only exists in the side branch
+        let b1_coinbase: Tx = coinbase_tx(1, 1, keypairs[0].pub_key.clone(), 100);
+        let block_b1_hash: String = chain.mine(vec![b1_coinbase.clone()], &genesis_hash).block_header.hash_block().unwrap();
+        let block_b2_hash: String = chain.mine(vec![coinbase_tx(2, 1, keypairs[0].pub_key.clone(), 100), spend_tx(&[(b1_coinbase.get_tx_id(), 0)], &keypairs[0], keypairs[1].pub_key.clone(), &[100])], &block_b1_hash).block_header.hash_block().unwrap();
+
+        // the side branch does not have more work than the valid chain, so its blocks are kept as pending
+        assert_eq!(chain.blocks.len(), 3);
+        assert!(chain.block_index.get(&block_b2_hash).unwrap().status == BlockStatus::HeaderValid, "A block on a side branch was not kept as pending");
+
+        // extending the side branch makes it the valid chain, and b2 is validated against the UTXO set of its own branch
+        let block_b3: Block = chain.mine(vec![coinbase_tx(3, 1, keypairs[0].pub_key.clone(), 100)], &block_b2_hash);
+        assert_eq!(chain.blocks.len(), 4);
+        assert_eq!(chain.blocks[3].block_header.hash_block().unwrap(), block_b3.block_header.hash_block().unwrap(), "The side branch was not activated");
+        assert!(chain.block_index.get(&block_b2_hash).unwrap().status == BlockStatus::Valid, "A connected block was not marked as valid");
+        assert!(chain.utxo.get(&OutPoint::new(b1_coinbase.get_tx_id(), 0)).is_none());
     }
 
-    fn insert_disk_blocks(&mut self, branches_filename: &String) {
-        let loaded_branch_chains_result: Result<Vec<Blockchain>, ()> = load_branches_from_file(branches_filename);
-        let loaded_branch_chains: Vec<Blockchain> = match loaded_branch_chains_result {
-            Ok(val) => val,
-            Err(()) => vec![],
+    #[test]
+    fn block_timestamp_rules() {
+        let keypairs: &[KeyPair] = test_keypairs();
+        let pub_key = &keypairs[0].pub_key;
+        let tx_vec: Vec<Tx> = vec![coinbase_tx(1, 0, pub_key.clone(), 100)];
+
+        let mut chain: TestChain = TestChain::new(pub_key);
+        let genesis_hash: String = chain.genesis_hash();
+        let genesis_timestamp: i64 = chain.blocks[0].block_header.timestamp;
+        let now: i64 = genesis_timestamp + 1000;
+        chain.clock = Arc::new(FixedClock { time: now });
+
+        let mine_block_with_timestamp = |blockchain: &Blockchain, timestamp: i64| -> Block {
+            let mut block: Block = blockchain.new_block(&tx_vec, &genesis_hash).unwrap();
+            block.block_header.timestamp = timestamp;
+            block.mine_block();
+            block
         };
 
-        if loaded_branch_chains.len() == 0 {
-            return;
+        // a block with the same timestamp as the median of the previous blocks is rejected
+        let stale_block: Block = mine_block_with_timestamp(&chain, genesis_timestamp);
+        chain.accept(&stale_block);
+        assert_eq!(chain.blocks.len(), 1, "A block that is not after the median time past was accepted");
+
+        // a block too far ahead of the node's clock is rejected
+        let future_block: Block = mine_block_with_timestamp(&chain, now + chain.params.max_future_block_time + 1);
+        chain.accept(&future_block);
+        assert_eq!(chain.blocks.len(), 1, "A block too far in the future was accepted");
+
+        // a block at the very edge of the allowed drift is accepted
+        let valid_block: Block = mine_block_with_timestamp(&chain, now + chain.params.max_future_block_time);
+        chain.accept(&valid_block);
+        assert_eq!(chain.blocks.len(), 2, "A block with a valid timestamp was rejected");
+    }
+
+    #[test]
+    fn orphan_blocks() {
+        let keypairs: &[KeyPair] = test_keypairs();
+        let mut chain: TestChain = TestChain::new(&keypairs[0].pub_key);
+        let genesis_hash: String = chain.genesis_hash();
+
+        // mine genesis -> b1 -> b2 -> b3 on a copy of the chain, so that the original one only learns about the blocks when they are accepted
+        let mut miner_blockchain: Blockchain = chain.blockchain.clone();
+        let miner_branches_filename: String = chain.temp_dir.file("minerbranches.bin");
+        let mut mined_blocks: Vec<Block> = vec![];
+        let mut prev_hash: String = genesis_hash;
+        for height in 1..=3 {
+            let mut block: Block = miner_blockchain.new_block(&[coinbase_tx(height, 1, keypairs[0].pub_key.clone(), 100)], &prev_hash).unwrap();
+            block.mine_block();
+            prev_hash = block.block_header.hash_block().unwrap();
+            miner_blockchain.accept_new_block(&block, &miner_branches_filename);
+            mined_blocks.push(block);
         }
 
-        let mut checked_block_hashes: HashMap<String, bool> = HashMap::new();
-        for block_in_chain in &self.blocks {
-            checked_block_hashes.insert(block_in_chain.block_header.hash_block(), true);
+        // an orphan without proof-of-work, or with a target easier than the proof-of-work limit, is not kept
+        let mut unmined_orphan: Block = mined_blocks[2].clone();
+        while unmined_orphan.block_header.meets_target() {
+            unmined_orphan.block_header.nonce += 1;
         }
-        for branch_chain in &loaded_branch_chains {
-            for block_in_branch_chain in &branch_chain.blocks {
-                if checked_block_hashes.contains_key(&block_in_branch_chain.block_header.hash_block()) {
-                    continue;
-                } else {
-                    checked_block_hashes.insert(block_in_branch_chain.block_header.hash_block(), true);
-                    self.blocks.push(block_in_branch_chain.clone());
-                }
+        assert!(matches!(chain.accept(&unmined_orphan), BlockAcceptance::Rejected), "An orphan without proof-of-work was kept");
+        let mut easy_orphan: Block = mined_blocks[2].clone();
+        easy_orphan.block_header.target = 0x207fffff;
+        easy_orphan.mine_block();
+        assert!(matches!(chain.accept(&easy_orphan), BlockAcceptance::Rejected), "An orphan with a target above the proof-of-work limit was kept");
+
+        // the blocks arrive in reverse order, so the first two are kept as orphans
+        for orphan_block in [&mined_blocks[2], &mined_blocks[1]] {
+            match chain.accept(orphan_block) {
+                BlockAcceptance::Orphaned { missing_parent_hash } => assert_eq!(missing_parent_hash, orphan_block.block_header.prev_block_hash),
+                _ => panic!("A block with an unknown parent was not kept as an orphan"),
             }
         }
+        // each orphan is kept under the hash of its missing parent
+        let mut orphan_pool: OrphanPool = chain.orphan_pool.clone();
+        for orphan_block in [&mined_blocks[2], &mined_blocks[1]] {
+            let children: Vec<(String, Block)> = orphan_pool.take_children(&orphan_block.block_header.prev_block_hash);
+            assert!(children.len() == 1 && children[0].0 == orphan_block.block_header.hash_block().unwrap(), "An orphan was not kept under its missing parent");
+        }
+        assert_eq!(chain.blocks.len(), 1);
+
+        // once the missing parent arrives, the orphans are connected as well
+        assert!(matches!(chain.accept(&mined_blocks[0]), BlockAcceptance::Accepted));
+        assert!(chain.orphan_pool.clone().take_children(&mined_blocks[1].block_header.prev_block_hash).is_empty(), "The orphan pool was not emptied");
+        assert!(chain.orphan_pool.clone().take_children(&mined_blocks[2].block_header.prev_block_hash).is_empty(), "The orphan pool was not emptied");
+        assert_eq!(chain.blocks.len(), 4);
+        assert_eq!(chain.blocks[3].block_header.hash_block().unwrap(), prev_hash, "The orphan blocks were not connected");
+    }
+
+    #[test]
+    fn transaction_fees() {
+        let keypairs: &[KeyPair] = test_keypairs();
+        let mut chain: TestChain = TestChain::with_params(&keypairs[0].pub_key, |params| params.coinbase_maturity = 0);
+        let genesis_coinbase: Tx = chain.blocks[0].txs.base[0].clone();
+        let genesis_hash: String = chain.genesis_hash();
+
+        // spend the 100 of the genesis coinbase, sending 90 and leaving 10 as the fee
+        let mut fee_tx: Tx = Tx::new(
+            vec![TxInput::new(initialize_empty_key_blocks(), genesis_coinbase.get_tx_id(), false, 0)],
+            vec![TxOutput::new(keypairs[1].pub_key.clone(), 90)],
+        );
+        fee_tx.inputs[0].signature = keypairs[0].create_signature(&fee_tx).into();
+        assert_eq!(fee_tx.fee(&chain.utxo), Some(10));
+
+        // the coinbase cannot claim more than the subsidy plus the fees
+        let greedy_block: Block = chain.new_block(&[coinbase_tx(1, 0, keypairs[0].pub_key.clone(), 111), fee_tx.clone()], &genesis_hash).unwrap();
+        assert!(matches!(check_block_transactions(&greedy_block, &chain.utxo, 1, &chain.params), Err(BlockError::CoinbaseAmountTooHigh { amount: 111, allowed_amount: 110 })), "The coinbase claimed more than the subsidy plus the fees");
+
+        // but it can claim exactly the subsidy plus the fees
+        let fee_block_hash: String = chain.mine(vec![coinbase_tx(1, 1, keypairs[0].pub_key.clone(), 110), fee_tx], &genesis_hash).block_header.hash_block().unwrap();
+        assert_eq!(chain.blocks.len(), 2, "The coinbase could not claim the fees of its block");
+        assert_eq!(chain.block_fees(&fee_block_hash), Some(10));
+        assert_eq!(chain.block_fees(&genesis_hash), Some(0));
+    }
+
+    #[test]
+    fn unencodable_header_rejected() {
+        let pub_key = &test_keypairs()[0].pub_key;
+        let mut chain: TestChain = TestChain::new(pub_key);
+        let mut block: Block = chain.new_block(&[coinbase_tx(1, 0, pub_key.clone(), 100)], &chain.genesis_hash()).unwrap();
+        block.block_header.merkle_root = "not a hash".to_string();
+        assert!(matches!(chain.accept(&block), BlockAcceptance::Rejected), "A block whose header cannot be encoded was accepted");
+        assert_eq!(chain.blocks.len(), 1);
+    }
+
+    #[test]
+    fn loaded_branches_start_with_genesis_block() {
+        let pub_key = &test_keypairs()[0].pub_key;
+        let mut chain: TestChain = TestChain::new(pub_key);
+        let genesis_hash: String = chain.genesis_hash();
+        chain.mine(vec![coinbase_tx(1, 0, pub_key.clone(), 100)], &genesis_hash);
+        let saved_branches: Vec<Blockchain> = load_branches_from_file(&chain.branches_filename).unwrap();
+
+        // the branches saved by a node of the main network are not loaded on the test network, which has another genesis block
+        let mut test_network_chain: Blockchain = Blockchain::with_chain_params(&ChainParams::test());
+        match test_network_chain.insert_branches(&saved_branches) {
+            Err(genesis_mismatch) => assert!(genesis_mismatch.found_hash == Some(genesis_hash.clone()) && genesis_mismatch.expected_hash == ChainParams::test().genesis.hash),
+            Ok(()) => panic!("Branches starting with the genesis block of another network were loaded"),
+        }
+        assert!(test_network_chain.block_index.tips().is_empty(), "Blocks of another network were indexed");
+
+        let mut main_network_chain: Blockchain = Blockchain::new();
+        assert!(main_network_chain.insert_branches(&saved_branches).is_ok());
+        assert_eq!(main_network_chain.block_index.tips().len(), 1);
+    }
+
+    #[test]
+    fn inserted_blocks_are_checked() {
+        let pub_key = &test_keypairs()[0].pub_key;
+        let mut miner_chain: TestChain = TestChain::new(pub_key);
+        let genesis_hash: String = miner_chain.genesis_hash();
+        let block_1: Block = miner_chain.mine(vec![coinbase_tx(1, 0, pub_key.clone(), 100)], &genesis_hash);
+        let block_1_hash: String = block_1.block_header.hash_block().unwrap();
+
+        // a block whose timestamp is not after the median time past, and a block built on top of it
+        let mut stale_block: Block = miner_chain.new_block(&[coinbase_tx(2, 0, pub_key.clone(), 100)], &block_1_hash).unwrap();
+        stale_block.block_header.timestamp = miner_chain.blocks[0].block_header.timestamp;
+        stale_block.mine_block();
+        let stale_block_hash: String = stale_block.block_header.hash_block().unwrap();
+        let mut child_block: Block = Block::new(&[coinbase_tx(3, 0, pub_key.clone(), 100)], stale_block_hash.clone(), stale_block.block_header.target);
+        child_block.mine_block();
+        let child_block_hash: String = child_block.block_header.hash_block().unwrap();
+
+        // the blocks arrive the way they are loaded from disk, starting with the genesis block
+        let mut chain: TestChain = TestChain::new(pub_key);
+        let loaded_blocks: Vec<Block> = vec![miner_chain.blocks[0].clone(), block_1, stale_block, child_block];
+        chain.insert_blocks(&loaded_blocks);
+        let branches_filename: String = chain.branches_filename.clone();
+        chain.choose_valid_chain_and_update_utxo(&branches_filename);
+
+        assert_eq!(chain.tip_hash(), block_1_hash, "A block that failed the header checks became part of the valid chain");
+        assert!(chain.block_index.get(&stale_block_hash).unwrap().status == BlockStatus::Invalid, "A block that failed the header checks was not marked as invalid");
+        assert!(chain.block_index.get(&child_block_hash).unwrap().status == BlockStatus::Invalid);
+    }
+
+    #[test]
+    fn only_genesis_block_has_no_parent() {
+        let keypairs: &[KeyPair] = test_keypairs();
+        let mut chain: TestChain = TestChain::new(&keypairs[0].pub_key);
+
+        // a block without a parent that is not the genesis block of the network is rejected, whether or not the genesis block is already known
+        let mut other_genesis: Block = chain.genesis.block(&keypairs[1].pub_key, &chain.params);
+        other_genesis.mine_block();
+        assert!(matches!(chain.accept(&other_genesis), BlockAcceptance::Rejected), "A second block without a parent was accepted");
+        assert!(chain.orphan_pool.take_children("").is_empty(), "A block without a parent was kept as an orphan");
+
+        let mut empty_blockchain: Blockchain = Blockchain::new();
+        let branches_filename: String = chain.temp_dir.file("emptybranches.bin");
+        assert!(matches!(empty_blockchain.accept_new_block(&other_genesis, &branches_filename), BlockAcceptance::Rejected));
+        empty_blockchain.load_genesis_block(&keypairs[1].pub_key, &branches_filename);
+        assert!(empty_blockchain.blocks.is_empty(), "A genesis block paying another key was loaded");
     }
 }
\ No newline at end of file
@@ -0,0 +1,10 @@
+use crate::classes::block::block::Block;
+
+// notifications sent to the services subscribed to the blockchain whenever the valid chain changes
+#[derive(Clone)]
+pub enum ChainEvent {
+    BlockConnected { block_hash: String, height: u64, block: Block },
+    BlockDisconnected { block_hash: String, height: u64, block: Block },
+    // sent once the valid chain has been updated, after all of the disconnect and connect events of the update
+    TipChanged { tip_hash: String, height: u64 },
+}
@@ -1,3 +1,7 @@
 pub mod block;
 pub mod block_header;
-pub mod blockchain;
\ No newline at end of file
+pub mod block_index;
+pub mod blockchain;
+pub mod chain_events;
+pub mod orphan_pool;
+pub mod validation;
\ No newline at end of file
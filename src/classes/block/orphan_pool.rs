@@ -0,0 +1,65 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::classes::block::block::Block;
+
+// the maximum number of orphan blocks kept at once, so that a peer cannot fill up the node's memory with blocks that never connect
+pub const MAX_ORPHAN_BLOCKS: usize = 100;
+
+/* Blocks whose parent is not known yet (e.g. because blocks arrived out of order), keyed by the hash of the missing parent.
+Once the parent is accepted, its orphan children are taken out of the pool and processed again. */
+#[derive(Clone, Default)]
+pub struct OrphanPool {
+    orphans_by_parent: HashMap<String, Vec<(String, Block)>>,
+    orphan_hashes: HashSet<String>,
+    // (parent hash, block hash) of every orphan, oldest first, so that the oldest orphan is evicted when the pool is full
+    insertion_order: VecDeque<(String, String)>,
+    max_orphans: usize,
+}
+
+impl OrphanPool {
+    pub fn new(max_orphans: usize) -> OrphanPool {
+        OrphanPool {
+            orphans_by_parent: HashMap::new(),
+            orphan_hashes: HashSet::new(),
+            insertion_order: VecDeque::new(),
+            max_orphans,
+        }
+    }
+
+    pub fn add(&mut self, block: &Block, block_hash: &str) {
+        if self.max_orphans == 0 || self.orphan_hashes.contains(block_hash) {
+            return;
+        }
+
+        while self.orphan_hashes.len() >= self.max_orphans {
+            let (parent_hash, evicted_hash) = self.insertion_order.pop_front().unwrap();
+            self.remove(&parent_hash, &evicted_hash);
+        }
+
+        let parent_hash: String = block.block_header.prev_block_hash.clone();
+        self.orphans_by_parent.entry(parent_hash.clone()).or_default().push((block_hash.to_string(), block.clone()));
+        self.orphan_hashes.insert(block_hash.to_string());
+        self.insertion_order.push_back((parent_hash, block_hash.to_string()));
+    }
+
+    // removes and returns the orphans (along with their hashes) that point to the given block
+    pub fn take_children(&mut self, parent_hash: &str) -> Vec<(String, Block)> {
+        let children: Vec<(String, Block)> = self.orphans_by_parent.remove(parent_hash).unwrap_or_default();
+        for (child_hash, _child) in &children {
+            self.orphan_hashes.remove(child_hash);
+        }
+        self.insertion_order.retain(|(_parent_hash, block_hash)| self.orphan_hashes.contains(block_hash));
+
+        children
+    }
+
+    fn remove(&mut self, parent_hash: &String, block_hash: &String) {
+        if let Some(siblings) = self.orphans_by_parent.get_mut(parent_hash) {
+            siblings.retain(|(sibling_hash, _sibling)| sibling_hash != block_hash);
+            if siblings.is_empty() {
+                self.orphans_by_parent.remove(parent_hash);
+            }
+        }
+        self.orphan_hashes.remove(block_hash);
+    }
+}
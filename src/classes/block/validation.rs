@@ -0,0 +1,300 @@
+use std::fmt;
+
+use crate::classes::block::block::Block;
+use crate::classes::consensus::params::{ConsensusParams, KeyReusePolicy};
+use crate::classes::consensus::subsidy::block_subsidy;
+use crate::classes::transaction::tx::Tx;
+use crate::classes::transaction::utxo_set::{OutPoint, UtxoEntry, UtxoLookup, UtxoSet, UtxoView};
+
+pub enum BlockError {
+    BlockTooLarge { size: usize, max_size: usize },
+    FirstTxNotCoinbase,
+    CoinbaseTooManyInputsOrOutputs,
+    CoinbaseAmountTooHigh { amount: u64, allowed_amount: u64 },
+    CoinbaseHeightMismatch { height: u64, committed_height: Option<u64> },
+    IncorrectCoinbaseStatus { tx_index: usize },
+    InvalidTransaction { tx_index: usize },
+    DuplicateInput { tx_index: usize },
+    DoubleSpend { tx_index: usize, outpoint: OutPoint },
+    ImmatureCoinbaseSpend { tx_index: usize, outpoint: OutPoint },
+    KeyReuse { tx_index: usize, pub_key_hash: String },
+    MissingSpentOutput(OutPoint),
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockError::BlockTooLarge { size, max_size } => write!(f, "The block is too large ({} bytes, the maximum is {} bytes)", size, max_size),
+            BlockError::FirstTxNotCoinbase => write!(f, "The first tx in the block is not a coinbase transaction"),
+            BlockError::CoinbaseTooManyInputsOrOutputs => write!(f, "The first transaction in the block has too many inputs and/or outputs"),
+            BlockError::CoinbaseAmountTooHigh { amount, allowed_amount } => write!(f, "The coinbase transaction claims {} but only the subsidy plus the fees of the block ({}) can be claimed", amount, allowed_amount),
+            BlockError::CoinbaseHeightMismatch { height, committed_height: Some(committed_height) } => write!(f, "The coinbase transaction commits to height {} but the block is at height {}", committed_height, height),
+            BlockError::CoinbaseHeightMismatch { height, committed_height: None } => write!(f, "The coinbase transaction does not commit to the height of the block ({})", height),
+            BlockError::IncorrectCoinbaseStatus { tx_index } => write!(f, "At least one tx input of transaction #{} has an incorrect coinbase status", tx_index),
+            BlockError::InvalidTransaction { tx_index } => write!(f, "Transaction #{} is invalid", tx_index),
+            BlockError::DuplicateInput { tx_index } => write!(f, "Transaction #{} spends the same output more than once", tx_index),
+            BlockError::DoubleSpend { tx_index, outpoint } => write!(f, "Transaction #{} spends the output {}:{}, which was already spent earlier in the block", tx_index, outpoint.tx_id, outpoint.index),
+            BlockError::ImmatureCoinbaseSpend { tx_index, outpoint } => write!(f, "Transaction #{} spends the coinbase output {}:{} before it has matured", tx_index, outpoint.tx_id, outpoint.index),
+            BlockError::KeyReuse { tx_index, pub_key_hash } => write!(f, "Transaction #{} is signed by the key {}, which has already signed a different transaction", tx_index, pub_key_hash),
+            BlockError::MissingSpentOutput(outpoint) => write!(f, "The output {}:{} spent by the block does not exist", outpoint.tx_id, outpoint.index),
+        }
+    }
+}
+
+/* Checks that do not depend on the branch the block is in: the block must not be larger than the maximum block size,
+and the first transaction (and only the first one) must be a valid coinbase transaction. */
+pub fn check_block_structure(block: &Block, params: &ConsensusParams) -> Result<(), BlockError> {
+    let block_size: usize = block.size();
+    if block_size > params.max_block_size {
+        return Err(BlockError::BlockTooLarge { size: block_size, max_size: params.max_block_size });
+    }
+
+    let coinbase_tx: &Tx = match block.txs.base.first() {
+        Some(tx) => tx,
+        None => return Err(BlockError::FirstTxNotCoinbase),
+    };
+
+    // a first transaction without any input is not a coinbase transaction either
+    match coinbase_tx.inputs.first() {
+        Some(coinbase_input) if coinbase_input.is_coinbase => {},
+        _ => return Err(BlockError::FirstTxNotCoinbase),
+    }
+
+    if coinbase_tx.inputs.len() > 1 || coinbase_tx.outputs.len() > 1 {
+        return Err(BlockError::CoinbaseTooManyInputsOrOutputs);
+    }
+
+    for (tx_index, block_tx) in block.txs.base.iter().enumerate().skip(1) {
+        // check to see if any inputs claim that they are a coinbase transaction
+        if block_tx.inputs.iter().any(|tx_input| tx_input.is_coinbase || tx_input.coinbase_data.is_some()) {
+            return Err(BlockError::IncorrectCoinbaseStatus { tx_index });
+        }
+
+        if block_tx.has_duplicate_inputs() {
+            return Err(BlockError::DuplicateInput { tx_index });
+        }
+    }
+
+    Ok(())
+}
+
+// the coinbase transaction must commit to the height the block is at, so that no two blocks of a chain have coinbase transactions with the same id (like BIP34)
+pub fn check_coinbase_height(block: &Block, height: u64) -> Result<(), BlockError> {
+    let committed_height: Option<u64> = block.txs.base.first()
+        .and_then(|coinbase_tx| coinbase_tx.coinbase_data())
+        .map(|coinbase_data| coinbase_data.height);
+
+    if committed_height != Some(height) {
+        return Err(BlockError::CoinbaseHeightMismatch { height, committed_height });
+    }
+
+    Ok(())
+}
+
+/* Verify each transaction in the block (including checking the signature) against the UTXO set of the branch the block is connected on top of,
+as it is after the transactions before it in the block: an output spent by one transaction cannot be spent again by a later one,
+but a transaction can spend an output created earlier in the block. The coinbase transaction can claim at most the subsidy of the block's height plus the fees of every other transaction in the block,
+and coinbase outputs can only be spent once they have matured. A key that already signed a different transaction (in the branch or earlier in the block)
+is flagged or rejected depending on the key reuse policy. */
+pub fn check_block_transactions(block: &Block, utxo: &UtxoSet, height: u64, params: &ConsensusParams) -> Result<(), BlockError> {
+    check_block_structure(block, params)?;
+
+    let mut total_fees: u64 = 0;
+
+    let mut utxo_view: UtxoView = UtxoView::new(utxo);
+    utxo_view.apply_tx(&block.txs.base[0], height);
+
+    for (tx_index, block_tx) in block.txs.base.iter().enumerate().skip(1) {
+        for tx_input in &block_tx.inputs {
+            let outpoint: OutPoint = OutPoint::new(tx_input.prev_tx_id.clone(), tx_input.index);
+            if utxo_view.is_spent(&outpoint) {
+                return Err(BlockError::DoubleSpend { tx_index, outpoint });
+            }
+
+            let spent_entry: Option<&UtxoEntry> = utxo_view.get(&outpoint);
+            if spent_entry.is_some_and(|spent_entry| !spent_entry.is_mature(height, params.coinbase_maturity)) {
+                return Err(BlockError::ImmatureCoinbaseSpend { tx_index, outpoint });
+            }
+        }
+
+        if !block_tx.verify_transaction(&utxo_view) {
+            return Err(BlockError::InvalidTransaction { tx_index });
+        }
+
+        if let Some(pub_key_hash) = block_tx.reused_signing_key(&utxo_view) {
+            match params.key_reuse_policy {
+                KeyReusePolicy::Flag => println!("Transaction #{} of the block is signed by a key that has already signed a different transaction...", tx_index),
+                KeyReusePolicy::Reject => return Err(BlockError::KeyReuse { tx_index, pub_key_hash }),
+            }
+        }
+
+        // verify_transaction already checked that the fee can be computed
+        let tx_fee: u64 = block_tx.fee(&utxo_view).unwrap_or(0);
+        total_fees = total_fees.checked_add(tx_fee).ok_or(BlockError::InvalidTransaction { tx_index })?;
+
+        utxo_view.apply_tx(block_tx, height);
+    }
+
+    let coinbase_amount: u64 = block.txs.base[0].output_amount().unwrap_or(u64::MAX);
+    let allowed_amount: u64 = block_subsidy(height, params).saturating_add(total_fees);
+    if coinbase_amount > allowed_amount {
+        return Err(BlockError::CoinbaseAmountTooHigh { amount: coinbase_amount, allowed_amount });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::classes::block::block::Block;
+    use crate::classes::block::block_index::BlockStatus;
+    use crate::classes::consensus::params::KeyReusePolicy;
+    use crate::classes::lamport_signature::key_pair::{initialize_empty_key_blocks, KeyPair};
+    use crate::classes::transaction::mempool::MempoolError;
+    use crate::classes::transaction::tx::{Tx, TxInput, TxOutput};
+    use crate::classes::transaction::utxo_set::{Balance, OutPoint, UtxoSet};
+    use crate::util::test_util::{coinbase_tx, spend_tx, test_keypairs, TestChain};
+
+    use super::{check_block_structure, check_block_transactions, check_coinbase_height, BlockError};
+
+    #[test]
+    fn double_spends_within_block() {
+        let keypairs: &[KeyPair] = test_keypairs();
+        // coinbase outputs are spent right away in this test
+        let mut chain: TestChain = TestChain::with_params(&keypairs[0].pub_key, |params| params.coinbase_maturity = 0);
+        let genesis_coinbase: Tx = chain.blocks[0].txs.base[0].clone();
+        let genesis_hash: String = chain.genesis_hash();
+
+        // two transactions in the same block spending the genesis coinbase
+        let first_spend: Tx = spend_tx(&[(genesis_coinbase.get_tx_id(), 0)], &keypairs[0], keypairs[1].pub_key.clone(), &[100]);
+        let second_spend: Tx = spend_tx(&[(genesis_coinbase.get_tx_id(), 0)], &keypairs[0], keypairs[2].pub_key.clone(), &[100]);
+        let double_spend_block: Block = chain.new_block(&[coinbase_tx(1, 0, keypairs[0].pub_key.clone(), 100), first_spend.clone(), second_spend], &genesis_hash).unwrap();
+        assert!(matches!(check_block_transactions(&double_spend_block, &chain.utxo, 1, &chain.params), Err(BlockError::DoubleSpend { tx_index: 2, .. })), "Two transactions of a block spent the same output");
+
+        // a transaction with two inputs spending the genesis coinbase
+        let duplicate_input_block: Block = chain.new_block(&[coinbase_tx(1, 1, keypairs[0].pub_key.clone(), 100), spend_tx(&[(genesis_coinbase.get_tx_id(), 0), (genesis_coinbase.get_tx_id(), 0)], &keypairs[0], keypairs[1].pub_key.clone(), &[100])], &genesis_hash).unwrap();
+        assert!(matches!(check_block_structure(&duplicate_input_block, &chain.params), Err(BlockError::DuplicateInput { tx_index: 1 })), "A transaction spent the same output twice");
+
+        // a first transaction without any input is rejected rather than indexed into
+        let no_input_coinbase: Tx = Tx::new(vec![], vec![TxOutput::new(keypairs[0].pub_key.clone(), 100)]);
+        let no_input_block: Block = Block::new(&[no_input_coinbase], genesis_hash.clone(), chain.params.pow_limit_bits);
+        assert!(matches!(check_block_transactions(&no_input_block, &chain.utxo, 1, &chain.params), Err(BlockError::FirstTxNotCoinbase)));
+
+        // a transaction spending an output created earlier in the same block
+        let chained_spend: Tx = spend_tx(&[(first_spend.get_tx_id(), 0)], &keypairs[1], keypairs[2].pub_key.clone(), &[100]);
+        let chained_txs: Vec<Tx> = vec![coinbase_tx(1, 2, keypairs[0].pub_key.clone(), 100), first_spend.clone(), chained_spend.clone()];
+        let chained_block: Block = chain.new_block(&chained_txs, &genesis_hash).unwrap();
+        assert!(check_block_transactions(&chained_block, &chain.utxo, 1, &chain.params).is_ok(), "A transaction could not spend an output created earlier in the block");
+
+        chain.mine(chained_txs, &genesis_hash);
+        assert_eq!(chain.blocks.len(), 2);
+        assert!(chain.utxo.get(&OutPoint::new(chained_spend.get_tx_id(), 0)).is_some());
+    }
+
+    #[test]
+    fn coinbase_maturity() {
+        let keypairs: &[KeyPair] = test_keypairs();
+        let chain: TestChain = TestChain::with_params(&keypairs[0].pub_key, |params| params.coinbase_maturity = 2);
+        let genesis_coinbase: Tx = chain.blocks[0].txs.base[0].clone();
+        let genesis_hash: String = chain.genesis_hash();
+
+        // the genesis coinbase is counted separately until it matures
+        let immature_balance: Balance = chain.utxo.balance(&keypairs[0].pub_key.hash_key(), 1, chain.params.coinbase_maturity);
+        assert_eq!((immature_balance.spendable, immature_balance.immature), (0, 100));
+        let mature_balance: Balance = chain.utxo.balance(&keypairs[0].pub_key.hash_key(), 2, chain.params.coinbase_maturity);
+        assert_eq!((mature_balance.spendable, mature_balance.immature), (100, 0));
+
+        let mut spend_tx: Tx = Tx::new(
+            vec![TxInput::new(initialize_empty_key_blocks(), genesis_coinbase.get_tx_id(), false, 0)],
+            vec![TxOutput::new(keypairs[1].pub_key.clone(), 100)],
+        );
+        spend_tx.inputs[0].signature = keypairs[0].create_signature(&spend_tx).into();
+        let block: Block = chain.new_block(&[coinbase_tx(1, 0, keypairs[0].pub_key.clone(), 100), spend_tx], &genesis_hash).unwrap();
+
+        // a block at height 1 cannot spend the genesis coinbase yet, but a block at height 2 can
+        assert!(matches!(check_block_transactions(&block, &chain.utxo, 1, &chain.params), Err(BlockError::ImmatureCoinbaseSpend { tx_index: 1, .. })), "An immature coinbase output was spent");
+        assert!(check_block_transactions(&block, &chain.utxo, 2, &chain.params).is_ok(), "A mature coinbase output could not be spent");
+    }
+
+    #[test]
+    fn coinbase_height() {
+        let keypairs: &[KeyPair] = test_keypairs();
+        let pub_key = &keypairs[0].pub_key;
+        let mut chain: TestChain = TestChain::new(pub_key);
+        let genesis_hash: String = chain.genesis_hash();
+
+        // coinbase transactions paying the same key are unique because they commit to the height
+        assert!(coinbase_tx(1, 0, pub_key.clone(), 100).get_tx_id() != coinbase_tx(2, 0, pub_key.clone(), 100).get_tx_id());
+        assert_eq!(coinbase_tx(1, 0, pub_key.clone(), 100).coinbase_data().map(|coinbase_data| coinbase_data.height), Some(1));
+
+        // a block whose coinbase commits to the wrong height is rejected and remembered as invalid
+        let wrong_height_txs: Vec<Tx> = vec![coinbase_tx(2, 0, pub_key.clone(), 100)];
+        assert!(matches!(check_coinbase_height(&chain.new_block(&wrong_height_txs, &genesis_hash).unwrap(), 1), Err(BlockError::CoinbaseHeightMismatch { height: 1, committed_height: Some(2) })));
+        let wrong_height_block: Block = chain.mine(wrong_height_txs, &genesis_hash);
+        assert_eq!(chain.blocks.len(), 1, "A block whose coinbase commits to the wrong height was accepted");
+        assert!(chain.block_index.get(&wrong_height_block.block_header.hash_block().unwrap()).unwrap().status == BlockStatus::Invalid);
+
+        // so is a block whose coinbase does not commit to any height
+        let no_height_coinbase: Tx = Tx::new(vec![TxInput::new(initialize_empty_key_blocks(), "".to_string(), true, 0)], vec![TxOutput::new(pub_key.clone(), 100)]);
+        let no_height_block: Block = chain.new_block(&[no_height_coinbase], &genesis_hash).unwrap();
+        assert!(matches!(check_coinbase_height(&no_height_block, 1), Err(BlockError::CoinbaseHeightMismatch { height: 1, committed_height: None })));
+    }
+
+    #[test]
+    fn key_reuse() {
+        let keypairs: &[KeyPair] = test_keypairs();
+        let coinbase_tx = |height: u64| -> Tx {
+            coinbase_tx(height, 0, keypairs[0].pub_key.clone(), 100)
+        };
+
+        let mut chain: TestChain = TestChain::with_params(&keypairs[0].pub_key, |params| {
+            params.coinbase_maturity = 0;
+        });
+        let genesis_hash: String = chain.genesis_hash();
+        let genesis_coinbase_id: String = chain.blocks[0].txs.base[0].get_tx_id();
+        let genesis_utxo: UtxoSet = chain.utxo.clone();
+        let key_0_hash: String = keypairs[0].pub_key.hash_key();
+        let key_1_hash: String = keypairs[1].pub_key.hash_key();
+
+        // the first signature of a key is accepted and remembered
+        let tx_a: Tx = spend_tx(&[(genesis_coinbase_id.clone(), 0)], &keypairs[0], keypairs[1].pub_key.clone(), &[45, 45]);
+        let block_1: Block = chain.mine(vec![coinbase_tx(1), tx_a.clone()], &genesis_hash);
+        let block_1_hash: String = block_1.block_header.hash_block().unwrap();
+        assert_eq!(chain.blocks.len(), 2);
+        assert!(chain.utxo.signed_message(&key_0_hash) == Some(&tx_a.get_tx_hash()), "The message signed by a key was not remembered");
+        assert!(chain.has_key_signed(&keypairs[0].pub_key.hash_key()));
+        assert!(!chain.has_key_signed(&keypairs[1].pub_key.hash_key()));
+
+        // a second signature by the same key is rejected by the mempool and by block validation
+        let tx_b: Tx = spend_tx(&[(block_1.txs.base[0].get_tx_id(), 0)], &keypairs[0], keypairs[1].pub_key.clone(), &[100]);
+        assert!(matches!(chain.add_to_mempool(&tx_b), Err(MempoolError::KeyReuse { .. })), "A reused key was accepted by the mempool");
+        assert!(matches!(check_block_transactions(&chain.new_block(&[coinbase_tx(2), tx_b.clone()], &block_1_hash).unwrap(), &chain.utxo, 2, &chain.params), Err(BlockError::KeyReuse { tx_index: 1, .. })));
+        chain.mine(vec![coinbase_tx(2), tx_b.clone()], &block_1_hash);
+        assert_eq!(chain.blocks.len(), 2, "A block with a reused key was connected");
+
+        // two transactions signed by the same key in one block are rejected, but one transaction spending several outputs of a key is fine
+        let tx_c: Tx = spend_tx(&[(tx_a.get_tx_id(), 0)], &keypairs[1], keypairs[2].pub_key.clone(), &[45]);
+        let tx_d: Tx = spend_tx(&[(tx_a.get_tx_id(), 1)], &keypairs[1], keypairs[2].pub_key.clone(), &[40]);
+        let tx_e: Tx = spend_tx(&[(tx_a.get_tx_id(), 0), (tx_a.get_tx_id(), 1)], &keypairs[1], keypairs[2].pub_key.clone(), &[90]);
+        assert!(matches!(check_block_transactions(&chain.new_block(&[coinbase_tx(2), tx_c.clone(), tx_d.clone()], &block_1_hash).unwrap(), &chain.utxo, 2, &chain.params), Err(BlockError::KeyReuse { tx_index: 2, .. })));
+        assert!(check_block_transactions(&chain.new_block(&[coinbase_tx(2), tx_e.clone()], &block_1_hash).unwrap(), &chain.utxo, 2, &chain.params).is_ok());
+
+        // the same goes for the mempool, which also remembers the keys of its transactions until they leave it
+        assert!(chain.add_to_mempool(&tx_c).is_ok());
+        assert!(chain.has_key_signed(&keypairs[1].pub_key.hash_key()), "A key that signed a transaction in the mempool is not seen as used");
+        assert!(matches!(chain.add_to_mempool(&tx_d), Err(MempoolError::KeyReuse { .. })));
+        chain.mempool.expire(i64::MAX);
+        assert!(chain.mempool.signed_message(&key_1_hash).is_none());
+
+        // disconnecting a block forgets the keys that signed in it
+        let mut utxo: UtxoSet = genesis_utxo.clone();
+        let block_1_undo = utxo.apply_block(&block_1, 1).expect("Could not apply block 1");
+        assert!(utxo.signed_message(&key_0_hash).is_some());
+        utxo.undo_block(&block_1, &block_1_undo);
+        assert!(utxo.signed_message(&key_0_hash).is_none(), "A disconnected block did not forget its signing keys");
+
+        // with the flag policy, key reuse is only reported
+        chain.params.key_reuse_policy = KeyReusePolicy::Flag;
+        assert!(chain.add_to_mempool(&tx_b).is_ok(), "A reused key was rejected with the flag policy");
+    }
+}
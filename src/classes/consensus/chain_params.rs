@@ -0,0 +1,238 @@
+use std::fmt;
+
+use crate::classes::block::block::Block;
+use crate::classes::block::block_header::{BlockHeader, BLOCK_VERSION};
+use crate::classes::consensus::params::{ConsensusParams, KeyReusePolicy};
+use crate::classes::consensus::subsidy::block_subsidy;
+use crate::classes::consensus::versionbits::Deployment;
+use crate::classes::lamport_signature::key_pair::Key;
+use crate::classes::transaction::tx::{Tx, TxInput, TxOutput};
+use crate::data_structures::merkle_tree::MerkleTree;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Network {
+    Main,
+    Test,
+    // a local network for development, where blocks are mined almost instantly
+    Regtest,
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Network::Main => write!(f, "main"),
+            Network::Test => write!(f, "test"),
+            Network::Regtest => write!(f, "regtest"),
+        }
+    }
+}
+
+impl Network {
+    pub const ALL: [Network; 3] = [Network::Main, Network::Test, Network::Regtest];
+
+    pub fn from_name(name: &str) -> Option<Network> {
+        match name.trim().to_lowercase().as_str() {
+            "main" | "mainnet" => Some(Network::Main),
+            "test" | "testnet" => Some(Network::Test),
+            "regtest" => Some(Network::Regtest),
+            _ => None,
+        }
+    }
+}
+
+/* What the genesis block of a network is made of (its coinbase transaction pays the subsidy of height 0 to the key it is loaded with),
+along with the hash it is expected to have, so that a node only accepts the genesis block of its own network. */
+#[derive(Clone)]
+pub struct GenesisParams {
+    pub timestamp: i64,
+    pub nonce: u128,
+    pub hash: String,
+}
+
+impl Default for GenesisParams {
+    fn default() -> Self {
+        ChainParams::main().genesis
+    }
+}
+
+impl GenesisParams {
+    pub fn block(&self, pub_key: &Key, params: &ConsensusParams) -> Block {
+        let tx_inputs: Vec<TxInput> = vec![TxInput::new_coinbase(0, 0)];
+        let tx_outputs: Vec<TxOutput> = vec![TxOutput::new(pub_key.clone(), block_subsidy(0, params))];
+        let tx: Tx = Tx::new(tx_inputs, tx_outputs);
+        let tx_merkle_tree: MerkleTree = MerkleTree::new(&[tx]);
+
+        Block {
+            block_header: BlockHeader {
+                version: BLOCK_VERSION,
+                prev_block_hash: "".to_string(),
+                target: params.pow_limit_bits,
+                merkle_root: tx_merkle_tree.merkle_root.clone(),
+                nonce: self.nonce,
+                timestamp: self.timestamp,
+            },
+            txs: tx_merkle_tree
+        }
+    }
+}
+
+/*
+    Everything that sets a network apart from the others: its consensus rules (subsidy, target rules, maturity, ...), its genesis block,
+    the port its nodes listen on by default and the magic bytes every message between its nodes starts with (so that nodes of different networks cannot talk to each other by mistake).
+*/
+#[derive(Clone)]
+pub struct ChainParams {
+    pub network: Network,
+    pub consensus: ConsensusParams,
+    pub genesis: GenesisParams,
+    pub default_port: u16,
+    pub magic_bytes: [u8; 4],
+}
+
+impl ChainParams {
+    pub fn for_network(network: Network) -> ChainParams {
+        match network {
+            Network::Main => ChainParams::main(),
+            Network::Test => ChainParams::test(),
+            Network::Regtest => ChainParams::regtest(),
+        }
+    }
+
+    pub fn main() -> ChainParams {
+        ChainParams {
+            network: Network::Main,
+            consensus: ConsensusParams::new(),
+            genesis: GenesisParams {
+                timestamp: 1735577085,
+                nonce: 14184,
+                hash: "0000bdd62f65bf5178e8749ebf0a6b613ac0d176c7a6497277a99186c0283996".to_string(),
+            },
+            default_port: 8333,
+            magic_bytes: [0xf9, 0xbe, 0xb4, 0xd9],
+        }
+    }
+
+    // the same rules as the main network, with its own genesis block
+    pub fn test() -> ChainParams {
+        ChainParams {
+            network: Network::Test,
+            consensus: ConsensusParams::new(),
+            genesis: GenesisParams {
+                timestamp: 1760745600,
+                nonce: 140475,
+                hash: "0000a8ac29ac13faafb32d965357e58249ab74edd7e19ac5a9b7fdb711602781".to_string(),
+            },
+            default_port: 18333,
+            magic_bytes: [0x0b, 0x11, 0x09, 0x07],
+        }
+    }
+
+    /* The easiest possible target without retargeting, fast halvings and short deployment windows, so that any scenario can be tested quickly.
+    Key reuse is only flagged, so that a scenario can reuse a key on purpose. */
+    pub fn regtest() -> ChainParams {
+        let mut consensus: ConsensusParams = ConsensusParams::new();
+        consensus.pow_limit_bits = 0x207fffff;
+        consensus.retarget_interval = 0;
+        consensus.subsidy_halving_interval = 150;
+        consensus.version_bits_window = 144;
+        consensus.version_bits_threshold = 108;
+        consensus.deployments = vec![Deployment::new("testdummy", 28, 0, u64::MAX).expect("The test deployment does not signal with a valid bit")];
+        consensus.key_reuse_policy = KeyReusePolicy::Flag;
+
+        ChainParams {
+            network: Network::Regtest,
+            consensus,
+            genesis: GenesisParams {
+                timestamp: 1760745600,
+                nonce: 4,
+                hash: "2e37077c0b37c2c8e0b8eba3fe9ba32778d86ce66e350894442f0cb41c2ef003".to_string(),
+            },
+            default_port: 18444,
+            magic_bytes: [0xfa, 0xbf, 0xb5, 0xda],
+        }
+    }
+}
+
+/* Mines a new genesis block for the given chain parameters, paying the subsidy of height 0 to the given key, with the given timestamp.
+This is how the genesis block of a new network (or a new environment) is created: the returned values go into its chain parameters. */
+pub fn mine_genesis(chain_params: &ChainParams, pub_key: &Key, timestamp: i64) -> GenesisParams {
+    let mut genesis: GenesisParams = GenesisParams {
+        timestamp,
+        nonce: 0,
+        hash: "".to_string(),
+    };
+
+    let mut genesis_block: Block = genesis.block(pub_key, &chain_params.consensus);
+    while !genesis_block.block_header.meets_target() {
+        genesis_block.block_header.nonce += 1;
+    }
+
+    genesis.nonce = genesis_block.block_header.nonce;
+    // a header that meets its target has been hashed, so it can always be encoded here
+    genesis.hash = genesis_block.block_header.hash_block().expect("The genesis header could not be encoded");
+    genesis
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::classes::block::block::Block;
+    use crate::classes::block::blockchain::{BlockAcceptance, Blockchain};
+    use crate::classes::consensus::params::KeyReusePolicy;
+    use crate::classes::lamport_signature::key_pair::Key;
+    use crate::util::test_util::{coinbase_tx, test_keypairs, TempDir};
+
+    use super::{mine_genesis, ChainParams, Network};
+
+    #[test]
+    fn chain_params() {
+        let pub_key: &Key = &test_keypairs()[0].pub_key;
+        let temp_dir: TempDir = TempDir::new();
+        let branches_filename: String = temp_dir.file("testbranches.bin");
+        let networks: Vec<ChainParams> = Network::ALL.iter().map(|network| ChainParams::for_network(*network)).collect();
+
+        // every network has its own genesis block, port and magic bytes
+        for (network_index, chain_params) in networks.iter().enumerate() {
+            let genesis_block: Block = chain_params.genesis.block(pub_key, &chain_params.consensus);
+            assert_eq!(genesis_block.block_header.hash_block().unwrap(), chain_params.genesis.hash, "The genesis block of the {} network does not have the expected hash", chain_params.network);
+            assert!(genesis_block.block_header.meets_target());
+
+            let mut blockchain = Blockchain::with_chain_params(chain_params);
+            blockchain.load_genesis_block(pub_key, &branches_filename);
+            assert_eq!(blockchain.blocks[0].block_header.hash_block().unwrap(), chain_params.genesis.hash);
+
+            for other_chain_params in networks.iter().skip(network_index + 1) {
+                assert!(chain_params.genesis.hash != other_chain_params.genesis.hash);
+                assert!(chain_params.default_port != other_chain_params.default_port);
+                assert!(chain_params.magic_bytes != other_chain_params.magic_bytes);
+            }
+            assert!(Network::from_name(&chain_params.network.to_string()) == Some(chain_params.network));
+        }
+        assert!(Network::from_name("devnet").is_none());
+
+        // only regtest lets a key sign twice
+        assert!(ChainParams::main().consensus.key_reuse_policy == KeyReusePolicy::Reject && ChainParams::test().consensus.key_reuse_policy == KeyReusePolicy::Reject);
+        assert!(ChainParams::regtest().consensus.key_reuse_policy == KeyReusePolicy::Flag);
+
+        // a node only accepts the genesis block of its own network
+        let main_genesis: Block = networks[0].genesis.block(pub_key, &networks[0].consensus);
+        let mut regtest_blockchain = Blockchain::with_chain_params(&networks[2]);
+        assert!(!matches!(regtest_blockchain.accept_new_block(&main_genesis, &branches_filename), BlockAcceptance::Accepted), "The genesis block of another network was accepted");
+        assert!(regtest_blockchain.blocks.is_empty());
+
+        // a custom network gets its own genesis block from the genesis miner
+        let mut custom_chain_params: ChainParams = ChainParams::regtest();
+        custom_chain_params.genesis = mine_genesis(&custom_chain_params, pub_key, 1767225600);
+        assert!(custom_chain_params.genesis.hash != networks[2].genesis.hash);
+        let custom_genesis: Block = custom_chain_params.genesis.block(pub_key, &custom_chain_params.consensus);
+        let mut custom_blockchain = Blockchain::with_chain_params(&custom_chain_params);
+        assert!(matches!(custom_blockchain.accept_new_block(&custom_genesis, &branches_filename), BlockAcceptance::Accepted));
+        assert_eq!(custom_blockchain.blocks.len(), 1, "The mined genesis block was not accepted");
+
+        // blocks are mined almost instantly on regtest
+        let custom_genesis_hash: String = custom_genesis.block_header.hash_block().unwrap();
+        let mut block: Block = custom_blockchain.new_block(&[coinbase_tx(1, 0, pub_key.clone(), 100)], &custom_genesis_hash).unwrap();
+        block.mine_block();
+        custom_blockchain.accept_new_block(&block, &branches_filename);
+        assert_eq!(custom_blockchain.blocks.len(), 2);
+    }
+}
@@ -0,0 +1,26 @@
+use chrono::Utc;
+
+// the source of the current time used when validating block timestamps, so that the node's clock can be replaced (e.g. when testing)
+pub trait Clock: Send + Sync {
+    fn now(&self) -> i64;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> i64 {
+        Utc::now().timestamp()
+    }
+}
+
+#[cfg(test)]
+pub struct FixedClock {
+    pub time: i64,
+}
+
+#[cfg(test)]
+impl Clock for FixedClock {
+    fn now(&self) -> i64 {
+        self.time
+    }
+}
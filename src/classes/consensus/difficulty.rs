@@ -0,0 +1,154 @@
+use crate::classes::block::block_index::{BlockIndex, BlockIndexEntry};
+use crate::classes::consensus::params::ConsensusParams;
+use crate::data_structures::uint256::U256;
+
+/*
+    Targets are stored in block headers in a compact form (like Bitcoin's nBits): the highest byte is the number of bytes of the target (the exponent),
+    and the lower three bytes are its most significant bytes (the mantissa), so target = mantissa * 256^(exponent - 3).
+    The 0x00800000 bit is a sign bit, so a compact target with it set is negative and therefore invalid.
+*/
+pub fn compact_to_target(compact: u32) -> Option<U256> {
+    let exponent: u32 = compact >> 24;
+    let mantissa: u32 = compact & 0x007fffff;
+
+    if compact & 0x00800000 != 0 && mantissa != 0 {
+        return None;
+    }
+
+    if exponent <= 3 {
+        return Some(U256::from_u64((mantissa >> (8 * (3 - exponent))) as u64));
+    }
+
+    // the mantissa shifted by the exponent must fit into 256 bits
+    if mantissa != 0 && (exponent > 34 || (mantissa > 0xff && exponent > 33) || (mantissa > 0xffff && exponent > 32)) {
+        return None;
+    }
+
+    Some(U256::from_u64(mantissa as u64).shl(8 * (exponent - 3)))
+}
+
+pub fn target_to_compact(target: &U256) -> u32 {
+    let mut exponent: u32 = target.bits().div_ceil(8);
+    let mut mantissa: u32 = if exponent <= 3 {
+        (target.low_u64() << (8 * (3 - exponent))) as u32
+    } else {
+        target.shr(8 * (exponent - 3)).low_u64() as u32
+    };
+
+    // the mantissa cannot have its sign bit set, so move one byte over to the exponent instead
+    if mantissa & 0x00800000 != 0 {
+        mantissa >>= 8;
+        exponent += 1;
+    }
+
+    mantissa | (exponent << 24)
+}
+
+/* The expected number of hashes needed to find a block hash at or below the target, which is 2^256 / (target + 1).
+2^256 does not fit in 256 bits, so it is computed as (2^256 - target - 1) / (target + 1) + 1. */
+pub fn target_work(compact: u32) -> u128 {
+    let target: U256 = match compact_to_target(compact) {
+        Some(target) => target,
+        None => return 0,
+    };
+
+    let target_plus_one: U256 = target.wrapping_add(&U256::one());
+    let work: U256 = match target.not().checked_div(&target_plus_one) {
+        Some(quotient) => quotient.wrapping_add(&U256::one()),
+        // the target was the maximum 256-bit value, so any hash meets it
+        None => U256::one(),
+    };
+
+    work.saturating_to_u128()
+}
+
+// whether the compact target is valid and no easier than the proof-of-work limit
+pub fn is_within_pow_limit(compact: u32, params: &ConsensusParams) -> bool {
+    let pow_limit: U256 = compact_to_target(params.pow_limit_bits).expect("Error: The proof-of-work limit is not a valid compact target");
+    compact_to_target(compact).is_some_and(|target| target <= pow_limit)
+}
+
+/*
+    Computes the target that the child of the given parent block must have.
+    The target only changes on the first block of every retarget window: the previous target is scaled by how long the previous window actually took to mine
+    compared to how long it should have taken. To keep a single window from swinging the difficulty too much, the adjustment is limited to a factor of 4 either way,
+    and the target can never be easier than the proof-of-work limit.
+*/
+pub fn next_target(parent: &BlockIndexEntry, block_index: &BlockIndex, params: &ConsensusParams) -> u32 {
+    let parent_target: u32 = parent.block.block_header.target;
+    let next_height: u64 = parent.height + 1;
+
+    if params.retarget_interval < 2 || !next_height.is_multiple_of(params.retarget_interval) {
+        return parent_target;
+    }
+
+    let window_start: &BlockIndexEntry = match block_index.ancestor(&parent.hash, next_height - params.retarget_interval) {
+        Some(entry) => entry,
+        None => return parent_target,
+    };
+
+    // the window holds retarget_interval blocks, so there are retarget_interval - 1 block spacings between its first and its last block
+    let expected_timespan: i64 = params.target_block_spacing * (params.retarget_interval as i64 - 1);
+    let mut actual_timespan: i64 = parent.block.block_header.timestamp - window_start.block.block_header.timestamp;
+    actual_timespan = actual_timespan.clamp(expected_timespan / 4, expected_timespan * 4);
+
+    let pow_limit: U256 = compact_to_target(params.pow_limit_bits).expect("Error: The proof-of-work limit is not a valid compact target");
+    let old_target: U256 = compact_to_target(parent_target).unwrap_or(pow_limit);
+
+    let new_target: U256 = match old_target.checked_mul_u64(actual_timespan as u64) {
+        Some(scaled_target) => scaled_target.checked_div(&U256::from_u64(expected_timespan as u64)).unwrap_or(pow_limit),
+        None => pow_limit,
+    };
+
+    if new_target > pow_limit {
+        return target_to_compact(&pow_limit);
+    }
+
+    target_to_compact(&new_target)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data_structures::uint256::U256;
+
+    use super::{compact_to_target, target_to_compact, target_work};
+
+    #[test]
+    fn compact_target_conversions() {
+        // (compact target, expanded target as hex) pairs, including the main network's proof-of-work limit and Bitcoin's genesis target
+        let test_vectors: Vec<(u32, &str)> = vec![
+            (0x1f00ffff, "0000ffff00000000000000000000000000000000000000000000000000000000"),
+            (0x1d00ffff, "00000000ffff0000000000000000000000000000000000000000000000000000"),
+            (0x1b0404cb, "00000000000404cb000000000000000000000000000000000000000000000000"),
+            (0x01120000, "0000000000000000000000000000000000000000000000000000000000000012"),
+            (0x02008000, "0000000000000000000000000000000000000000000000000000000000000080"),
+            (0x05009234, "0000000000000000000000000000000000000000000000000000000092340000"),
+        ];
+
+        for (compact, target_hex) in &test_vectors {
+            let target: U256 = compact_to_target(*compact).expect("Compact target should be valid");
+            assert_eq!(hex::encode(target.to_be_bytes()), *target_hex, "Expanding {:#010x} gave the wrong target", compact);
+            assert_eq!(target_to_compact(&target), *compact, "Compressing the target of {:#010x} did not give it back", compact);
+        }
+
+        // the mantissa is truncated to three bytes, and an empty mantissa is zero
+        assert_eq!(compact_to_target(0x01123456), Some(U256::from_u64(0x12)));
+        assert_eq!(compact_to_target(0x01003456), Some(U256::zero()));
+        assert_eq!(target_to_compact(&U256::zero()), 0);
+
+        // a set sign bit makes the target negative and a too big exponent makes it overflow
+        assert_eq!(compact_to_target(0x04923456), None);
+        assert_eq!(compact_to_target(0xff123456), None);
+
+        // work is 2^256 / (target + 1)
+        assert_eq!(target_work(0x1f00ffff), 65537);
+        assert_eq!(target_work(0x1d00ffff), 4295032833);
+
+        // hashes are compared numerically against the target
+        let target: U256 = compact_to_target(0x1f00ffff).unwrap();
+        let hash_below_target: [u8; 32] = hex::decode("0000fffeffffffffffffffffffffffffffffffffffffffffffffffffffffffff").unwrap().try_into().unwrap();
+        let hash_above_target: [u8; 32] = hex::decode("0000ffff00000000000000000000000000000000000000000000000000000001").unwrap().try_into().unwrap();
+        assert!(U256::from_be_bytes(&hash_below_target) <= target);
+        assert!(U256::from_be_bytes(&hash_above_target) > target);
+    }
+}
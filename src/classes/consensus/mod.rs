@@ -0,0 +1,7 @@
+pub mod chain_params;
+pub mod clock;
+pub mod difficulty;
+pub mod params;
+pub mod subsidy;
+pub mod timestamp;
+pub mod versionbits;
\ No newline at end of file
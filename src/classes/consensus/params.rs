@@ -0,0 +1,72 @@
+use crate::classes::consensus::versionbits::Deployment;
+
+// what happens to a transaction signed by a Lamport key that has already signed a different transaction
+#[derive(Clone, Copy, PartialEq)]
+pub enum KeyReusePolicy {
+    // the transaction is accepted, but a warning is printed
+    Flag,
+    // the transaction (and any block that includes it) is rejected
+    Reject,
+}
+
+#[derive(Clone)]
+pub struct ConsensusParams {
+    // the compact target of the genesis block, and the easiest target a block can ever have
+    pub pow_limit_bits: u32,
+    // the target is recomputed every retarget_interval blocks
+    pub retarget_interval: u64,
+    // the number of seconds that should pass between two blocks
+    pub target_block_spacing: i64,
+    // a block's timestamp must be after the median timestamp of this many previous blocks
+    pub median_time_span: usize,
+    // how many seconds a block's timestamp can be ahead of this node's clock
+    pub max_future_block_time: i64,
+    // the amount of new money a coinbase transaction can create (on top of the fees of the transactions in its block) before the first halving
+    pub initial_block_subsidy: u64,
+    // the block subsidy is halved every subsidy_halving_interval blocks (0 means it is never halved)
+    pub subsidy_halving_interval: u64,
+    // the block subsidy never drops below this amount (0 means no tail emission, so the money supply is capped)
+    pub tail_emission: u64,
+    // a coinbase output can only be spent by a block at least coinbase_maturity blocks above the block that created it
+    pub coinbase_maturity: u64,
+    // the maximum size of a block (the total size of its serialized transactions), in bytes
+    pub max_block_size: usize,
+    // deployments are signalled for and activated in windows of version_bits_window blocks
+    pub version_bits_window: u64,
+    // how many blocks of a window must signal for a deployment for it to lock in
+    pub version_bits_threshold: u64,
+    // the rule changes that can be activated by signalling with the block version
+    pub deployments: Vec<Deployment>,
+    // whether a second signature by the same one-time key is only flagged or rejected
+    pub key_reuse_policy: KeyReusePolicy,
+}
+
+impl ConsensusParams {
+    pub fn new() -> ConsensusParams {
+        ConsensusParams {
+            // 0x0000ffff00000000...: a block hash needs at least 16 leading zero bits
+            pow_limit_bits: 0x1f00ffff,
+            retarget_interval: 10,
+            target_block_spacing: 60,
+            median_time_span: 11,
+            max_future_block_time: 2 * 60 * 60,
+            initial_block_subsidy: 100,
+            subsidy_halving_interval: 1000,
+            tail_emission: 0,
+            coinbase_maturity: 10,
+            max_block_size: 1_000_000,
+            version_bits_window: 100,
+            // 95% of a window
+            version_bits_threshold: 95,
+            // a deployment that never starts, only there to show how deployments are defined
+            deployments: vec![Deployment::new("testdummy", 28, u64::MAX, u64::MAX).expect("The test deployment does not signal with a valid bit")],
+            key_reuse_policy: KeyReusePolicy::Reject,
+        }
+    }
+}
+
+impl Default for ConsensusParams {
+    fn default() -> Self {
+        Self::new()
+    }
+}
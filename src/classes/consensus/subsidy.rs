@@ -0,0 +1,96 @@
+use crate::classes::consensus::params::ConsensusParams;
+
+/*
+    The amount of new money the coinbase transaction of the block at the given height can create (on top of the fees of its block).
+    The subsidy starts at initial_block_subsidy and is halved every subsidy_halving_interval blocks, until it reaches zero (or the tail emission, if there is one,
+    below which the subsidy never drops).
+*/
+pub fn block_subsidy(height: u64, params: &ConsensusParams) -> u64 {
+    // an interval of 0 means the subsidy is never halved
+    let halvings: u64 = height.checked_div(params.subsidy_halving_interval).unwrap_or(0);
+
+    // shifting a u64 by 64 bits or more is not allowed, and the subsidy would be zero anyway
+    let halved_subsidy: u64 = if halvings >= 64 { 0 } else { params.initial_block_subsidy >> halvings };
+
+    halved_subsidy.max(params.tail_emission)
+}
+
+/* The total amount of money created by the subsidies of the blocks from the genesis block up to and including the block at the given height.
+Since the subsidy is constant within a halving interval, this is computed one interval at a time instead of one block at a time. */
+pub fn total_supply(height: u64, params: &ConsensusParams) -> u64 {
+    if params.subsidy_halving_interval == 0 {
+        return block_subsidy(0, params).saturating_mul(height.saturating_add(1));
+    }
+
+    let mut supply: u64 = 0;
+    let mut interval_start: u64 = 0;
+    while interval_start <= height {
+        let subsidy: u64 = block_subsidy(interval_start, params);
+        let interval_end: u64 = interval_start.saturating_add(params.subsidy_halving_interval - 1).min(height);
+
+        // once the subsidy reaches zero, no more money is ever created
+        if subsidy == 0 {
+            break;
+        }
+
+        // once the subsidy stops halving (because of the tail emission), every remaining block creates the same amount
+        let remaining_blocks: u64 = if subsidy == params.tail_emission { height - interval_start + 1 } else { interval_end - interval_start + 1 };
+        supply = supply.saturating_add(subsidy.saturating_mul(remaining_blocks));
+        if subsidy == params.tail_emission || interval_end == u64::MAX {
+            break;
+        }
+
+        interval_start = interval_end + 1;
+    }
+
+    supply
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::classes::block::block::Block;
+    use crate::classes::block::validation::{check_block_transactions, BlockError};
+    use crate::classes::consensus::params::ConsensusParams;
+    use crate::util::test_util::{coinbase_tx, test_keypairs, TestChain};
+
+    use super::{block_subsidy, total_supply};
+
+    #[test]
+    fn subsidy_schedule() {
+        let mut params: ConsensusParams = ConsensusParams::new();
+        params.initial_block_subsidy = 100;
+        params.subsidy_halving_interval = 1000;
+        params.tail_emission = 0;
+
+        assert_eq!(block_subsidy(0, &params), 100);
+        assert_eq!(block_subsidy(999, &params), 100);
+        assert_eq!(block_subsidy(1000, &params), 50);
+        assert_eq!(block_subsidy(6999, &params), 1);
+        assert_eq!(block_subsidy(7000, &params), 0);
+        assert_eq!(block_subsidy(u64::MAX, &params), 0);
+
+        // the total supply matches adding up the subsidy of every block, and is capped once the subsidy reaches zero
+        let mut expected_supply: u64 = 0;
+        for height in 0..8000 {
+            expected_supply += block_subsidy(height, &params);
+            assert_eq!(total_supply(height, &params), expected_supply, "The total supply at height {} is wrong", height);
+        }
+        assert_eq!(total_supply(u64::MAX, &params), 197000);
+
+        // with a tail emission, the subsidy stops halving and the supply keeps growing
+        params.tail_emission = 10;
+        assert_eq!(block_subsidy(4000, &params), 10);
+        assert_eq!(block_subsidy(u64::MAX, &params), 10);
+        assert_eq!(total_supply(4999, &params), 197000);
+        assert_eq!(total_supply(5999, &params), 207000);
+    }
+
+    #[test]
+    fn coinbase_claims_subsidy_of_its_height() {
+        let pub_key = &test_keypairs()[0].pub_key;
+        let chain: TestChain = TestChain::with_params(pub_key, |params| params.subsidy_halving_interval = 2);
+        let block: Block = chain.new_block(&[coinbase_tx(1, 0, pub_key.clone(), 100)], &chain.genesis_hash()).unwrap();
+        assert!(check_block_transactions(&block, &chain.utxo, 1, &chain.params).is_ok());
+        assert!(matches!(check_block_transactions(&block, &chain.utxo, 2, &chain.params), Err(BlockError::CoinbaseAmountTooHigh { amount: 100, allowed_amount: 50 })), "A coinbase claimed more than the subsidy of its height");
+    }
+}
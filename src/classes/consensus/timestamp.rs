@@ -0,0 +1,58 @@
+use std::fmt;
+
+use crate::classes::block::block_header::BlockHeader;
+use crate::classes::block::block_index::{BlockIndex, BlockIndexEntry};
+use crate::classes::consensus::params::ConsensusParams;
+
+pub enum TimestampError {
+    NotAfterMedianTimePast { timestamp: i64, median_time_past: i64 },
+    TooFarInFuture { timestamp: i64, max_allowed: i64 },
+}
+
+impl fmt::Display for TimestampError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimestampError::NotAfterMedianTimePast { timestamp, median_time_past } => {
+                write!(f, "Block timestamp {} is not after the median time of the previous blocks ({})", timestamp, median_time_past)
+            },
+            TimestampError::TooFarInFuture { timestamp, max_allowed } => {
+                write!(f, "Block timestamp {} is too far in the future (the latest allowed timestamp is {})", timestamp, max_allowed)
+            },
+        }
+    }
+}
+
+// the median timestamp of the given block and the blocks before it, looking at no more than median_time_span blocks
+pub fn median_time_past(block: &BlockIndexEntry, block_index: &BlockIndex, median_time_span: usize) -> i64 {
+    let mut timestamps: Vec<i64> = vec![];
+    let mut current_entry: Option<&BlockIndexEntry> = Some(block);
+
+    while let Some(entry) = current_entry {
+        if timestamps.len() == median_time_span {
+            break;
+        }
+        timestamps.push(entry.block.block_header.timestamp);
+        current_entry = block_index.get(&entry.prev_hash);
+    }
+
+    timestamps.sort();
+    timestamps[timestamps.len() / 2]
+}
+
+/*
+    A block's timestamp must be greater than the median timestamp of the previous blocks, so that timestamps keep moving forward even if a few blocks lie about the time,
+    and it cannot be further into the future (according to this node's clock) than the allowed drift.
+*/
+pub fn check_block_timestamp(block_header: &BlockHeader, parent: &BlockIndexEntry, block_index: &BlockIndex, params: &ConsensusParams, now: i64) -> Result<(), TimestampError> {
+    let median_time_past: i64 = median_time_past(parent, block_index, params.median_time_span);
+    if block_header.timestamp <= median_time_past {
+        return Err(TimestampError::NotAfterMedianTimePast { timestamp: block_header.timestamp, median_time_past });
+    }
+
+    let max_allowed: i64 = now + params.max_future_block_time;
+    if block_header.timestamp > max_allowed {
+        return Err(TimestampError::TooFarInFuture { timestamp: block_header.timestamp, max_allowed });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+use crate::classes::block::block_index::{BlockIndex, BlockIndexEntry};
+use crate::classes::consensus::params::ConsensusParams;
+
+// the top three bits of a block version that signals for deployments are 001, so that the other 29 bits can be used as signalling bits
+pub const VERSION_BITS_TOP_BITS: u32 = 0x20000000;
+pub const VERSION_BITS_TOP_MASK: u32 = 0xe0000000;
+// the number of bits below the top bits
+pub const VERSION_BITS_COUNT: u8 = 29;
+
+// a rule change that is rolled out once enough blocks signal for it with their version
+#[derive(Clone)]
+pub struct Deployment {
+    pub name: String,
+    // the bit of the block version that signals for the deployment (0 to 28)
+    pub bit: u8,
+    // signalling starts with the first window that starts at or above this height
+    pub start_height: u64,
+    // if the deployment has not locked in by the first window that starts at or above this height, it fails
+    pub timeout_height: u64,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum DeploymentState {
+    // the deployment has not started yet
+    Defined,
+    // blocks signal for the deployment, until enough blocks of a window do
+    Started,
+    // enough blocks of the last window signalled, so the deployment becomes active in the next window
+    LockedIn,
+    // the rule is enforced by every block from now on
+    Active,
+    // the deployment timed out before locking in
+    Failed,
+}
+
+impl fmt::Display for DeploymentState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeploymentState::Defined => write!(f, "defined"),
+            DeploymentState::Started => write!(f, "started"),
+            DeploymentState::LockedIn => write!(f, "locked in"),
+            DeploymentState::Active => write!(f, "active"),
+            DeploymentState::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+impl Deployment {
+    // a deployment can only signal with one of the bits below the top bits, so any other bit gives no deployment
+    pub fn new(name: &str, bit: u8, start_height: u64, timeout_height: u64) -> Option<Deployment> {
+        if bit >= VERSION_BITS_COUNT {
+            return None;
+        }
+
+        Some(Deployment {
+            name: name.to_string(),
+            bit,
+            start_height,
+            timeout_height,
+        })
+    }
+
+    // whether a block with the given version signals for the deployment
+    pub fn is_signalled_by(&self, version: u32) -> bool {
+        version & VERSION_BITS_TOP_MASK == VERSION_BITS_TOP_BITS && version & (1 << self.bit) != 0
+    }
+}
+
+/* The state of every deployment for the blocks of a window never changes once it is known (it only depends on the blocks before the window),
+so it is kept for the last block of the previous window, keyed by the hash of that block and the name of the deployment. */
+#[derive(Default)]
+pub struct VersionBitsCache {
+    states: Mutex<HashMap<(String, String), DeploymentState>>,
+}
+
+impl Clone for VersionBitsCache {
+    fn clone(&self) -> Self {
+        return VersionBitsCache {
+            states: Mutex::new(self.states.lock().unwrap().clone()),
+        };
+    }
+}
+
+impl VersionBitsCache {
+    fn get(&self, boundary_hash: &str, deployment: &Deployment) -> Option<DeploymentState> {
+        self.states.lock().unwrap().get(&(boundary_hash.to_string(), deployment.name.clone())).copied()
+    }
+
+    fn insert(&self, boundary_hash: &str, deployment: &Deployment, state: DeploymentState) {
+        self.states.lock().unwrap().insert((boundary_hash.to_string(), deployment.name.clone()), state);
+    }
+}
+
+/*
+    Computes the state of a deployment for the child of the given parent block (like Bitcoin's BIP9, but with heights instead of times).
+    The blocks are split into windows of version_bits_window blocks, and the state only changes on the first block of a window, based on the previous window:
+    a defined deployment starts once the window reaches its start height, a started deployment locks in if at least version_bits_threshold blocks of the previous
+    window signalled for it (or fails once the window reaches its timeout height), and a locked in deployment becomes active one window later.
+    Active and failed are final states.
+    The states are cached for the last block of every window, so only the windows since the last cached one are looked at, and only the blocks of the window
+    that ends with a started state are counted.
+*/
+pub fn deployment_state(parent: &BlockIndexEntry, block_index: &BlockIndex, params: &ConsensusParams, deployment: &Deployment, cache: &VersionBitsCache) -> DeploymentState {
+    let window: u64 = params.version_bits_window.max(1);
+
+    // the last block of the previous window (there is none in the first window, where every deployment is defined)
+    let next_height: u64 = parent.height + 1;
+    let mut current_boundary: Option<&BlockIndexEntry> = (next_height - next_height % window).checked_sub(1)
+        .and_then(|boundary_height| block_index.ancestor(&parent.hash, boundary_height));
+
+    // walk back one window at a time, until a window whose state is already known
+    let mut state: DeploymentState = DeploymentState::Defined;
+    let mut uncached_boundaries: Vec<&BlockIndexEntry> = vec![];
+    while let Some(boundary) = current_boundary {
+        if let Some(cached_state) = cache.get(&boundary.hash, deployment) {
+            state = cached_state;
+            break;
+        }
+
+        // a window that starts before the deployment can start or time out is defined, and so is every window before it
+        let window_start_height: u64 = boundary.height + 1;
+        if window_start_height < deployment.start_height && window_start_height < deployment.timeout_height {
+            cache.insert(&boundary.hash, deployment, DeploymentState::Defined);
+            break;
+        }
+
+        uncached_boundaries.push(boundary);
+        current_boundary = boundary.height.checked_sub(window).and_then(|boundary_height| block_index.ancestor(&boundary.hash, boundary_height));
+    }
+
+    // then move the state forward from the oldest window to the newest one, caching it for every window
+    while let Some(boundary) = uncached_boundaries.pop() {
+        let window_start_height: u64 = boundary.height + 1;
+
+        state = match state {
+            DeploymentState::Defined if window_start_height >= deployment.timeout_height => DeploymentState::Failed,
+            DeploymentState::Defined if window_start_height >= deployment.start_height => DeploymentState::Started,
+            DeploymentState::Started => {
+                if window_signal_count(boundary, block_index, window, deployment) >= params.version_bits_threshold {
+                    DeploymentState::LockedIn
+                } else if window_start_height >= deployment.timeout_height {
+                    DeploymentState::Failed
+                } else {
+                    DeploymentState::Started
+                }
+            }
+            DeploymentState::LockedIn => DeploymentState::Active,
+            other_state => other_state,
+        };
+
+        cache.insert(&boundary.hash, deployment, state);
+    }
+
+    state
+}
+
+// the number of blocks of the window ending with the given block that signal for the deployment
+fn window_signal_count(window_end: &BlockIndexEntry, block_index: &BlockIndex, window: u64, deployment: &Deployment) -> u64 {
+    let mut signal_count: u64 = 0;
+    let mut current_entry: Option<&BlockIndexEntry> = Some(window_end);
+    for _ in 0..window {
+        let entry: &BlockIndexEntry = match current_entry {
+            Some(entry) => entry,
+            None => break,
+        };
+        if deployment.is_signalled_by(entry.block.block_header.version) {
+            signal_count += 1;
+        }
+        current_entry = block_index.get(&entry.prev_hash);
+    }
+
+    signal_count
+}
+
+// the version of the child of the given parent block: it signals for every deployment that is started or locked in
+pub fn next_block_version(parent: &BlockIndexEntry, block_index: &BlockIndex, params: &ConsensusParams, cache: &VersionBitsCache) -> u32 {
+    let mut version: u32 = VERSION_BITS_TOP_BITS;
+    for deployment in &params.deployments {
+        let state: DeploymentState = deployment_state(parent, block_index, params, deployment, cache);
+        if state == DeploymentState::Started || state == DeploymentState::LockedIn {
+            version |= 1 << deployment.bit;
+        }
+    }
+
+    version
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::classes::block::block::Block;
+    use crate::classes::block::block_index::{BlockIndex, BlockStatus};
+    use crate::classes::consensus::params::ConsensusParams;
+    use crate::classes::lamport_signature::key_pair::Key;
+    use crate::classes::transaction::tx::Tx;
+    use crate::util::test_util::{coinbase_tx, test_keypairs, TestChain};
+
+    use super::{deployment_state, next_block_version, Deployment, DeploymentState, VersionBitsCache, VERSION_BITS_COUNT, VERSION_BITS_TOP_BITS};
+
+    #[test]
+    fn version_bits() {
+        let pub_key: &Key = &test_keypairs()[0].pub_key;
+        let mut params: ConsensusParams = ConsensusParams::new();
+        params.version_bits_window = 4;
+        params.version_bits_threshold = 3;
+        params.deployments = vec![Deployment::new("locks in", 1, 4, 16).unwrap(), Deployment::new("times out", 2, 4, 12).unwrap()];
+        let locks_in: Deployment = params.deployments[0].clone();
+        let times_out: Deployment = params.deployments[1].clone();
+        let signalling_version: u32 = VERSION_BITS_TOP_BITS | (1 << locks_in.bit);
+
+        let cache: VersionBitsCache = VersionBitsCache::default();
+
+        // only the bits below the top bits can signal
+        assert!(Deployment::new("top bit", VERSION_BITS_COUNT, 4, 16).is_none());
+
+        // the deployment state only depends on the block index, so the blocks are indexed without being connected
+        let mut block_index: BlockIndex = BlockIndex::new();
+        let mut block_hashes: Vec<String> = vec![];
+        let append_block = |block_index: &mut BlockIndex, block_hashes: &mut Vec<String>, version: u32| {
+            let height: u64 = block_hashes.len() as u64;
+            let prev_block_hash: String = block_hashes.last().cloned().unwrap_or("".to_string());
+            let mut block: Block = Block::new(&[coinbase_tx(height, 0, pub_key.clone(), 100)], prev_block_hash, params.pow_limit_bits);
+            block.block_header.version = version;
+            block.mine_block();
+            block_hashes.push(block_index.insert(&block, BlockStatus::Valid).unwrap());
+        };
+        let state_after = |block_index: &BlockIndex, block_hash: &String, deployment: &Deployment| -> DeploymentState {
+            deployment_state(block_index.get(block_hash).unwrap(), block_index, &params, deployment, &cache)
+        };
+
+        // window 0 (heights 0 to 3): both deployments are defined, and start with window 1
+        for _ in 0..4 {
+            append_block(&mut block_index, &mut block_hashes, 1);
+        }
+        assert!(state_after(&block_index, &block_hashes[2], &locks_in) == DeploymentState::Defined);
+        assert!(state_after(&block_index, &block_hashes[3], &locks_in) == DeploymentState::Started);
+        assert_eq!(next_block_version(block_index.get(&block_hashes[3]).unwrap(), &block_index, &params, &cache), VERSION_BITS_TOP_BITS | (1 << locks_in.bit) | (1 << times_out.bit));
+
+        // window 1: only two blocks signal properly (the bit does not count without the top bits), which is below the threshold
+        for version in [signalling_version, signalling_version, 1 << locks_in.bit, VERSION_BITS_TOP_BITS] {
+            append_block(&mut block_index, &mut block_hashes, version);
+        }
+        assert!(state_after(&block_index, &block_hashes[7], &locks_in) == DeploymentState::Started, "A deployment locked in below the threshold");
+
+        // window 2: three blocks signal, so the deployment locks in, while the other one reaches its timeout
+        for version in [signalling_version, VERSION_BITS_TOP_BITS, signalling_version, signalling_version] {
+            append_block(&mut block_index, &mut block_hashes, version);
+        }
+        assert!(state_after(&block_index, &block_hashes[11], &locks_in) == DeploymentState::LockedIn, "A deployment did not lock in");
+        assert!(state_after(&block_index, &block_hashes[11], &times_out) == DeploymentState::Failed, "A deployment did not time out");
+        assert_eq!(next_block_version(block_index.get(&block_hashes[11]).unwrap(), &block_index, &params, &cache), signalling_version);
+
+        // window 3: the locked in deployment becomes active (even though it is past its timeout), whatever the blocks signal
+        for _ in 0..4 {
+            append_block(&mut block_index, &mut block_hashes, VERSION_BITS_TOP_BITS);
+        }
+        assert!(state_after(&block_index, &block_hashes[15], &locks_in) == DeploymentState::Active, "A locked in deployment did not become active");
+        assert!(state_after(&block_index, &block_hashes[15], &times_out) == DeploymentState::Failed, "A failed deployment became active");
+        assert_eq!(next_block_version(block_index.get(&block_hashes[15]).unwrap(), &block_index, &params, &cache), VERSION_BITS_TOP_BITS);
+
+        // the states were cached for the last block of every window, and a new cache finds the same states
+        assert!(cache.get(&block_hashes[11], &locks_in) == Some(DeploymentState::LockedIn));
+        let new_cache: VersionBitsCache = VersionBitsCache::default();
+        assert!(deployment_state(block_index.get(&block_hashes[15]).unwrap(), &block_index, &params, &locks_in, &new_cache) == DeploymentState::Active);
+        assert!(deployment_state(block_index.get(&block_hashes[13]).unwrap(), &block_index, &params, &times_out, &new_cache) == DeploymentState::Failed);
+    }
+
+    #[test]
+    fn default_deployment_never_starts() {
+        let pub_key: &Key = &test_keypairs()[0].pub_key;
+        let chain: TestChain = TestChain::new(pub_key);
+        let txs: Vec<Tx> = vec![coinbase_tx(1, 0, pub_key.clone(), 100)];
+        let block: Block = chain.new_block(&txs, &chain.genesis_hash()).unwrap();
+        assert_eq!(block.block_header.version, VERSION_BITS_TOP_BITS);
+        assert!(chain.deployment_states().iter().all(|(_deployment_name, state)| *state == DeploymentState::Defined));
+    }
+}
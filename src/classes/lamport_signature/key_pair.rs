@@ -23,27 +23,58 @@ impl KeyPair {
         let mut priv_key_zero_blocks: [KeyBlock; 256] = initialize_empty_key_blocks();
         let mut priv_key_one_blocks: [KeyBlock; 256] = initialize_empty_key_blocks();
 
-        let mut pub_key_zero_blocks: [KeyBlock; 256] = initialize_empty_key_blocks();
-        let mut pub_key_one_blocks: [KeyBlock; 256] = initialize_empty_key_blocks();
-
         for i in 0..256 {
-            /* generate block with random u128 values for privKey zero part, and hash them to create a zero block for the public key */
+            /* generate blocks with random u128 values for the zero and one parts of the private key */
             priv_key_zero_blocks[i] = KeyBlock {
                 first_part: rng.gen(),
                 second_part: rng.gen()
             };  
 
-            pub_key_zero_blocks[i] = priv_key_zero_blocks[i].hash_priv_key_block();
-
-            /* repeat the same process as above but for the one part */
             priv_key_one_blocks[i] = KeyBlock {
                 first_part: rng.gen(),
                 second_part: rng.gen()
             };
-
-            pub_key_one_blocks[i] = priv_key_one_blocks[i].hash_priv_key_block();
         }
 
+        KeyPair::from_priv_key_blocks(priv_key_zero_blocks, priv_key_one_blocks)
+    }
+
+    /*
+        Derives the key pair at the path account_index/key_index from a master seed, so that every key of a wallet can be recovered from the seed alone.
+        The key seed is SHA256("lamport-key" || master seed || account index || key index), and each private key block is SHA256(key seed || part || block index),
+        where part is 0 for the zero blocks and 1 for the one blocks. Knowing one key (or any number of them) tells nothing about the master seed or the other keys.
+    */
+    pub fn from_seed(master_seed: &[u8; 32], account_index: u32, key_index: u32) -> Self {
+        let mut key_seed_hasher = Sha256::new();
+        key_seed_hasher.update(b"lamport-key");
+        key_seed_hasher.update(master_seed);
+        key_seed_hasher.update(account_index.to_be_bytes());
+        key_seed_hasher.update(key_index.to_be_bytes());
+        let key_seed = key_seed_hasher.finalize();
+
+        let derive_block = |part: u8, block_index: usize| -> KeyBlock {
+            let mut hasher = Sha256::new();
+            hasher.update(key_seed);
+            hasher.update([part]);
+            hasher.update((block_index as u16).to_be_bytes());
+            let block_bytes = hasher.finalize();
+            KeyBlock {
+                first_part: u128::from_be_bytes(block_bytes[0..16].try_into().expect("slice with incorrect length")),
+                second_part: u128::from_be_bytes(block_bytes[16..32].try_into().expect("slice with incorrect length")),
+            }
+        };
+
+        let priv_key_zero_blocks: [KeyBlock; 256] = array::from_fn(|i| derive_block(0, i));
+        let priv_key_one_blocks: [KeyBlock; 256] = array::from_fn(|i| derive_block(1, i));
+
+        KeyPair::from_priv_key_blocks(priv_key_zero_blocks, priv_key_one_blocks)
+    }
+
+    // hashes each private key block to create the matching public key block
+    fn from_priv_key_blocks(priv_key_zero_blocks: [KeyBlock; 256], priv_key_one_blocks: [KeyBlock; 256]) -> Self {
+        let pub_key_zero_blocks: [KeyBlock; 256] = array::from_fn(|i| priv_key_zero_blocks[i].hash_priv_key_block());
+        let pub_key_one_blocks: [KeyBlock; 256] = array::from_fn(|i| priv_key_one_blocks[i].hash_priv_key_block());
+
         return KeyPair {
             priv_key: Key {
                 zero_blocks: priv_key_zero_blocks,
@@ -56,7 +87,6 @@ impl KeyPair {
                 is_private: false
             }
         };
-    
     }
 
     pub fn create_signature(&self, tx: &Tx) -> [KeyBlock; 256]{
@@ -119,6 +149,37 @@ impl Key {
         let hex_string: String = hex::encode(result);
         hex_string
     }
+
+    // whether the signature was made by the private key of this public key over the given transaction hash
+    pub fn verify_signature(&self, tx_hash: &str, signature: &[KeyBlock; 256]) -> bool {
+        let tx_hash_bits: Vec<u8> = hex_string_to_bit_vector(tx_hash.to_string());
+        let mut verified: bool = true;
+
+        for (j, bit) in tx_hash_bits.iter().enumerate() {
+            if *bit == 0 {
+                // get the corresponding public key block from zero blocks
+                let pub_key_block: KeyBlock = self.zero_blocks[j];
+                // construct it yourself using the signature blocks that are supposed to be chosen from the private key blocks
+                let constructed_pub_key_block: KeyBlock = signature[j].hash_priv_key_block();
+
+                // if they don't match, set verified to false
+                if pub_key_block.first_part != constructed_pub_key_block.first_part || pub_key_block.second_part != constructed_pub_key_block.second_part {
+                    verified = false;
+                }
+            } else {
+                // repeat the same process as above but for one blocks
+                let pub_key_block: KeyBlock = self.one_blocks[j];
+                let constructed_pub_key_block: KeyBlock = signature[j].hash_priv_key_block();
+
+
+                if pub_key_block.first_part != constructed_pub_key_block.first_part || pub_key_block.second_part != constructed_pub_key_block.second_part {
+                    verified = false;
+                }
+            }
+        }
+
+        verified
+    }
 }
 
 /* Each key block, in order to meet the 256 bits length requirement, must be two u128 integers stuck together, rather than a simple primitive type. */
@@ -158,4 +219,26 @@ pub fn initialize_empty_key_blocks() -> [KeyBlock; 256] {
         first_part: 0,
         second_part: 0,
     });
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::classes::wallet::seed::generate_seed;
+
+    use super::{KeyBlock, KeyPair};
+
+    #[test]
+    fn keys_derived_from_seed() {
+        // every key is derived from the seed and its path alone
+        let seed: [u8; 32] = generate_seed();
+        let keypair: KeyPair = KeyPair::from_seed(&seed, 0, 0);
+        assert_eq!(keypair.pub_key.hash_key(), KeyPair::from_seed(&seed, 0, 0).pub_key.hash_key(), "The same path derived two different keys");
+        assert!(keypair.pub_key.hash_key() != KeyPair::from_seed(&seed, 0, 1).pub_key.hash_key());
+        assert!(keypair.pub_key.hash_key() != KeyPair::from_seed(&seed, 1, 0).pub_key.hash_key());
+        assert!(keypair.pub_key.hash_key() != KeyPair::from_seed(&[0; 32], 0, 0).pub_key.hash_key());
+        for block_index in 0..256 {
+            let hashed_block: KeyBlock = keypair.priv_key.zero_blocks[block_index].hash_priv_key_block();
+            assert!(hashed_block.first_part == keypair.pub_key.zero_blocks[block_index].first_part && hashed_block.second_part == keypair.pub_key.zero_blocks[block_index].second_part);
+        }
+    }
+}
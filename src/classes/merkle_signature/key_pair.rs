@@ -0,0 +1,455 @@
+use std::{array, fmt};
+
+use sha2::{Digest, Sha256};
+
+use crate::Tx;
+use crate::classes::block::blockchain::Blockchain;
+use crate::classes::lamport_signature::key_pair::{Key, KeyBlock, KeyPair};
+use crate::classes::signature::scheme::{PublicKey, Signature};
+use crate::classes::transaction::tx::{TxInput, TxOutput};
+use crate::util::conversions::hex_string_to_bit_vector;
+use crate::util::disk::save_merkle_leaf_index_to_file;
+use bincode;
+use serde::{Serialize, Deserialize};
+use serde_big_array::big_array;
+
+big_array! { BigArray; 256 }
+
+// a tree of this height has 65536 leaves, which already takes a while to generate since every leaf is a Lamport key pair
+pub const MAX_MERKLE_TREE_HEIGHT: u8 = 16;
+// the height of the Merkle key of the node, which can sign 1024 times
+pub const NODE_MERKLE_TREE_HEIGHT: u8 = 10;
+
+#[derive(Debug)]
+pub enum MerkleKeyError {
+    // every leaf of the tree has already signed
+    Exhausted { leaf_count: u64 },
+    // the used leaf could not be recorded on disk, so no signature was made with it
+    StateNotSaved,
+    InsufficientFunds { spendable: u64, amount: u64 },
+}
+
+impl fmt::Display for MerkleKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MerkleKeyError::Exhausted { leaf_count } => write!(f, "All {} one-time keys of the Merkle key have already signed", leaf_count),
+            MerkleKeyError::StateNotSaved => write!(f, "The state of the Merkle key could not be saved, so nothing was signed"),
+            MerkleKeyError::InsufficientFunds { spendable, amount } => write!(f, "Not enough spendable money on the Merkle key ({} can be spent, {} is needed)", spendable, amount),
+        }
+    }
+}
+
+/*
+    A Merkle signature key pair (XMSS-style): 2^h Lamport one-time key pairs, all derived from one secret tree seed, are the leaves of a Merkle tree of height h,
+    and the public key is only the root of the tree. Each signature is made by the next unused leaf, and carries what the verifier needs to get back to the root:
+    the index of the leaf, the Lamport signature, the public key blocks of the leaf that the signature does not reveal, and the authentication path (the sibling
+    of every node on the way from the leaf to the root). So one public key can receive and sign 2^h times, and the index of the next leaf is the state that must
+    never go backwards: it is saved to disk before every signature.
+
+    The wallet does not hold Merkle key pairs: they are signers for accounts that keep one reusable address, in their own files (see save_merkle_keypair_to_file).
+    The node derives one from the master seed of its wallet, which the accounts of the wallet can pay and which can pay them back.
+*/
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MerkleKeyPair {
+    tree_seed: [u8; 32],
+    // the nodes of the tree: the root at index 1, the children of node i at 2i and 2i + 1, and the leaves at 2^h to 2^(h + 1) - 1
+    nodes: Vec<[u8; 32]>,
+    // saved in its own file, so that a signature does not rewrite the whole tree
+    #[serde(skip)]
+    next_leaf_index: u32,
+    pub pub_key: MerkleKey,
+}
+
+impl MerkleKeyPair {
+    // derives the key pair at the path account_index/key_index from a master seed, with a different domain than the Lamport and Winternitz keys of the same path
+    pub fn from_seed(master_seed: &[u8; 32], account_index: u32, key_index: u32, height: u8) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(b"merkle-key");
+        hasher.update(master_seed);
+        hasher.update(account_index.to_be_bytes());
+        hasher.update(key_index.to_be_bytes());
+        let tree_seed: [u8; 32] = hasher.finalize().into();
+        MerkleKeyPair::from_tree_seed(tree_seed, height)
+    }
+
+    fn from_tree_seed(tree_seed: [u8; 32], height: u8) -> Self {
+        assert!(height <= MAX_MERKLE_TREE_HEIGHT, "Merkle trees can be at most {} high", MAX_MERKLE_TREE_HEIGHT);
+
+        let leaf_count: usize = 1 << height;
+        let mut nodes: Vec<[u8; 32]> = vec![[0; 32]; 2 * leaf_count];
+        for leaf_index in 0..leaf_count {
+            nodes[leaf_count + leaf_index] = hash_leaf(&leaf_keypair(&tree_seed, leaf_index as u32).pub_key);
+        }
+        for node_index in (1..leaf_count).rev() {
+            nodes[node_index] = hash_node(&nodes[2 * node_index], &nodes[2 * node_index + 1]);
+        }
+
+        let pub_key: MerkleKey = MerkleKey {
+            height,
+            root: nodes[1],
+        };
+
+        MerkleKeyPair {
+            tree_seed,
+            nodes,
+            next_leaf_index: 0,
+            pub_key,
+        }
+    }
+
+    pub fn leaf_count(&self) -> u64 {
+        1 << self.pub_key.height
+    }
+
+    pub fn next_leaf_index(&self) -> u32 {
+        self.next_leaf_index
+    }
+
+    pub fn remaining_signatures(&self) -> u64 {
+        self.leaf_count() - self.next_leaf_index as u64
+    }
+
+    // the saved state can only move the next leaf forward
+    pub fn restore_next_leaf_index(&mut self, next_leaf_index: u32) {
+        self.next_leaf_index = self.next_leaf_index.max(next_leaf_index);
+    }
+
+    /* Signs the transaction with the next unused leaf. The leaf is marked as used and the new state is saved next to the key pair file before the signature
+    is made, so a crash can only ever skip a leaf, never let one sign twice. If the state cannot be saved, nothing is signed (and the leaf stays skipped). */
+    pub fn create_signature(&mut self, tx: &Tx, keypair_filename: &str) -> Result<MerkleSignature, MerkleKeyError> {
+        if self.remaining_signatures() == 0 {
+            return Err(MerkleKeyError::Exhausted { leaf_count: self.leaf_count() });
+        }
+
+        let leaf_index: u32 = self.next_leaf_index;
+        self.next_leaf_index += 1;
+        if save_merkle_leaf_index_to_file(self.next_leaf_index, keypair_filename).is_err() {
+            return Err(MerkleKeyError::StateNotSaved);
+        }
+
+        Ok(self.sign_with_leaf(leaf_index, tx))
+    }
+
+    /* Builds and signs a transaction paying the amount to the recipient from the outputs paid to this key. Unlike a one-time key, a Merkle key can sign again,
+    so only as many of its spendable outputs (mature and not spent in the mempool) as the amount needs are spent, and the change goes back to the key itself.
+    Every input signs the same message, so the whole transaction only uses up one leaf. */
+    pub fn create_transaction(&mut self, recipient: PublicKey, amount: u64, blockchain: &Blockchain, keypair_filename: &str) -> Result<Tx, MerkleKeyError> {
+        let pub_key: PublicKey = self.pub_key.clone().into();
+        let pub_key_hash: String = pub_key.hash_key();
+        let next_block_height: u64 = blockchain.next_block_height();
+
+        let mut tx_inputs: Vec<TxInput> = vec![];
+        let mut input_amount: u64 = 0;
+        for (outpoint, utxo_entry) in blockchain.utxo.iter() {
+            if input_amount >= amount {
+                break;
+            }
+
+            let is_spendable: bool = utxo_entry.is_mature(next_block_height, blockchain.params.coinbase_maturity) && !blockchain.mempool.is_spent(outpoint);
+            if utxo_entry.output.pub_key.hash_key() == pub_key_hash && is_spendable {
+                tx_inputs.push(TxInput::new(Signature::unsigned(pub_key.scheme()), outpoint.tx_id.clone(), false, outpoint.index));
+                input_amount += utxo_entry.output.amount;
+            }
+        }
+
+        if input_amount < amount {
+            return Err(MerkleKeyError::InsufficientFunds { spendable: input_amount, amount });
+        }
+
+        let mut tx_outputs: Vec<TxOutput> = vec![TxOutput::new(recipient, amount)];
+        if input_amount > amount {
+            tx_outputs.push(TxOutput::new(pub_key, input_amount - amount));
+        }
+
+        let mut transaction: Tx = Tx::new(tx_inputs, tx_outputs);
+        let signature: Signature = self.create_signature(&transaction, keypair_filename)?.into();
+        for tx_input in transaction.inputs.iter_mut() {
+            tx_input.signature = signature.clone();
+        }
+
+        Ok(transaction)
+    }
+
+    /* Moves the next leaf past every leaf the valid chain or the mempool has seen sign, in case the saved state went backwards
+    (e.g. the key pair was restored from an old backup). Returns how many leaves were skipped. */
+    pub fn skip_signed_leaves(&mut self, blockchain: &Blockchain) -> u32 {
+        let mut skipped_leaf_count: u32 = 0;
+        let mut leaf_index: u32 = self.next_leaf_index;
+        while (leaf_index as u64) < self.leaf_count() {
+            let leaf_key_hash: String = self.pub_key.leaf_key_hash(leaf_index);
+            if blockchain.utxo.signed_message(&leaf_key_hash).is_some() || blockchain.mempool.signed_message(&leaf_key_hash).is_some() {
+                skipped_leaf_count += leaf_index + 1 - self.next_leaf_index;
+                self.next_leaf_index = leaf_index + 1;
+            }
+            leaf_index += 1;
+        }
+
+        skipped_leaf_count
+    }
+
+    fn sign_with_leaf(&self, leaf_index: u32, tx: &Tx) -> MerkleSignature {
+        let keypair: KeyPair = leaf_keypair(&self.tree_seed, leaf_index);
+        let signature_blocks: [KeyBlock; 256] = keypair.create_signature(tx);
+
+        // the public key blocks of the bits that were not signed, which the verifier cannot get from the signature
+        let msg_hash_bits: Vec<u8> = hex_string_to_bit_vector(tx.get_tx_hash());
+        let other_pub_key_blocks: [KeyBlock; 256] = array::from_fn(|i| {
+            if msg_hash_bits[i] == 0 { keypair.pub_key.one_blocks[i] } else { keypair.pub_key.zero_blocks[i] }
+        });
+
+        let mut auth_path: Vec<[u8; 32]> = vec![];
+        let mut node_index: usize = self.leaf_count() as usize + leaf_index as usize;
+        while node_index > 1 {
+            auth_path.push(self.nodes[node_index ^ 1]);
+            node_index /= 2;
+        }
+
+        MerkleSignature {
+            leaf_index,
+            signature_blocks,
+            other_pub_key_blocks,
+            auth_path,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MerkleKey {
+    pub height: u8,
+    pub root: [u8; 32],
+}
+
+impl MerkleKey {
+    pub fn hash_key(&self) -> String {
+        let bytes: Vec<u8> = match bincode::serialize(self) {
+            Ok(val) => val,
+            Err(_e) => {
+                println!("Error! Could not convert key to bytes");
+                vec![]
+            }
+        };
+
+        let mut hasher = Sha256::new();
+        hasher.update(&bytes);
+        let result = hasher.finalize();
+        let hex_string: String = hex::encode(result);
+        hex_string
+    }
+
+    // what stands for the one-time key of a leaf when key reuse is tracked, since the Merkle key itself signs many times
+    pub fn leaf_key_hash(&self, leaf_index: u32) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.hash_key().as_bytes());
+        hasher.update(leaf_index.to_be_bytes());
+        hex::encode(hasher.finalize())
+    }
+
+    /* Whether the signature was made by a leaf of this key over the given transaction hash: the Lamport public key of the leaf is rebuilt from the signature
+    (hashing the revealed private blocks) and the unsigned public blocks, and it has to hash up to the root along the authentication path. */
+    pub fn verify(&self, tx_hash: &str, signature: &MerkleSignature) -> bool {
+        if self.height > MAX_MERKLE_TREE_HEIGHT || signature.auth_path.len() != self.height as usize || (signature.leaf_index as u64) >= (1 << self.height) {
+            return false;
+        }
+
+        let msg_hash_bits: Vec<u8> = hex_string_to_bit_vector(tx_hash.to_string());
+        let signed_pub_key_blocks: [KeyBlock; 256] = array::from_fn(|i| signature.signature_blocks[i].hash_priv_key_block());
+        let leaf_pub_key: Key = Key {
+            zero_blocks: array::from_fn(|i| if msg_hash_bits[i] == 0 { signed_pub_key_blocks[i] } else { signature.other_pub_key_blocks[i] }),
+            one_blocks: array::from_fn(|i| if msg_hash_bits[i] == 0 { signature.other_pub_key_blocks[i] } else { signed_pub_key_blocks[i] }),
+            is_private: false,
+        };
+
+        let mut node: [u8; 32] = hash_leaf(&leaf_pub_key);
+        let mut node_index: u32 = signature.leaf_index;
+        for sibling in &signature.auth_path {
+            node = if node_index.is_multiple_of(2) { hash_node(&node, sibling) } else { hash_node(sibling, &node) };
+            node_index /= 2;
+        }
+
+        node == self.root
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MerkleSignature {
+    pub leaf_index: u32,
+    #[serde(with = "BigArray")]
+    pub signature_blocks: [KeyBlock; 256],
+    #[serde(with = "BigArray")]
+    pub other_pub_key_blocks: [KeyBlock; 256],
+    // the sibling of every node from the leaf up to the root, leaf level first
+    pub auth_path: Vec<[u8; 32]>,
+}
+
+// the leaves are derived like the keys of a wallet, with the tree seed as the master seed
+fn leaf_keypair(tree_seed: &[u8; 32], leaf_index: u32) -> KeyPair {
+    KeyPair::from_seed(tree_seed, 0, leaf_index)
+}
+
+// leaves and inner nodes are hashed with a different prefix, so that an inner node can never pass for a leaf
+fn hash_leaf(pub_key: &Key) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([0]);
+    hasher.update(hex::decode(pub_key.hash_key()).expect("Invalid hex string..."));
+    hasher.finalize().into()
+}
+
+fn hash_node(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([1]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::classes::lamport_signature::key_pair::KeyPair;
+    use crate::classes::signature::scheme::PublicKey;
+    use crate::classes::transaction::mempool::MempoolError;
+    use crate::classes::transaction::tx::Tx;
+    use crate::util::disk::{load_merkle_keypair_from_file, save_merkle_keypair_to_file};
+    use crate::util::test_util::{coinbase_tx, spend_tx, test_keypairs, unsigned_spend_tx, TempDir, TestChain};
+
+    use super::{MerkleKeyError, MerkleKeyPair, MerkleSignature};
+
+    #[test]
+    fn sign_and_verify() {
+        let keypairs: &[KeyPair] = test_keypairs();
+        let temp_dir: TempDir = TempDir::new();
+        let keypair_filename: String = temp_dir.file("testmerklekey.bin");
+        let message_tx: Tx = coinbase_tx(1, 0, keypairs[0].pub_key.clone(), 100);
+        let other_message_tx: Tx = coinbase_tx(1, 0, keypairs[0].pub_key.clone(), 99);
+
+        // one public key signs once per leaf, each time with another leaf
+        let mut merkle_keypair: MerkleKeyPair = MerkleKeyPair::from_seed(&[1; 32], 0, 0, 3);
+        assert!(merkle_keypair.leaf_count() == 8 && merkle_keypair.remaining_signatures() == 8);
+        assert!(save_merkle_keypair_to_file(&merkle_keypair, &keypair_filename).is_ok());
+        let saved_tree: Vec<u8> = std::fs::read(&keypair_filename).unwrap();
+        let signature: MerkleSignature = merkle_keypair.create_signature(&message_tx, &keypair_filename).expect("Could not sign with a Merkle key");
+        assert!(merkle_keypair.pub_key.verify(&message_tx.get_tx_hash(), &signature), "A valid Merkle signature was rejected");
+        assert!(!merkle_keypair.pub_key.verify(&other_message_tx.get_tx_hash(), &signature), "A Merkle signature verified another message");
+        assert!(!MerkleKeyPair::from_seed(&[1; 32], 0, 1, 3).pub_key.verify(&message_tx.get_tx_hash(), &signature), "A Merkle signature verified with another key");
+        let second_signature: MerkleSignature = merkle_keypair.create_signature(&other_message_tx, &keypair_filename).unwrap();
+        assert!(signature.leaf_index == 0 && second_signature.leaf_index == 1 && second_signature.auth_path.len() == 3);
+        assert!(merkle_keypair.pub_key.verify(&other_message_tx.get_tx_hash(), &second_signature));
+
+        // a signature with a wrong leaf index, a wrong authentication path or a tampered block does not verify
+        let mut tampered_signature: MerkleSignature = signature.clone();
+        tampered_signature.leaf_index = 1;
+        assert!(!merkle_keypair.pub_key.verify(&message_tx.get_tx_hash(), &tampered_signature));
+        let mut tampered_signature: MerkleSignature = signature.clone();
+        tampered_signature.auth_path[2][0] ^= 1;
+        assert!(!merkle_keypair.pub_key.verify(&message_tx.get_tx_hash(), &tampered_signature));
+        let mut tampered_signature: MerkleSignature = signature.clone();
+        tampered_signature.auth_path.pop();
+        assert!(!merkle_keypair.pub_key.verify(&message_tx.get_tx_hash(), &tampered_signature));
+        let mut tampered_signature: MerkleSignature = signature.clone();
+        tampered_signature.signature_blocks[5].first_part ^= 1;
+        assert!(!merkle_keypair.pub_key.verify(&message_tx.get_tx_hash(), &tampered_signature));
+
+        // the used leaves are on disk before the signature is returned
+        let loaded_keypair: MerkleKeyPair = load_merkle_keypair_from_file(&keypair_filename).expect("Could not load the saved Merkle key pair");
+        assert!(loaded_keypair.next_leaf_index() == 2 && loaded_keypair.pub_key.root == merkle_keypair.pub_key.root, "The used leaves were not saved");
+        assert!(std::fs::read(&keypair_filename).unwrap() == saved_tree, "A signature rewrote the Merkle tree");
+        assert!(std::fs::remove_file(format!("{}.leaf", keypair_filename)).is_ok() && load_merkle_keypair_from_file(&keypair_filename).is_err(), "A Merkle key pair was loaded without its state");
+
+        // nothing is signed if the state cannot be saved, and the key stops signing once every leaf is used
+        assert!(matches!(merkle_keypair.create_signature(&message_tx, &temp_dir.file("missing_directory/testmerklekey.bin")), Err(MerkleKeyError::StateNotSaved)));
+        assert_eq!(merkle_keypair.next_leaf_index(), 3, "A leaf was reused after its state could not be saved");
+        while merkle_keypair.remaining_signatures() > 0 {
+            assert!(merkle_keypair.create_signature(&message_tx, &keypair_filename).is_ok());
+        }
+        assert!(matches!(merkle_keypair.create_signature(&message_tx, &keypair_filename), Err(MerkleKeyError::Exhausted { leaf_count: 8 })));
+
+        // keys derived from a seed are the same every time
+        let seed: [u8; 32] = [7; 32];
+        assert!(MerkleKeyPair::from_seed(&seed, 0, 0, 2).pub_key.root == MerkleKeyPair::from_seed(&seed, 0, 0, 2).pub_key.root);
+        assert!(MerkleKeyPair::from_seed(&seed, 0, 0, 2).pub_key.root != MerkleKeyPair::from_seed(&seed, 0, 1, 2).pub_key.root);
+    }
+
+    #[test]
+    fn spend_outputs_of_one_merkle_key() {
+        let keypairs: &[KeyPair] = test_keypairs();
+        let mut chain: TestChain = TestChain::with_params(&keypairs[0].pub_key, |params| {
+            params.coinbase_maturity = 0;
+        });
+        let keypair_filename: String = chain.temp_dir.file("testmerklekey.bin");
+        let genesis_hash: String = chain.genesis_hash();
+        let genesis_coinbase_id: String = chain.blocks[0].txs.base[0].get_tx_id();
+
+        // the same Merkle public key is paid three times
+        let mut merkle_keypair: MerkleKeyPair = MerkleKeyPair::from_seed(&[2; 32], 0, 0, 2);
+        let merkle_pub_key: PublicKey = merkle_keypair.pub_key.clone().into();
+        let lamport_tx: Tx = spend_tx(&[(genesis_coinbase_id, 0)], &keypairs[0], merkle_pub_key.clone(), &[30, 30, 30]);
+        assert!(bincode::serialize(&merkle_pub_key).unwrap().len() < 64);
+        let block_1_hash: String = chain.mine(vec![coinbase_tx(1, 0, keypairs[0].pub_key.clone(), 100), lamport_tx.clone()], &genesis_hash).block_header.hash_block().unwrap();
+        assert_eq!(chain.blocks.len(), 2);
+
+        // a backup of the key pair made before it signed anything
+        let mut old_keypair: MerkleKeyPair = merkle_keypair.clone();
+
+        // the amounts differ, so that every spend signs a different message
+        let lamport_tx_id: String = lamport_tx.get_tx_id();
+        let mut merkle_txs: Vec<Tx> = vec![];
+        for output_index in 0..2 {
+            let mut tx: Tx = unsigned_spend_tx(&[(lamport_tx_id.clone(), output_index)], merkle_pub_key.scheme(), keypairs[1].pub_key.clone(), &[30 - output_index as u64]);
+            tx.inputs[0].signature = merkle_keypair.create_signature(&tx, &keypair_filename).unwrap().into();
+            assert!(chain.add_to_mempool(&tx).is_ok(), "The second signature of a Merkle key was rejected as key reuse");
+            merkle_txs.push(tx);
+        }
+
+        // a signature for one output of the key does not spend another output paid to the same key
+        let mut redirected_tx: Tx = merkle_txs[0].clone();
+        redirected_tx.inputs[0].index = 2;
+        assert!(!redirected_tx.verify_signature(&chain.utxo), "A Merkle signature spent another output of the same key");
+
+        // an output paid to a Merkle key cannot be spent with a Lamport signature
+        let mut lamport_spend_tx: Tx = unsigned_spend_tx(&[(lamport_tx_id.clone(), 2)], merkle_pub_key.scheme(), keypairs[1].pub_key.clone(), &[28]);
+        lamport_spend_tx.inputs[0].signature = keypairs[0].create_signature(&lamport_spend_tx).into();
+        assert!(!lamport_spend_tx.verify_transaction(&chain.utxo));
+
+        chain.mine(vec![coinbase_tx(2, 0, keypairs[0].pub_key.clone(), 100), merkle_txs[0].clone(), merkle_txs[1].clone()], &block_1_hash);
+        assert_eq!(chain.blocks.len(), 3, "A block with two signatures of the same Merkle key was rejected");
+
+        // the old backup would sign with a leaf that has already signed, which is caught as key reuse, until it skips the leaves the chain has seen sign
+        let mut reused_leaf_tx: Tx = unsigned_spend_tx(&[(lamport_tx_id.clone(), 2)], merkle_pub_key.scheme(), keypairs[1].pub_key.clone(), &[28]);
+        reused_leaf_tx.inputs[0].signature = old_keypair.clone().create_signature(&reused_leaf_tx, &keypair_filename).unwrap().into();
+        assert!(matches!(chain.add_to_mempool(&reused_leaf_tx), Err(MempoolError::KeyReuse { .. })), "A reused Merkle leaf was accepted");
+        assert_eq!(old_keypair.skip_signed_leaves(&chain), 2);
+        let mut restored_tx: Tx = unsigned_spend_tx(&[(lamport_tx_id.clone(), 2)], merkle_pub_key.scheme(), keypairs[1].pub_key.clone(), &[28]);
+        restored_tx.inputs[0].signature = old_keypair.create_signature(&restored_tx, &keypair_filename).unwrap().into();
+        assert!(chain.add_to_mempool(&restored_tx).is_ok());
+    }
+
+    #[test]
+    fn create_transaction_returns_change_to_the_key() {
+        let keypairs: &[KeyPair] = test_keypairs();
+        let mut chain: TestChain = TestChain::with_params(&keypairs[0].pub_key, |params| {
+            params.coinbase_maturity = 0;
+        });
+        let keypair_filename: String = chain.temp_dir.file("testmerklekey.bin");
+        let genesis_hash: String = chain.genesis_hash();
+        let genesis_coinbase_id: String = chain.blocks[0].txs.base[0].get_tx_id();
+
+        // the Merkle key is paid twice
+        let mut merkle_keypair: MerkleKeyPair = MerkleKeyPair::from_seed(&[3; 32], 0, 0, 2);
+        let merkle_pub_key: PublicKey = merkle_keypair.pub_key.clone().into();
+        let lamport_tx: Tx = spend_tx(&[(genesis_coinbase_id, 0)], &keypairs[0], merkle_pub_key.clone(), &[60, 40]);
+        let block_1_hash: String = chain.mine(vec![coinbase_tx(1, 0, keypairs[0].pub_key.clone(), 100), lamport_tx], &genesis_hash).block_header.hash_block().unwrap();
+
+        // no leaf is used up for a transaction that cannot be paid
+        assert!(matches!(merkle_keypair.create_transaction(keypairs[1].pub_key.clone().into(), 101, &chain, &keypair_filename), Err(MerkleKeyError::InsufficientFunds { spendable: 100, amount: 101 })));
+        assert_eq!(merkle_keypair.next_leaf_index(), 0);
+
+        // both outputs are spent with one leaf, and the change goes back to the Merkle key, which spends it with its next leaf
+        let first_tx: Tx = merkle_keypair.create_transaction(keypairs[1].pub_key.clone().into(), 70, &chain, &keypair_filename).unwrap();
+        assert!(first_tx.inputs.len() == 2 && first_tx.outputs[1].pub_key.hash_key() == merkle_pub_key.hash_key() && first_tx.outputs[1].amount == 30);
+        assert!(chain.add_to_mempool(&first_tx).is_ok(), "A transaction of a Merkle key was rejected");
+        chain.mine(vec![coinbase_tx(2, 0, keypairs[0].pub_key.clone(), 100), first_tx], &block_1_hash);
+        let second_tx: Tx = merkle_keypair.create_transaction(keypairs[1].pub_key.clone().into(), 30, &chain, &keypair_filename).unwrap();
+        assert_eq!(second_tx.outputs.len(), 1);
+        assert!(chain.add_to_mempool(&second_tx).is_ok(), "The change of a Merkle key could not be spent");
+        assert_eq!(merkle_keypair.next_leaf_index(), 2);
+    }
+}
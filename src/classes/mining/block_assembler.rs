@@ -0,0 +1,146 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::classes::block::block::Block;
+use crate::classes::block::blockchain::Blockchain;
+use crate::classes::consensus::subsidy::block_subsidy;
+use crate::classes::signature::scheme::PublicKey;
+use crate::classes::transaction::mempool::MempoolEntry;
+use crate::classes::transaction::tx::{Tx, TxInput, TxOutput};
+
+// a block that is ready to be mined (only its nonce and extra nonce still have to be found), along with what it was built from
+pub struct BlockTemplate {
+    pub block: Block,
+    pub height: u64,
+    pub total_fees: u64,
+}
+
+/*
+    Builds the next block of the valid chain out of the transactions in the mempool. Transactions are picked from the highest fee rate to the lowest,
+    as long as they fit in the maximum block size, and a transaction that spends the output of another unconfirmed transaction is only picked once that
+    transaction has been picked (so it always comes after it in the block). The coinbase transaction pays the block subsidy plus the fees of the picked
+    transactions to the payout key.
+*/
+pub struct BlockAssembler {
+    payout_key: PublicKey,
+}
+
+impl BlockAssembler {
+    pub fn new(payout_key: PublicKey) -> BlockAssembler {
+        BlockAssembler {
+            payout_key,
+        }
+    }
+
+    pub fn create_template(&self, blockchain: &Blockchain) -> Option<BlockTemplate> {
+        let tip_hash: String = blockchain.blocks.last()?.block_header.hash_block()?;
+        let height: u64 = blockchain.blocks.len() as u64;
+
+        // the coinbase amount does not change the size of the coinbase transaction, so the space it takes can be reserved before picking the other transactions
+        let mut coinbase_tx: Tx = self.coinbase_tx(height, block_subsidy(height, &blockchain.params));
+        let mut remaining_size: usize = blockchain.params.max_block_size.checked_sub(coinbase_tx.convert_tx_to_bytes().len())?;
+
+        let mut picked_txs: Vec<Tx> = vec![];
+        let mut picked_tx_ids: HashSet<String> = HashSet::new();
+        let mut total_fees: u64 = 0;
+        // transactions waiting for one of their unconfirmed parents to be picked, keyed by the id of that parent
+        let mut waiting_txs: HashMap<String, Vec<&MempoolEntry>> = HashMap::new();
+
+        for mempool_entry in blockchain.mempool.entries_by_fee_rate() {
+            let mut pickable_entries: Vec<&MempoolEntry> = vec![mempool_entry];
+
+            while let Some(entry) = pickable_entries.pop() {
+                let missing_parent_id: Option<String> = entry.tx.inputs.iter()
+                    .map(|tx_input| tx_input.prev_tx_id.clone())
+                    .find(|prev_tx_id| blockchain.mempool.contains(prev_tx_id) && !picked_tx_ids.contains(prev_tx_id));
+
+                if let Some(missing_parent_id) = missing_parent_id {
+                    waiting_txs.entry(missing_parent_id).or_default().push(entry);
+                    continue;
+                }
+
+                if entry.size > remaining_size {
+                    continue;
+                }
+
+                remaining_size -= entry.size;
+                total_fees += entry.fee;
+                picked_txs.push(entry.tx.clone());
+                picked_tx_ids.insert(entry.tx_id.clone());
+
+                // the transactions that were waiting for this one can now be picked, unless they are waiting for another parent as well
+                if let Some(children) = waiting_txs.remove(&entry.tx_id) {
+                    pickable_entries.extend(children.into_iter().rev());
+                }
+            }
+        }
+
+        coinbase_tx.outputs[0].amount = block_subsidy(height, &blockchain.params) + total_fees;
+
+        let mut block_txs: Vec<Tx> = vec![coinbase_tx];
+        block_txs.extend(picked_txs);
+        let block: Block = blockchain.new_block(&block_txs, &tip_hash)?;
+
+        Some(BlockTemplate {
+            block,
+            height,
+            total_fees,
+        })
+    }
+
+    // the coinbase transaction commits to the height of the block, which keeps the coinbase transactions of different blocks from having the same id
+    fn coinbase_tx(&self, height: u64, amount: u64) -> Tx {
+        Tx::new(vec![TxInput::new_coinbase(height, 0)], vec![TxOutput::new(self.payout_key.clone(), amount)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::classes::block::block::Block;
+    use crate::classes::lamport_signature::key_pair::KeyPair;
+    use crate::classes::transaction::tx::Tx;
+    use crate::util::test_util::{spend_tx, test_keypairs, TestChain};
+
+    use super::{BlockAssembler, BlockTemplate};
+
+    #[test]
+    fn block_assembler() {
+        let keypairs: &[KeyPair] = test_keypairs();
+        // coinbase outputs are spent right away in this test
+        let mut chain: TestChain = TestChain::with_params(&keypairs[0].pub_key, |params| params.coinbase_maturity = 0);
+        let genesis_coinbase: Tx = chain.blocks[0].txs.base[0].clone();
+
+        // the child transaction has a higher fee rate than its parent, but it can only come after it in the block
+        let parent_tx: Tx = spend_tx(&[(genesis_coinbase.get_tx_id(), 0)], &keypairs[0], keypairs[1].pub_key.clone(), &[90]);
+        let child_tx: Tx = spend_tx(&[(parent_tx.get_tx_id(), 0)], &keypairs[1], keypairs[0].pub_key.clone(), &[60]);
+        assert!(chain.add_to_mempool(&parent_tx).is_ok());
+        assert!(chain.add_to_mempool(&child_tx).is_ok());
+
+        let block_assembler: BlockAssembler = BlockAssembler::new(keypairs[1].pub_key.clone().into());
+        let block_template: BlockTemplate = block_assembler.create_template(&chain).unwrap();
+        let template_txs: &Vec<Tx> = &block_template.block.txs.base;
+        assert_eq!(block_template.height, 1);
+        assert_eq!(block_template.total_fees, 40);
+        assert_eq!(template_txs.len(), 3);
+        assert_eq!(template_txs[1].get_tx_id(), parent_tx.get_tx_id(), "A transaction was placed before the unconfirmed transaction it spends");
+        assert_eq!(template_txs[2].get_tx_id(), child_tx.get_tx_id());
+        assert_eq!(template_txs[0].outputs[0].amount, 140, "The coinbase does not claim the subsidy plus the fees");
+        assert_eq!(template_txs[0].outputs[0].pub_key.hash_key(), keypairs[1].pub_key.hash_key(), "The coinbase does not pay the payout key");
+
+        // with less space, only the parent fits (the child cannot be picked without it)
+        let full_size: usize = block_template.block.size();
+        chain.params.max_block_size = full_size - 1;
+        let small_template: BlockTemplate = block_assembler.create_template(&chain).unwrap();
+        assert_eq!(small_template.block.txs.base.len(), 2);
+        assert_eq!(small_template.block.txs.base[1].get_tx_id(), parent_tx.get_tx_id());
+        assert_eq!(small_template.total_fees, 10);
+        assert!(small_template.block.size() <= chain.params.max_block_size);
+
+        // the mined template is a valid block, and its transactions leave the mempool
+        chain.params.max_block_size = full_size;
+        let mut block: Block = block_template.block;
+        block.mine_block();
+        chain.accept(&block);
+        assert_eq!(chain.blocks.len(), 2, "The block built from the template was not accepted");
+        assert_eq!(chain.mempool.len(), 0);
+    }
+}
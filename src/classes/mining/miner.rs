@@ -0,0 +1,261 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::classes::block::block::Block;
+
+// how many nonces a worker tries between two checks of whether it should stop
+const HASHES_PER_CHECK: u64 = 1000;
+// how many header nonces are tried with each extra nonce of the coinbase transaction, before moving on to the next one
+pub const NONCE_RANGE: u128 = 1 << 32;
+// how often the hashrate is reported while mining
+const HASHRATE_REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+pub enum MiningResult {
+    Found(Block),
+    // the valid chain has a new tip, so the block being mined would only extend a stale tip
+    NewTip,
+    Stopped,
+    // another block is already being mined
+    Busy,
+}
+
+/*
+    Mines blocks on several worker threads at once: worker i tries the nonces i, i + n, i + 2n, ... (where n is the number of workers) of the nonce range,
+    so the workers never try the same nonce twice. Once the whole range has been tried, every worker moves on to the next extra nonce of the coinbase
+    transaction (which changes the merkle root) and tries the range again. The miner only works on a copy of the block, so the blockchain does not have to be locked while hashing.
+    Mining can be stopped at any time, and it is interrupted when the valid chain gets a new tip (e.g. a block from a peer), so that a new block can be built on top of it.
+*/
+pub struct Miner {
+    worker_count: usize,
+    nonce_range: u128,
+    is_mining: AtomicBool,
+    stop_requested: AtomicBool,
+    new_tip: AtomicBool,
+    // the hash of the block that the block being mined builds on top of
+    job_prev_hash: Mutex<Option<String>>,
+    hash_count: AtomicU64,
+    job_start: Mutex<Option<Instant>>,
+}
+
+impl Miner {
+    pub fn new(worker_count: usize) -> Miner {
+        Miner::with_nonce_range(worker_count, NONCE_RANGE)
+    }
+
+    pub fn with_nonce_range(worker_count: usize, nonce_range: u128) -> Miner {
+        Miner {
+            worker_count: worker_count.max(1),
+            nonce_range: nonce_range.max(1),
+            is_mining: AtomicBool::new(false),
+            stop_requested: AtomicBool::new(false),
+            new_tip: AtomicBool::new(false),
+            job_prev_hash: Mutex::new(None),
+            hash_count: AtomicU64::new(0),
+            job_start: Mutex::new(None),
+        }
+    }
+
+    // a miner with one worker for every CPU core
+    pub fn with_available_parallelism() -> Miner {
+        let worker_count: usize = thread::available_parallelism().map(|parallelism| parallelism.get()).unwrap_or(1);
+        Miner::new(worker_count)
+    }
+
+    pub fn is_mining(&self) -> bool {
+        self.is_mining.load(Ordering::SeqCst)
+    }
+
+    pub fn stop(&self) {
+        self.stop_requested.store(true, Ordering::SeqCst);
+    }
+
+    // interrupts the current job if the new tip of the valid chain is not the block it builds on top of
+    pub fn notify_new_tip(&self, tip_hash: &str) {
+        if let Some(job_prev_hash) = self.job_prev_hash.lock().unwrap().as_ref() {
+            if job_prev_hash != tip_hash {
+                self.new_tip.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+
+    // the number of hashes per second of the current (or last) job
+    pub fn hashrate(&self) -> f64 {
+        let elapsed_seconds: f64 = match *self.job_start.lock().unwrap() {
+            Some(job_start) => job_start.elapsed().as_secs_f64(),
+            None => return 0.0,
+        };
+
+        if elapsed_seconds == 0.0 {
+            return 0.0;
+        }
+
+        self.hash_count.load(Ordering::SeqCst) as f64 / elapsed_seconds
+    }
+
+    /* Blocks until one of the workers finds a nonce that meets the block's target, or until mining is stopped or interrupted.
+    The current tip of the valid chain is read once the job is set up: a tip that changed after the block was built but before then
+    was notified while there was no job to compare it with, so the block is not mined on top of a stale tip. */
+    pub fn mine(&self, block: &Block, current_tip_hash: impl FnOnce() -> Option<String>) -> MiningResult {
+        if self.is_mining.compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst).is_err() {
+            return MiningResult::Busy;
+        }
+
+        self.stop_requested.store(false, Ordering::SeqCst);
+        self.new_tip.store(false, Ordering::SeqCst);
+        self.hash_count.store(0, Ordering::SeqCst);
+        *self.job_prev_hash.lock().unwrap() = Some(block.block_header.prev_block_hash.clone());
+        *self.job_start.lock().unwrap() = Some(Instant::now());
+
+        if current_tip_hash().is_some_and(|tip_hash| tip_hash != block.block_header.prev_block_hash) {
+            self.new_tip.store(true, Ordering::SeqCst);
+        }
+
+        let solved: AtomicBool = AtomicBool::new(false);
+        let found_block: Mutex<Option<Block>> = Mutex::new(None);
+
+        thread::scope(|scope| {
+            let mut workers: Vec<thread::ScopedJoinHandle<()>> = vec![];
+            // no worker is started for a job that is already interrupted
+            let worker_count: usize = if self.new_tip.load(Ordering::SeqCst) { 0 } else { self.worker_count };
+            for worker_index in 0..worker_count {
+                let solved: &AtomicBool = &solved;
+                let found_block: &Mutex<Option<Block>> = &found_block;
+                workers.push(scope.spawn(move || self.work(block, worker_index, solved, found_block)));
+            }
+
+            let mut last_report: Instant = Instant::now();
+            while workers.iter().any(|worker| !worker.is_finished()) {
+                thread::sleep(Duration::from_millis(50));
+                if last_report.elapsed() >= HASHRATE_REPORT_INTERVAL {
+                    println!("Mining at {:.0} hashes per second with {} worker(s)...", self.hashrate(), self.worker_count);
+                    last_report = Instant::now();
+                }
+            }
+        });
+
+        println!("Tried {} nonces at {:.0} hashes per second...", self.hash_count.load(Ordering::SeqCst), self.hashrate());
+        *self.job_prev_hash.lock().unwrap() = None;
+        self.is_mining.store(false, Ordering::SeqCst);
+
+        match found_block.into_inner().unwrap() {
+            Some(mined_block) => MiningResult::Found(mined_block),
+            None if self.new_tip.load(Ordering::SeqCst) => MiningResult::NewTip,
+            None => MiningResult::Stopped,
+        }
+    }
+
+    fn work(&self, block: &Block, worker_index: usize, solved: &AtomicBool, found_block: &Mutex<Option<Block>>) {
+        let mut mined_block: Block = block.clone();
+        let nonce_step: u128 = self.worker_count as u128;
+        let start_nonce: u128 = block.block_header.nonce;
+        // how far the nonce being tried is from the start of the nonce range
+        let mut nonce_offset: u128 = worker_index as u128;
+        let mut extra_nonce: u64 = block.extra_nonce().unwrap_or(0);
+        mined_block.block_header.nonce = start_nonce.wrapping_add(nonce_offset);
+
+        loop {
+            for _ in 0..HASHES_PER_CHECK {
+                if mined_block.block_header.meets_target() {
+                    // only the first worker to find a solution reports it
+                    if !solved.swap(true, Ordering::SeqCst) {
+                        *found_block.lock().unwrap() = Some(mined_block.clone());
+                    }
+                    return;
+                }
+
+                nonce_offset += nonce_step;
+                // every worker has tried its share of the nonce range, so the range is tried again with the next extra nonce
+                if nonce_offset >= self.nonce_range {
+                    extra_nonce = extra_nonce.wrapping_add(1);
+                    if mined_block.set_extra_nonce(extra_nonce) {
+                        nonce_offset = worker_index as u128;
+                    }
+                }
+                mined_block.block_header.nonce = start_nonce.wrapping_add(nonce_offset);
+            }
+
+            self.hash_count.fetch_add(HASHES_PER_CHECK, Ordering::SeqCst);
+            if solved.load(Ordering::SeqCst) || self.stop_requested.load(Ordering::SeqCst) || self.new_tip.load(Ordering::SeqCst) {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::classes::block::block::Block;
+    use crate::data_structures::merkle_tree::MerkleTree;
+    use crate::util::test_util::{coinbase_tx, test_keypairs, TestChain};
+
+    use super::{Miner, MiningResult};
+
+    #[test]
+    fn miner() {
+        let pub_key = &test_keypairs()[0].pub_key;
+        let mut chain: TestChain = TestChain::new(pub_key);
+        let genesis_hash: String = chain.genesis_hash();
+        let miner: Miner = Miner::new(4);
+
+        // the workers find a nonce that meets the target, and the block is accepted
+        let block: Block = chain.new_block(&[coinbase_tx(1, 0, pub_key.clone(), 100)], &genesis_hash).unwrap();
+        let mined_block: Block = match miner.mine(&block, || chain.active_tip_hash()) {
+            MiningResult::Found(mined_block) => mined_block,
+            _ => panic!("The miner did not find a block"),
+        };
+        assert!(mined_block.block_header.meets_target());
+        assert!(miner.hashrate() > 0.0);
+        chain.accept(&mined_block);
+        assert_eq!(chain.blocks.len(), 2, "The mined block was not accepted");
+
+        // a block with an (almost) impossible target is mined until the miner is stopped
+        let mut impossible_block: Block = block.clone();
+        impossible_block.block_header.target = 0x03000001;
+        let stopped_result: MiningResult = std::thread::scope(|scope| {
+            scope.spawn(|| {
+                std::thread::sleep(std::time::Duration::from_millis(500));
+                // the tip the block is built on top of does not interrupt the miner
+                miner.notify_new_tip(&genesis_hash);
+                std::thread::sleep(std::time::Duration::from_millis(500));
+                miner.stop();
+            });
+            miner.mine(&impossible_block, || Some(genesis_hash.clone()))
+        });
+        assert!(matches!(stopped_result, MiningResult::Stopped), "The miner was not stopped");
+
+        // a new tip interrupts the miner
+        let new_tip_result: MiningResult = std::thread::scope(|scope| {
+            scope.spawn(|| {
+                std::thread::sleep(std::time::Duration::from_millis(500));
+                miner.notify_new_tip(&mined_block.block_header.hash_block().unwrap());
+            });
+            miner.mine(&impossible_block, || Some(genesis_hash.clone()))
+        });
+        assert!(matches!(new_tip_result, MiningResult::NewTip), "The miner was not interrupted by a new tip");
+
+        // a tip that changed before the job was set up interrupts it right away
+        let stale_tip_result: MiningResult = miner.mine(&impossible_block, || chain.active_tip_hash());
+        assert!(matches!(stale_tip_result, MiningResult::NewTip), "The miner mined on top of a stale tip");
+        assert!(!miner.is_mining());
+    }
+
+    #[test]
+    fn extra_nonce() {
+        let pub_key = &test_keypairs()[0].pub_key;
+        let mut chain: TestChain = TestChain::new(pub_key);
+        let block: Block = chain.new_block(&[coinbase_tx(1, 0, pub_key.clone(), 100)], &chain.genesis_hash()).unwrap();
+
+        // with a tiny nonce range, the miner has to move on to other extra nonces, and only tries the nonces of the range
+        let miner: Miner = Miner::with_nonce_range(2, 4);
+        let mined_block: Block = match miner.mine(&block, || chain.active_tip_hash()) {
+            MiningResult::Found(mined_block) => mined_block,
+            _ => panic!("The miner did not find a block"),
+        };
+        assert!(mined_block.block_header.nonce.wrapping_sub(block.block_header.nonce) < 4, "The miner tried a nonce outside of its range");
+        assert_eq!(mined_block.block_header.merkle_root, MerkleTree::new(&mined_block.txs.base).merkle_root);
+        chain.accept(&mined_block);
+        assert_eq!(chain.blocks.len(), 2, "A block mined with an extra nonce was not accepted");
+    }
+}
@@ -0,0 +1,2 @@
+pub mod block_assembler;
+pub mod miner;
\ No newline at end of file
@@ -1,3 +1,9 @@
 pub mod lamport_signature;
+pub mod winternitz_signature;
+pub mod merkle_signature;
+pub mod signature;
 pub mod transaction;
-pub mod block;
\ No newline at end of file
+pub mod block;
+pub mod consensus;
+pub mod mining;
+pub mod wallet;
\ No newline at end of file
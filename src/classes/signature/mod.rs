@@ -0,0 +1 @@
+pub mod scheme;
\ No newline at end of file
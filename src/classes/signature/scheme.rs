@@ -0,0 +1,206 @@
+use std::fmt;
+
+use sha2::{Digest, Sha256};
+
+use crate::classes::lamport_signature::key_pair::{initialize_empty_key_blocks, Key, KeyBlock};
+use crate::classes::merkle_signature::key_pair::{MerkleKey, MerkleSignature};
+use crate::classes::winternitz_signature::key_pair::{is_supported_winternitz_parameter, WinternitzKey, WinternitzSignature, DEFAULT_WINTERNITZ_PARAMETER};
+
+use serde::{Serialize, Deserialize};
+use serde_big_array::big_array;
+
+big_array! { BigArray; 256 }
+
+// the signature scheme a public key (and so every signature spending an output paid to it) uses
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SignatureScheme {
+    Lamport,
+    Winternitz { winternitz_parameter: u16 },
+    // a Merkle tree of the given height over Lamport one-time keys
+    Merkle { height: u8 },
+}
+
+impl fmt::Display for SignatureScheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureScheme::Lamport => write!(f, "Lamport"),
+            SignatureScheme::Winternitz { winternitz_parameter } => write!(f, "Winternitz (w = {})", winternitz_parameter),
+            SignatureScheme::Merkle { height } => write!(f, "Merkle (h = {})", height),
+        }
+    }
+}
+
+impl SignatureScheme {
+    // the byte a transaction hash commits to for the scheme of an input or of an output's key
+    pub fn tag(&self) -> u8 {
+        match self {
+            SignatureScheme::Lamport => 0,
+            SignatureScheme::Winternitz { .. } => 1,
+            SignatureScheme::Merkle { .. } => 2,
+        }
+    }
+
+    // "lamport", or "winternitz" followed by one of the supported Winternitz parameters (e.g. "winternitz 4"), which is 16 when it is left out
+    pub fn from_name(name: &str) -> Option<SignatureScheme> {
+        let lowercase_name: String = name.trim().to_lowercase();
+        let mut name_parts = lowercase_name.split_whitespace();
+        let scheme: SignatureScheme = match (name_parts.next()?, name_parts.next()) {
+            ("lamport", None) => SignatureScheme::Lamport,
+            ("winternitz", None) => SignatureScheme::Winternitz { winternitz_parameter: DEFAULT_WINTERNITZ_PARAMETER },
+            ("winternitz", Some(winternitz_parameter)) => SignatureScheme::Winternitz { winternitz_parameter: winternitz_parameter.parse().ok()? },
+            _ => return None,
+        };
+
+        match scheme {
+            SignatureScheme::Winternitz { winternitz_parameter } if !is_supported_winternitz_parameter(winternitz_parameter) => None,
+            _ if name_parts.next().is_some() => None,
+            _ => Some(scheme),
+        }
+    }
+}
+
+/* The public key an output is paid to. A Lamport public key is 16 KB, while a Winternitz public key is only its parameter, its public seed
+and the hash of its chain ends, so paying to a Winternitz key makes the output (and every transaction hash committing to it) far smaller.
+A Merkle public key is only the root of its tree, and unlike the others it can be paid and sign many times.
+The Lamport variant is kept inline rather than boxed, like the Lamport keys and signatures everywhere else. */
+#[derive(Clone, Serialize, Deserialize)]
+#[allow(clippy::large_enum_variant)]
+pub enum PublicKey {
+    Lamport(Key),
+    Winternitz(WinternitzKey),
+    Merkle(MerkleKey),
+}
+
+impl PublicKey {
+    pub fn scheme(&self) -> SignatureScheme {
+        match self {
+            PublicKey::Lamport(_key) => SignatureScheme::Lamport,
+            PublicKey::Winternitz(key) => SignatureScheme::Winternitz { winternitz_parameter: key.winternitz_parameter },
+            PublicKey::Merkle(key) => SignatureScheme::Merkle { height: key.height },
+        }
+    }
+
+    // a Lamport public key keeps the hash it always had, so that the keys of a wallet can still be found in the UTXO set by their hash
+    pub fn hash_key(&self) -> String {
+        match self {
+            PublicKey::Lamport(key) => key.hash_key(),
+            PublicKey::Winternitz(key) => key.hash_key(),
+            PublicKey::Merkle(key) => key.hash_key(),
+        }
+    }
+
+    /* The hash of the one-time key that made the signature, which is what key reuse is tracked by: the public key itself for a Lamport or Winternitz key,
+    or the leaf that signed for a Merkle key. */
+    pub fn signing_key_hash(&self, signature: &Signature) -> String {
+        match (self, signature) {
+            (PublicKey::Merkle(key), Signature::Merkle(merkle_signature)) => key.leaf_key_hash(merkle_signature.leaf_index),
+            _ => self.hash_key(),
+        }
+    }
+
+    // what a transaction hash commits to for an output paid to this key: the scheme first, then every part of the key
+    pub fn update_tx_hasher(&self, hasher: &mut Sha256) {
+        hasher.update([self.scheme().tag()]);
+        match self {
+            PublicKey::Lamport(key) => {
+                for pub_key_block in key.zero_blocks.iter().chain(key.one_blocks.iter()) {
+                    let block_bytes = [
+                        pub_key_block.first_part.to_be_bytes(),
+                        pub_key_block.second_part.to_be_bytes()
+                    ].concat();
+                    hasher.update(&block_bytes);
+                }
+            },
+            PublicKey::Winternitz(key) => {
+                hasher.update(key.winternitz_parameter.to_be_bytes());
+                hasher.update(key.public_seed);
+                hasher.update(key.chain_ends_hash);
+            },
+            PublicKey::Merkle(key) => {
+                hasher.update([key.height]);
+                hasher.update(key.root);
+            }
+        }
+    }
+
+    // whether the signature was made with this key over the given transaction hash, which requires both to use the same scheme
+    pub fn verify(&self, tx_hash: &str, signature: &Signature) -> bool {
+        match (self, signature) {
+            (PublicKey::Lamport(key), Signature::Lamport(signature_blocks)) => key.verify_signature(tx_hash, signature_blocks),
+            (PublicKey::Winternitz(key), Signature::Winternitz(winternitz_signature)) => key.verify(tx_hash, winternitz_signature),
+            (PublicKey::Merkle(key), Signature::Merkle(merkle_signature)) => key.verify(tx_hash, merkle_signature),
+            _ => false,
+        }
+    }
+}
+
+impl From<Key> for PublicKey {
+    fn from(key: Key) -> Self {
+        PublicKey::Lamport(key)
+    }
+}
+
+impl From<WinternitzKey> for PublicKey {
+    fn from(key: WinternitzKey) -> Self {
+        PublicKey::Winternitz(key)
+    }
+}
+
+impl From<MerkleKey> for PublicKey {
+    fn from(key: MerkleKey) -> Self {
+        PublicKey::Merkle(key)
+    }
+}
+
+// the signature of a transaction input, made with the scheme of the output it spends
+#[derive(Clone, Serialize, Deserialize)]
+#[allow(clippy::large_enum_variant)]
+pub enum Signature {
+    Lamport(#[serde(with = "BigArray")] [KeyBlock; 256]),
+    Winternitz(WinternitzSignature),
+    Merkle(MerkleSignature),
+}
+
+impl Signature {
+    /* What an input holds until it is signed. The transaction hash commits to the scheme of every input, so an input has to be created with
+    the placeholder of the scheme it will be signed with. */
+    pub fn unsigned(scheme: SignatureScheme) -> Signature {
+        match scheme {
+            SignatureScheme::Lamport => Signature::Lamport(initialize_empty_key_blocks()),
+            SignatureScheme::Winternitz { winternitz_parameter } => Signature::Winternitz(WinternitzSignature { winternitz_parameter, chain_values: vec![] }),
+            SignatureScheme::Merkle { height } => Signature::Merkle(MerkleSignature {
+                leaf_index: 0,
+                signature_blocks: initialize_empty_key_blocks(),
+                other_pub_key_blocks: initialize_empty_key_blocks(),
+                auth_path: vec![[0; 32]; height as usize],
+            }),
+        }
+    }
+
+    pub fn scheme(&self) -> SignatureScheme {
+        match self {
+            Signature::Lamport(_signature_blocks) => SignatureScheme::Lamport,
+            Signature::Winternitz(signature) => SignatureScheme::Winternitz { winternitz_parameter: signature.winternitz_parameter },
+            // a longer authentication path than a tree can have never verifies anyway
+            Signature::Merkle(signature) => SignatureScheme::Merkle { height: signature.auth_path.len() as u8 },
+        }
+    }
+}
+
+impl From<[KeyBlock; 256]> for Signature {
+    fn from(signature_blocks: [KeyBlock; 256]) -> Self {
+        Signature::Lamport(signature_blocks)
+    }
+}
+
+impl From<WinternitzSignature> for Signature {
+    fn from(signature: WinternitzSignature) -> Self {
+        Signature::Winternitz(signature)
+    }
+}
+
+impl From<MerkleSignature> for Signature {
+    fn from(signature: MerkleSignature) -> Self {
+        Signature::Merkle(signature)
+    }
+}
@@ -0,0 +1,398 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::classes::block::block::Block;
+use crate::classes::consensus::params::{ConsensusParams, KeyReusePolicy};
+use crate::classes::transaction::tx::Tx;
+use crate::classes::transaction::utxo_set::{OutPoint, UtxoEntry, UtxoLookup, UtxoSet};
+
+// the maximum total size (in bytes) of the transactions kept in the mempool, so that it cannot fill up the node's memory
+pub const MAX_MEMPOOL_SIZE: usize = 10_000_000;
+// how many seconds a transaction can wait in the mempool before it is dropped
+pub const MEMPOOL_EXPIRY: i64 = 24 * 60 * 60;
+
+#[derive(Debug)]
+pub enum MempoolError {
+    Coinbase,
+    AlreadyInMempool,
+    Conflict { outpoint: OutPoint, conflicting_tx_id: String },
+    ImmatureCoinbaseSpend(OutPoint),
+    InvalidTransaction,
+    KeyReuse { pub_key_hash: String },
+    MempoolFull,
+}
+
+impl fmt::Display for MempoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MempoolError::Coinbase => write!(f, "A coinbase transaction can only be included in a block"),
+            MempoolError::AlreadyInMempool => write!(f, "The transaction is already in the mempool"),
+            MempoolError::Conflict { outpoint, conflicting_tx_id } => write!(f, "The output {}:{} is already spent by transaction {} in the mempool", outpoint.tx_id, outpoint.index, conflicting_tx_id),
+            MempoolError::ImmatureCoinbaseSpend(outpoint) => write!(f, "The coinbase output {}:{} has not matured yet", outpoint.tx_id, outpoint.index),
+            MempoolError::InvalidTransaction => write!(f, "The transaction is invalid"),
+            MempoolError::KeyReuse { pub_key_hash } => write!(f, "The key {} has already signed a different transaction", pub_key_hash),
+            MempoolError::MempoolFull => write!(f, "The mempool is full and the fee rate of the transaction is too low"),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct MempoolEntry {
+    pub tx: Tx,
+    pub tx_id: String,
+    pub fee: u64,
+    // size of the serialized transaction, in bytes
+    pub size: usize,
+    pub time_added: i64,
+    // the keys that signed for the first time with this transaction
+    signed_keys: Vec<String>,
+    // the order the transactions were added in, so that a transaction always comes after the unconfirmed transactions it spends
+    sequence: u64,
+}
+
+impl MempoolEntry {
+    // compares the fee per byte of two entries without dividing (fee_a / size_a vs fee_b / size_b)
+    pub fn cmp_fee_rate(&self, other: &MempoolEntry) -> Ordering {
+        let fee_rate: u128 = (self.fee as u128) * (other.size as u128);
+        let other_fee_rate: u128 = (other.fee as u128) * (self.size as u128);
+        fee_rate.cmp(&other_fee_rate)
+    }
+}
+
+/*
+    Transactions that are waiting to be included in a block. Every transaction is validated against the UTXO set of the valid chain's tip
+    (plus the outputs of the other transactions in the mempool, so that unconfirmed transactions can be chained), and no two transactions in the mempool
+    can spend the same output. When the mempool is too big, the transactions with the lowest fee rate are evicted first.
+*/
+#[derive(Clone)]
+pub struct Mempool {
+    entries: HashMap<String, MempoolEntry>,
+    // which transaction in the mempool spends each outpoint, to detect conflicting spends
+    spent_by: HashMap<OutPoint, String>,
+    // the outputs created by the transactions in the mempool
+    created_utxos: HashMap<OutPoint, UtxoEntry>,
+    // the message signed by each key that signed a transaction in the mempool
+    signed_keys: HashMap<String, String>,
+    total_size: usize,
+    next_sequence: u64,
+    max_size: usize,
+    expiry: i64,
+}
+
+// the UTXO set of the valid chain's tip, along with the outputs created (and the keys that signed) by the transactions in the mempool
+struct MempoolUtxoView<'a> {
+    utxo: &'a UtxoSet,
+    created_utxos: &'a HashMap<OutPoint, UtxoEntry>,
+    signed_keys: &'a HashMap<String, String>,
+}
+
+impl UtxoLookup for MempoolUtxoView<'_> {
+    fn get(&self, outpoint: &OutPoint) -> Option<&UtxoEntry> {
+        match self.created_utxos.get(outpoint) {
+            Some(utxo_entry) => Some(utxo_entry),
+            None => self.utxo.get(outpoint),
+        }
+    }
+
+    fn signed_message(&self, pub_key_hash: &str) -> Option<&String> {
+        match self.signed_keys.get(pub_key_hash) {
+            Some(message) => Some(message),
+            None => self.utxo.signed_message(pub_key_hash),
+        }
+    }
+}
+
+impl Default for Mempool {
+    fn default() -> Self {
+        Self::new(MAX_MEMPOOL_SIZE, MEMPOOL_EXPIRY)
+    }
+}
+
+impl Mempool {
+    pub fn new(max_size: usize, expiry: i64) -> Mempool {
+        Mempool {
+            entries: HashMap::new(),
+            spent_by: HashMap::new(),
+            created_utxos: HashMap::new(),
+            signed_keys: HashMap::new(),
+            total_size: 0,
+            next_sequence: 0,
+            max_size,
+            expiry,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn contains(&self, tx_id: &str) -> bool {
+        self.entries.contains_key(tx_id)
+    }
+
+    pub fn total_size(&self) -> usize {
+        self.total_size
+    }
+
+    // whether a transaction in the mempool already spends the given output
+    pub fn is_spent(&self, outpoint: &OutPoint) -> bool {
+        self.spent_by.contains_key(outpoint)
+    }
+
+    // the message signed by the key with the given hash in a transaction in the mempool, if it signed one
+    pub fn signed_message(&self, pub_key_hash: &str) -> Option<&String> {
+        self.signed_keys.get(pub_key_hash)
+    }
+
+    /* Validates the transaction against the UTXO set of the valid chain's tip, as if it was included in a block at spend_height, and adds it to the mempool.
+    Returns the id of the transaction. */
+    pub fn add_tx(&mut self, tx: &Tx, utxo: &UtxoSet, spend_height: u64, params: &ConsensusParams, time_added: i64) -> Result<String, MempoolError> {
+        if tx.inputs.iter().any(|tx_input| tx_input.is_coinbase) {
+            return Err(MempoolError::Coinbase);
+        }
+
+        let tx_id: String = tx.get_tx_id();
+        if self.entries.contains_key(&tx_id) {
+            return Err(MempoolError::AlreadyInMempool);
+        }
+
+        let utxo_view: MempoolUtxoView = MempoolUtxoView { utxo, created_utxos: &self.created_utxos, signed_keys: &self.signed_keys };
+        for tx_input in &tx.inputs {
+            let outpoint: OutPoint = OutPoint::new(tx_input.prev_tx_id.clone(), tx_input.index);
+            if let Some(conflicting_tx_id) = self.spent_by.get(&outpoint) {
+                return Err(MempoolError::Conflict { outpoint, conflicting_tx_id: conflicting_tx_id.clone() });
+            }
+
+            if utxo_view.get(&outpoint).is_some_and(|spent_entry| !spent_entry.is_mature(spend_height, params.coinbase_maturity)) {
+                return Err(MempoolError::ImmatureCoinbaseSpend(outpoint));
+            }
+        }
+
+        if !tx.verify_transaction(&utxo_view) {
+            return Err(MempoolError::InvalidTransaction);
+        }
+        let fee: u64 = tx.fee(&utxo_view).ok_or(MempoolError::InvalidTransaction)?;
+
+        if let Some(pub_key_hash) = tx.reused_signing_key(&utxo_view) {
+            match params.key_reuse_policy {
+                KeyReusePolicy::Flag => println!("Transaction {} is signed by a key that has already signed a different transaction...", tx_id),
+                KeyReusePolicy::Reject => return Err(MempoolError::KeyReuse { pub_key_hash }),
+            }
+        }
+
+        let tx_hash: String = tx.get_tx_hash();
+        let mut signed_keys: Vec<String> = vec![];
+        for tx_input in &tx.inputs {
+            let outpoint: OutPoint = OutPoint::new(tx_input.prev_tx_id.clone(), tx_input.index);
+            if let Some(pub_key_hash) = utxo_view.get(&outpoint).map(|spent_entry| spent_entry.output.pub_key.signing_key_hash(&tx_input.signature)) {
+                if utxo_view.signed_message(&pub_key_hash).is_none() && !signed_keys.contains(&pub_key_hash) {
+                    signed_keys.push(pub_key_hash);
+                }
+            }
+        }
+        for pub_key_hash in &signed_keys {
+            self.signed_keys.insert(pub_key_hash.clone(), tx_hash.clone());
+        }
+
+        for tx_input in &tx.inputs {
+            self.spent_by.insert(OutPoint::new(tx_input.prev_tx_id.clone(), tx_input.index), tx_id.clone());
+        }
+        for (output_index, tx_output) in tx.outputs.iter().enumerate() {
+            self.created_utxos.insert(OutPoint::new(tx_id.clone(), output_index), UtxoEntry {
+                output: tx_output.clone(),
+                height: spend_height,
+                is_coinbase: false,
+            });
+        }
+
+        let size: usize = tx.convert_tx_to_bytes().len();
+        self.total_size += size;
+        self.entries.insert(tx_id.clone(), MempoolEntry {
+            tx: tx.clone(),
+            tx_id: tx_id.clone(),
+            fee,
+            size,
+            time_added,
+            signed_keys,
+            sequence: self.next_sequence,
+        });
+        self.next_sequence += 1;
+
+        self.trim_to_size();
+        if !self.entries.contains_key(&tx_id) {
+            return Err(MempoolError::MempoolFull);
+        }
+
+        Ok(tx_id)
+    }
+
+    // every transaction in the mempool, from the highest fee rate to the lowest (the earliest one first when the fee rates are equal)
+    pub fn entries_by_fee_rate(&self) -> Vec<&MempoolEntry> {
+        let mut sorted_entries: Vec<&MempoolEntry> = self.entries.values().collect();
+        sorted_entries.sort_by(|first, second| second.cmp_fee_rate(first).then(first.sequence.cmp(&second.sequence)));
+        sorted_entries
+    }
+
+    // drops the transactions that have been waiting for longer than the expiry time (along with the transactions that spend their outputs)
+    pub fn expire(&mut self, now: i64) {
+        let expired_tx_ids: Vec<String> = self.entries.values()
+            .filter(|entry| entry.time_added.saturating_add(self.expiry) < now)
+            .map(|entry| entry.tx_id.clone())
+            .collect();
+
+        for expired_tx_id in expired_tx_ids {
+            self.remove_with_descendants(&expired_tx_id);
+        }
+    }
+
+    /* Called when a block is connected to the valid chain: the transactions it includes are removed from the mempool,
+    and so are the transactions that spend the same outputs as the block (along with the transactions that spend their outputs), since they can never be valid anymore. */
+    pub fn remove_for_block(&mut self, block: &Block) {
+        for block_tx in block.txs.base.iter().skip(1) {
+            let block_tx_id: String = block_tx.get_tx_id();
+            self.remove_entry(&block_tx_id);
+
+            for tx_input in &block_tx.inputs {
+                let outpoint: OutPoint = OutPoint::new(tx_input.prev_tx_id.clone(), tx_input.index);
+                if let Some(conflicting_tx_id) = self.spent_by.get(&outpoint).cloned() {
+                    self.remove_with_descendants(&conflicting_tx_id);
+                }
+            }
+        }
+    }
+
+    /*
+        Called after a reorganization that disconnected blocks from the valid chain: the transactions of the disconnected blocks are put back into the mempool
+        (before the transactions that were already in it, since those may spend their outputs), and every transaction is validated again against the UTXO set
+        of the new tip. The ones that are no longer valid (e.g. because the new valid chain already includes them or spends the same outputs) are dropped.
+    */
+    pub fn readd_after_reorg(&mut self, disconnected_txs: &[Tx], utxo: &UtxoSet, spend_height: u64, params: &ConsensusParams, now: i64) {
+        let mut previous_entries: Vec<MempoolEntry> = self.entries.drain().map(|(_tx_id, entry)| entry).collect();
+        previous_entries.sort_by_key(|entry| entry.sequence);
+        self.spent_by.clear();
+        self.created_utxos.clear();
+        self.signed_keys.clear();
+        self.total_size = 0;
+
+        for disconnected_tx in disconnected_txs {
+            let _ = self.add_tx(disconnected_tx, utxo, spend_height, params, now);
+        }
+        for previous_entry in previous_entries {
+            let _ = self.add_tx(&previous_entry.tx, utxo, spend_height, params, previous_entry.time_added);
+        }
+    }
+
+    // evicts the transactions with the lowest fee rate (along with the transactions that spend their outputs) until the mempool fits in its maximum size
+    fn trim_to_size(&mut self) {
+        while self.total_size > self.max_size {
+            let lowest_fee_rate_tx_id: String = match self.entries.values().min_by(|first, second| first.cmp_fee_rate(second).then(second.sequence.cmp(&first.sequence))) {
+                Some(entry) => entry.tx_id.clone(),
+                None => return,
+            };
+            self.remove_with_descendants(&lowest_fee_rate_tx_id);
+        }
+    }
+
+    fn remove_with_descendants(&mut self, tx_id: &str) {
+        let mut removed_tx_ids: Vec<String> = vec![tx_id.to_string()];
+        while let Some(removed_tx_id) = removed_tx_ids.pop() {
+            let removed_entry: MempoolEntry = match self.remove_entry(&removed_tx_id) {
+                Some(entry) => entry,
+                None => continue,
+            };
+
+            for output_index in 0..removed_entry.tx.outputs.len() {
+                if let Some(child_tx_id) = self.spent_by.get(&OutPoint::new(removed_tx_id.clone(), output_index)) {
+                    removed_tx_ids.push(child_tx_id.clone());
+                }
+            }
+        }
+    }
+
+    fn remove_entry(&mut self, tx_id: &String) -> Option<MempoolEntry> {
+        let entry: MempoolEntry = self.entries.remove(tx_id)?;
+
+        for tx_input in &entry.tx.inputs {
+            self.spent_by.remove(&OutPoint::new(tx_input.prev_tx_id.clone(), tx_input.index));
+        }
+        for output_index in 0..entry.tx.outputs.len() {
+            self.created_utxos.remove(&OutPoint::new(tx_id.clone(), output_index));
+        }
+        for pub_key_hash in &entry.signed_keys {
+            self.signed_keys.remove(pub_key_hash);
+        }
+        self.total_size -= entry.size;
+
+        Some(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::classes::lamport_signature::key_pair::KeyPair;
+    use crate::classes::transaction::tx::Tx;
+    use crate::classes::transaction::utxo_set::UtxoSet;
+    use crate::util::test_util::{coinbase_tx, spend_tx, test_keypairs, TestChain};
+
+    use super::{Mempool, MempoolEntry, MempoolError};
+
+    #[test]
+    fn mempool() {
+        let keypairs: &[KeyPair] = test_keypairs();
+        let coinbase_tx = |height: u64, extra_nonce: u64, amount: u64| -> Tx {
+            coinbase_tx(height, extra_nonce, keypairs[0].pub_key.clone(), amount)
+        };
+        // coinbase outputs are spent right away in this test
+        let mut chain: TestChain = TestChain::with_params(&keypairs[0].pub_key, |params| params.coinbase_maturity = 0);
+        let genesis_coinbase: Tx = chain.blocks[0].txs.base[0].clone();
+        let genesis_hash: String = chain.genesis_hash();
+        let genesis_utxo: UtxoSet = chain.utxo.clone();
+
+        // a transaction paying a fee of 10, and an unconfirmed transaction spending its output with a fee of 5
+        let parent_tx: Tx = spend_tx(&[(genesis_coinbase.get_tx_id(), 0)], &keypairs[0], keypairs[1].pub_key.clone(), &[90]);
+        let child_tx: Tx = spend_tx(&[(parent_tx.get_tx_id(), 0)], &keypairs[1], keypairs[0].pub_key.clone(), &[85]);
+        assert!(chain.add_to_mempool(&parent_tx).is_ok(), "A valid transaction was not added to the mempool");
+        assert!(chain.add_to_mempool(&child_tx).is_ok(), "A transaction spending an unconfirmed output was not added to the mempool");
+        let entries: Vec<&MempoolEntry> = chain.mempool.entries_by_fee_rate();
+        assert_eq!(entries[0].tx_id, parent_tx.get_tx_id(), "The mempool is not ordered by fee rate");
+        assert_eq!(entries[0].fee, 10);
+
+        // spending the same output twice, or adding a coinbase transaction, is not allowed
+        let conflicting_tx: Tx = spend_tx(&[(genesis_coinbase.get_tx_id(), 0)], &keypairs[0], keypairs[1].pub_key.clone(), &[95]);
+        assert!(matches!(chain.add_to_mempool(&conflicting_tx), Err(MempoolError::Conflict { .. })), "A conflicting transaction was added to the mempool");
+        assert!(matches!(chain.add_to_mempool(&coinbase_tx(1, 0, 100)), Err(MempoolError::Coinbase)));
+        assert!(matches!(chain.add_to_mempool(&parent_tx), Err(MempoolError::AlreadyInMempool)));
+
+        // a block including the conflicting transaction removes both mempool transactions
+        chain.mine(vec![coinbase_tx(1, 0, 105), conflicting_tx.clone()], &genesis_hash);
+        assert_eq!(chain.blocks.len(), 2);
+        assert!(chain.mempool.len() == 0, "Transactions conflicting with a connected block were kept in the mempool");
+
+        // a reorganization that disconnects the block puts its transaction back into the mempool
+        let block_b1_hash: String = chain.mine(vec![coinbase_tx(1, 1, 100)], &genesis_hash).block_header.hash_block().unwrap();
+        let block_b2_hash: String = chain.mine(vec![coinbase_tx(2, 1, 100)], &block_b1_hash).block_header.hash_block().unwrap();
+        assert_eq!(chain.blocks[2].block_header.hash_block().unwrap(), block_b2_hash, "The longer branch was not activated");
+        assert!(chain.mempool.contains(&conflicting_tx.get_tx_id()), "A transaction of a disconnected block was not put back into the mempool");
+        assert_eq!(chain.mempool.len(), 1);
+        assert_eq!(chain.next_block_height(), 3, "The mempool is not checked against the height of the next block of the valid chain");
+
+        // a block including the transaction removes it from the mempool
+        chain.mine(vec![coinbase_tx(3, 1, 105), conflicting_tx.clone()], &block_b2_hash);
+        assert_eq!(chain.blocks.len(), 4);
+        assert!(chain.mempool.len() == 0, "A confirmed transaction was kept in the mempool");
+
+        // when the mempool is full, the transaction with the lowest fee rate is evicted
+        let parent_tx_size: usize = parent_tx.convert_tx_to_bytes().len();
+        let mut small_mempool: Mempool = Mempool::new(parent_tx_size + parent_tx_size / 2, 100);
+        assert!(small_mempool.add_tx(&parent_tx, &genesis_utxo, 1, &chain.params, 0).is_ok());
+        assert!(matches!(small_mempool.add_tx(&child_tx, &genesis_utxo, 1, &chain.params, 0), Err(MempoolError::MempoolFull)), "A transaction with a low fee rate was kept in a full mempool");
+        assert_eq!(small_mempool.len(), 1);
+
+        // transactions that waited for too long are dropped
+        small_mempool.expire(50);
+        assert_eq!(small_mempool.len(), 1);
+        small_mempool.expire(101);
+        assert!(small_mempool.len() == 0, "An expired transaction was kept in the mempool");
+    }
+}
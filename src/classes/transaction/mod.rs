@@ -1 +1,3 @@
-pub mod tx;
\ No newline at end of file
+pub mod mempool;
+pub mod tx;
+pub mod utxo_set;
\ No newline at end of file
@@ -1,13 +1,13 @@
+use std::collections::HashSet;
+
 use sha2::{Sha256, Digest};
 
-use crate::classes::lamport_signature::key_pair::{Key, KeyBlock};
-use crate::util::conversions::hex_string_to_bit_vector;
+use crate::classes::lamport_signature::key_pair::initialize_empty_key_blocks;
+use crate::classes::signature::scheme::{PublicKey, Signature};
+use crate::classes::transaction::utxo_set::{OutPoint, UtxoEntry, UtxoLookup};
 
 use bincode;
 use serde::{Serialize, Deserialize};
-use serde_big_array::big_array;
-
-big_array! { BigArray; 256 }
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Tx {
@@ -52,28 +52,17 @@ impl Tx {
     pub fn get_tx_hash(&self) -> String{
         let mut hasher = Sha256::new();
         
+        /* Every input commits to the output it spends (its index too, since outputs of the same transaction can be paid to the same Merkle key)
+        and to the scheme it is signed with. */
         for input in &self.inputs {
             hasher.update(&[if input.is_coinbase {1} else {0}]);
             hasher.update(input.prev_tx_id.as_bytes());
+            hasher.update((input.index as u64).to_be_bytes());
+            hasher.update([input.signature.scheme().tag()]);
         }
 
         for output in &self.outputs {
-            for pub_key_zero_block in &output.pub_key.zero_blocks {
-                let block_bytes = [
-                    pub_key_zero_block.first_part.to_be_bytes(),
-                    pub_key_zero_block.second_part.to_be_bytes()
-                ].concat();
-                hasher.update(&block_bytes);
-            }
-
-            for pub_key_one_block in &output.pub_key.one_blocks {
-                let block_bytes = [
-                    pub_key_one_block.first_part.to_be_bytes(),
-                    pub_key_one_block.second_part.to_be_bytes()
-                ].concat();
-                hasher.update(&block_bytes);
-            }
-
+            output.pub_key.update_tx_hasher(&mut hasher);
             hasher.update(&output.amount.to_be_bytes());
         }
 
@@ -82,34 +71,95 @@ impl Tx {
         return hex_result;
     }
 
-    pub fn verify_transaction(&self, utxo: &Vec<Tx>) -> bool {
-        // check if the total input amount >= total output amount
-        
+    // what the coinbase input of the transaction commits to, or None if it is not a coinbase transaction
+    pub fn coinbase_data(&self) -> Option<CoinbaseData> {
+        match self.inputs.first() {
+            Some(tx_input) if tx_input.is_coinbase => tx_input.coinbase_data,
+            _ => None,
+        }
+    }
+
+    // whether two inputs of the transaction consume the same output
+    pub fn has_duplicate_inputs(&self) -> bool {
+        let mut consumed_outpoints: HashSet<OutPoint> = HashSet::new();
+        for tx_input in &self.inputs {
+            if !consumed_outpoints.insert(OutPoint::new(tx_input.prev_tx_id.clone(), tx_input.index)) {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    // the total amount of the outputs the transaction consumes, or None if one of them cannot be found (or the total overflows)
+    pub fn input_amount(&self, utxo: &dyn UtxoLookup) -> Option<u64> {
         let mut input_sum: u64 = 0;
+        for tx_input in &self.inputs {
+            let utxo_entry: &UtxoEntry = utxo.get(&OutPoint::new(tx_input.prev_tx_id.clone(), tx_input.index))?;
+            input_sum = input_sum.checked_add(utxo_entry.output.amount)?;
+        }
+
+        Some(input_sum)
+    }
+
+    // the total amount of the outputs the transaction creates, or None if the total overflows
+    pub fn output_amount(&self) -> Option<u64> {
         let mut output_sum: u64 = 0;
+        for tx_output in &self.outputs {
+            output_sum = output_sum.checked_add(tx_output.amount)?;
+        }
+
+        Some(output_sum)
+    }
 
+    /* The fee of a transaction is whatever part of the input amount is not sent to any of its outputs, and it can be claimed by the miner of the block the transaction is in.
+    Returns None if the fee cannot be computed, i.e. if an input cannot be found or if the outputs are worth more than the inputs. */
+    pub fn fee(&self, utxo: &dyn UtxoLookup) -> Option<u64> {
+        let input_sum: u64 = self.input_amount(utxo)?;
+        let output_sum: u64 = self.output_amount()?;
+        input_sum.checked_sub(output_sum)
+    }
+
+    /* The hash of a public key that signs an input of the transaction after already signing a different transaction, if there is one.
+    Inputs of the same transaction that are signed by the same key all sign the same message, so they do not count as reuse. */
+    pub fn reused_signing_key(&self, utxo: &dyn UtxoLookup) -> Option<String> {
+        let tx_hash: String = self.get_tx_hash();
         for tx_input in &self.inputs {
-            let prev_tx_id: &String = &tx_input.prev_tx_id;
-            let prev_tx_index = &tx_input.index;
-
-            let mut found_consumed_output: bool = false;
-            for utxo_tx in utxo {
-                // if found the transaction that holds the consumed output
-                if &utxo_tx.get_tx_id() == prev_tx_id && &utxo_tx.outputs.len() > prev_tx_index { 
-                    found_consumed_output = true;
-                    input_sum += utxo_tx.outputs[prev_tx_index.clone()].amount;
-                }
+            let pub_key_hash: String = match utxo.get(&OutPoint::new(tx_input.prev_tx_id.clone(), tx_input.index)) {
+                Some(utxo_entry) => utxo_entry.output.pub_key.signing_key_hash(&tx_input.signature),
+                None => continue,
+            };
+
+            if utxo.signed_message(&pub_key_hash).is_some_and(|signed_message| *signed_message != tx_hash) {
+                return Some(pub_key_hash);
             }
+        }
+
+        None
+    }
 
-            if !found_consumed_output {
+    pub fn verify_transaction(&self, utxo: &dyn UtxoLookup) -> bool {
+        if self.has_duplicate_inputs() {
+            println!("New transaction spends the same output more than once...");
+            return false;
+        }
+
+        // check if the total input amount >= total output amount, where the difference is the fee of the transaction
+        let input_sum: u64 = match self.input_amount(utxo) {
+            Some(input_sum) => input_sum,
+            None => {
                 println!("Could not find a matching output for a new transaction...");
                 return false;
             }
-        }
+        };
 
-        for tx_output in &self.outputs {
-            output_sum += tx_output.amount;
-        }
+        let output_sum: u64 = match self.output_amount() {
+            Some(output_sum) => output_sum,
+            None => {
+                println!("New transaction has an output amount that is too large...");
+                return false;
+            }
+        };
 
         if input_sum < output_sum {
             println!("New transaction has more output amount than input amount...");
@@ -122,51 +172,27 @@ impl Tx {
         return verified_tx_signature;
     }
 
-    pub fn verify_signature(&self, utxo: &Vec<Tx>) -> bool {
-        let tx_hash_bits: Vec<u8> = hex_string_to_bit_vector(self.get_tx_hash());
+    // each input must be signed with the scheme of the output it spends, by the key of that output
+    pub fn verify_signature(&self, utxo: &dyn UtxoLookup) -> bool {
+        let tx_hash: String = self.get_tx_hash();
         let mut verified: bool = true;
 
         for input in &self.inputs {
-            let input_signature: &[KeyBlock; 256] = &input.signature;
-
-            let mut pub_key_option: Option<Key> = None;
-            for utxo_tx in utxo {
-                if utxo_tx.get_tx_id() == input.prev_tx_id {
-                    pub_key_option = Some(utxo_tx.outputs[input.index].pub_key.clone());
-                    break;
+            let pub_key: &PublicKey = match utxo.get(&OutPoint::new(input.prev_tx_id.clone(), input.index)) {
+                Some(utxo_entry) => &utxo_entry.output.pub_key,
+                None => {
+                    println!("Could not find matching output for transaction input...");
+                    return false;
                 }
-            }
+            };
 
-            if pub_key_option.is_none() {
-                println!("Could not find matching output for transaction input...");
+            if input.signature.scheme() != pub_key.scheme() {
+                println!("Transaction input is signed with the {} scheme, but the output it spends requires the {} scheme...", input.signature.scheme(), pub_key.scheme());
                 return false;
             }
 
-            let pub_key: Key = pub_key_option.unwrap();
-
-            let mut j = 0;
-            for bit in &tx_hash_bits {
-                if *bit == (0 as u8) {
-                    // get the corresponding public key block from zero blocks
-                    let pub_key_block: KeyBlock = pub_key.zero_blocks[j].clone();
-                    // construct it yourself using the signature blocks that are supposed to be chosen from the private key blocks
-                    let constructed_pub_key_block: KeyBlock = input_signature[j].hash_priv_key_block();
-
-                    // if they don't match, set verified to false
-                    if pub_key_block.first_part != constructed_pub_key_block.first_part || pub_key_block.second_part != constructed_pub_key_block.second_part {
-                        verified = false;
-                    }
-                } else {
-                    // repeat the same process as above but for one blocks
-                    let pub_key_block: KeyBlock = pub_key.one_blocks[j].clone();
-                    let constructed_pub_key_block: KeyBlock = input_signature[j].hash_priv_key_block();
-
-
-                    if pub_key_block.first_part != constructed_pub_key_block.first_part || pub_key_block.second_part != constructed_pub_key_block.second_part {
-                        verified = false;
-                    }
-                }
-                j += 1;
+            if !pub_key.verify(&tx_hash, &input.signature) {
+                verified = false;
             }
         }
         
@@ -176,34 +202,58 @@ impl Tx {
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct TxInput {
-    #[serde(with = "BigArray")]
-    pub signature: [KeyBlock; 256],
+    pub signature: Signature,
     pub prev_tx_id: String,
     pub index: usize,
     pub is_coinbase: bool,
+    // only set on the input of a coinbase transaction
+    pub coinbase_data: Option<CoinbaseData>,
+}
+
+/*
+    What the input of a coinbase transaction commits to instead of a previous output. The height of the block makes the coinbase transactions of
+    different blocks (and so their ids) unique, and the extra nonce gives the miner more values to try once every header nonce has been tried,
+    since changing it changes the merkle root.
+*/
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CoinbaseData {
+    pub height: u64,
+    pub extra_nonce: u64,
 }
 
 impl TxInput {
-    pub fn new(signature: [KeyBlock; 256], prev_tx_id: String, is_coinbase: bool, index:usize) -> TxInput{
+    pub fn new(signature: impl Into<Signature>, prev_tx_id: String, is_coinbase: bool, index:usize) -> TxInput{
         return TxInput {
-            signature,
+            signature: signature.into(),
             prev_tx_id,
             is_coinbase,
-            index
+            index,
+            coinbase_data: None,
         };
     }
+
+    // the input of the coinbase transaction of the block at the given height
+    pub fn new_coinbase(height: u64, extra_nonce: u64) -> TxInput {
+        TxInput {
+            signature: Signature::Lamport(initialize_empty_key_blocks()),
+            prev_tx_id: "".to_string(),
+            is_coinbase: true,
+            index: 0,
+            coinbase_data: Some(CoinbaseData { height, extra_nonce }),
+        }
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct TxOutput {
-    pub pub_key: Key,
+    pub pub_key: PublicKey,
     pub amount: u64,
 }
 
 impl TxOutput {
-    pub fn new(pub_key: Key, amount: u64) -> TxOutput {
+    pub fn new(pub_key: impl Into<PublicKey>, amount: u64) -> TxOutput {
         return TxOutput {
-            pub_key,
+            pub_key: pub_key.into(),
             amount
         };
     }
@@ -0,0 +1,344 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::classes::block::block::Block;
+use crate::classes::transaction::tx::{Tx, TxOutput};
+
+// a reference to a single output of a transaction
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct OutPoint {
+    pub tx_id: String,
+    pub index: usize,
+}
+
+impl OutPoint {
+    pub fn new(tx_id: String, index: usize) -> OutPoint {
+        OutPoint {
+            tx_id,
+            index
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct UtxoEntry {
+    pub output: TxOutput,
+    // height of the block that created the output
+    pub height: u64,
+    pub is_coinbase: bool,
+}
+
+impl UtxoEntry {
+    /* Coinbase outputs can only be spent once coinbase_maturity blocks have been built on top of the block that created them,
+    since a coinbase transaction disappears for good if its block ends up on a losing branch after a reorganization. */
+    pub fn is_mature(&self, spend_height: u64, coinbase_maturity: u64) -> bool {
+        !self.is_coinbase || spend_height >= self.height.saturating_add(coinbase_maturity)
+    }
+}
+
+// the money an account owns, split between what it can spend in the next block and the coinbase outputs that have not matured yet
+#[derive(Clone, Copy, Default)]
+pub struct Balance {
+    pub spendable: u64,
+    pub immature: u64,
+}
+
+/* The outputs a block spent, so that they can be put back into the UTXO set if the block is disconnected from the valid chain,
+along with the keys that signed for the first time in the block. */
+#[derive(Clone, Serialize, Deserialize, Default)]
+pub struct BlockUndo {
+    pub spent_outputs: Vec<(OutPoint, UtxoEntry)>,
+    pub signed_keys: Vec<String>,
+}
+
+// anything unspent outputs can be looked up in, so that transactions can be verified against the UTXO set itself or against a view of it
+pub trait UtxoLookup {
+    fn get(&self, outpoint: &OutPoint) -> Option<&UtxoEntry>;
+
+    // the message (transaction hash) that the public key with the given hash has already signed, if it has signed one
+    fn signed_message(&self, pub_key_hash: &str) -> Option<&String>;
+}
+
+/*
+    The unspent outputs of the valid chain. Since Lamport keys are one-time keys, the UTXO set also remembers which public keys have already signed
+    a transaction (and which message they signed), so that a key signing a second, different message can be detected: every signature reveals
+    half of the private key blocks, so a second signature lets anyone forge signatures for more messages. Each leaf of a Merkle key counts as a key of its own.
+*/
+#[derive(Clone, Default)]
+pub struct UtxoSet {
+    utxos: HashMap<OutPoint, UtxoEntry>,
+    // the message signed by each key that has signed a transaction, keyed by the hash of the key
+    signed_keys: HashMap<String, String>,
+}
+
+impl UtxoSet {
+    pub fn new() -> UtxoSet {
+        UtxoSet {
+            utxos: HashMap::new(),
+            signed_keys: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, outpoint: &OutPoint) -> Option<&UtxoEntry> {
+        self.utxos.get(outpoint)
+    }
+
+    pub fn signed_message(&self, pub_key_hash: &str) -> Option<&String> {
+        self.signed_keys.get(pub_key_hash)
+    }
+
+    pub fn len(&self) -> usize {
+        self.utxos.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&OutPoint, &UtxoEntry)> {
+        self.utxos.iter()
+    }
+
+    // the balance of the public key with the given hash for a transaction included in a block at spend_height
+    pub fn balance(&self, pub_key_hash: &str, spend_height: u64, coinbase_maturity: u64) -> Balance {
+        let mut balance: Balance = Balance::default();
+
+        for utxo_entry in self.utxos.values() {
+            if utxo_entry.output.pub_key.hash_key() != *pub_key_hash {
+                continue;
+            }
+
+            if utxo_entry.is_mature(spend_height, coinbase_maturity) {
+                balance.spendable += utxo_entry.output.amount;
+            } else {
+                balance.immature += utxo_entry.output.amount;
+            }
+        }
+
+        balance
+    }
+
+    /*
+        Spends the outputs consumed by the block's transactions and adds the outputs they create, in the order of the transactions in the block
+        (so a transaction can spend an output created earlier in the same block). Returns the undo data of the block, or the first outpoint
+        that could not be found, in which case the UTXO set is left unchanged.
+    */
+    pub fn apply_block(&mut self, block: &Block, height: u64) -> Result<BlockUndo, OutPoint> {
+        let mut undo: BlockUndo = BlockUndo::default();
+        let mut created_outpoints: Vec<OutPoint> = vec![];
+
+        for tx in &block.txs.base {
+            let is_coinbase: bool = tx.inputs.iter().any(|tx_input| tx_input.is_coinbase);
+
+            if !is_coinbase {
+                let tx_hash: String = tx.get_tx_hash();
+                for tx_input in &tx.inputs {
+                    let outpoint: OutPoint = OutPoint::new(tx_input.prev_tx_id.clone(), tx_input.index);
+                    match self.utxos.remove(&outpoint) {
+                        Some(spent_entry) => {
+                            // only the first message a key signs is remembered
+                            let pub_key_hash: String = spent_entry.output.pub_key.signing_key_hash(&tx_input.signature);
+                            if !self.signed_keys.contains_key(&pub_key_hash) {
+                                self.signed_keys.insert(pub_key_hash.clone(), tx_hash.clone());
+                                undo.signed_keys.push(pub_key_hash);
+                            }
+                            undo.spent_outputs.push((outpoint, spent_entry));
+                        },
+                        None => {
+                            self.revert(&created_outpoints, &undo);
+                            return Err(outpoint);
+                        }
+                    }
+                }
+            }
+
+            let tx_id: String = tx.get_tx_id();
+            for (output_index, tx_output) in tx.outputs.iter().enumerate() {
+                let outpoint: OutPoint = OutPoint::new(tx_id.clone(), output_index);
+                self.utxos.insert(outpoint.clone(), UtxoEntry {
+                    output: tx_output.clone(),
+                    height,
+                    is_coinbase,
+                });
+                created_outpoints.push(outpoint);
+            }
+        }
+
+        Ok(undo)
+    }
+
+    // removes the outputs created by the block and puts back the outputs it spent
+    pub fn undo_block(&mut self, block: &Block, undo: &BlockUndo) {
+        let mut created_outpoints: Vec<OutPoint> = vec![];
+        for tx in &block.txs.base {
+            let tx_id: String = tx.get_tx_id();
+            for output_index in 0..tx.outputs.len() {
+                created_outpoints.push(OutPoint::new(tx_id.clone(), output_index));
+            }
+        }
+
+        self.revert(&created_outpoints, undo);
+    }
+
+    fn revert(&mut self, created_outpoints: &Vec<OutPoint>, undo: &BlockUndo) {
+        for outpoint in created_outpoints {
+            self.utxos.remove(outpoint);
+        }
+
+        for (outpoint, spent_entry) in undo.spent_outputs.iter().rev() {
+            self.utxos.insert(outpoint.clone(), spent_entry.clone());
+        }
+
+        for pub_key_hash in &undo.signed_keys {
+            self.signed_keys.remove(pub_key_hash);
+        }
+    }
+}
+
+impl UtxoLookup for UtxoSet {
+    fn get(&self, outpoint: &OutPoint) -> Option<&UtxoEntry> {
+        self.utxos.get(outpoint)
+    }
+
+    fn signed_message(&self, pub_key_hash: &str) -> Option<&String> {
+        self.signed_keys.get(pub_key_hash)
+    }
+}
+
+/* The UTXO set as it is partway through a block: the outputs spent by the transactions seen so far are hidden, and the outputs they created are added,
+without modifying the underlying UTXO set. This lets a block be validated one transaction at a time, so that an output cannot be spent twice in the same block
+while a transaction can still spend an output created earlier in the block. */
+pub struct UtxoView<'a> {
+    base: &'a UtxoSet,
+    spent_outpoints: HashSet<OutPoint>,
+    created_utxos: HashMap<OutPoint, UtxoEntry>,
+    // the keys that signed a transaction seen so far, along with the message they signed
+    signed_keys: HashMap<String, String>,
+}
+
+impl<'a> UtxoView<'a> {
+    pub fn new(base: &'a UtxoSet) -> UtxoView<'a> {
+        UtxoView {
+            base,
+            spent_outpoints: HashSet::new(),
+            created_utxos: HashMap::new(),
+            signed_keys: HashMap::new(),
+        }
+    }
+
+    pub fn is_spent(&self, outpoint: &OutPoint) -> bool {
+        self.spent_outpoints.contains(outpoint)
+    }
+
+    // spends the outputs consumed by the transaction and adds the outputs it creates
+    pub fn apply_tx(&mut self, tx: &Tx, height: u64) {
+        let is_coinbase: bool = tx.inputs.iter().any(|tx_input| tx_input.is_coinbase);
+
+        if !is_coinbase {
+            let tx_hash: String = tx.get_tx_hash();
+            for tx_input in &tx.inputs {
+                let outpoint: OutPoint = OutPoint::new(tx_input.prev_tx_id.clone(), tx_input.index);
+                if let Some(pub_key_hash) = self.get(&outpoint).map(|spent_entry| spent_entry.output.pub_key.signing_key_hash(&tx_input.signature)) {
+                    if self.signed_message(&pub_key_hash).is_none() {
+                        self.signed_keys.insert(pub_key_hash, tx_hash.clone());
+                    }
+                }
+                self.created_utxos.remove(&outpoint);
+                self.spent_outpoints.insert(outpoint);
+            }
+        }
+
+        let tx_id: String = tx.get_tx_id();
+        for (output_index, tx_output) in tx.outputs.iter().enumerate() {
+            let outpoint: OutPoint = OutPoint::new(tx_id.clone(), output_index);
+            self.spent_outpoints.remove(&outpoint);
+            self.created_utxos.insert(outpoint, UtxoEntry {
+                output: tx_output.clone(),
+                height,
+                is_coinbase,
+            });
+        }
+    }
+}
+
+impl UtxoLookup for UtxoView<'_> {
+    fn get(&self, outpoint: &OutPoint) -> Option<&UtxoEntry> {
+        if self.spent_outpoints.contains(outpoint) {
+            return None;
+        }
+
+        match self.created_utxos.get(outpoint) {
+            Some(utxo_entry) => Some(utxo_entry),
+            None => self.base.get(outpoint),
+        }
+    }
+
+    fn signed_message(&self, pub_key_hash: &str) -> Option<&String> {
+        match self.signed_keys.get(pub_key_hash) {
+            Some(message) => Some(message),
+            None => self.base.signed_message(pub_key_hash),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::classes::block::block::Block;
+    use crate::classes::lamport_signature::key_pair::{initialize_empty_key_blocks, KeyPair};
+    use crate::classes::transaction::tx::{Tx, TxInput, TxOutput};
+    use crate::util::test_util::{coinbase_tx, test_keypairs};
+
+    use super::{OutPoint, UtxoSet};
+
+    #[test]
+    fn apply_and_undo() {
+        let keypairs: &[KeyPair] = test_keypairs();
+        let coinbase_tx: Tx = coinbase_tx(0, 0, keypairs[0].pub_key.clone(), 100);
+        let coinbase_block: Block = Block::new(std::slice::from_ref(&coinbase_tx), "".to_string(), 0);
+
+        // split the coinbase output into two outputs
+        let mut split_tx: Tx = Tx::new(
+            vec![TxInput::new(initialize_empty_key_blocks(), coinbase_tx.get_tx_id(), false, 0)],
+            vec![TxOutput::new(keypairs[1].pub_key.clone(), 40), TxOutput::new(keypairs[0].pub_key.clone(), 60)],
+        );
+        split_tx.inputs[0].signature = keypairs[0].create_signature(&split_tx).into();
+        let split_block: Block = Block::new(&[split_tx.clone()], coinbase_block.block_header.hash_block().unwrap(), 0);
+
+        // spend the first output of the split transaction, and then the second one
+        let mut first_spend_tx: Tx = Tx::new(
+            vec![TxInput::new(initialize_empty_key_blocks(), split_tx.get_tx_id(), false, 0)],
+            vec![TxOutput::new(keypairs[0].pub_key.clone(), 40)],
+        );
+        first_spend_tx.inputs[0].signature = keypairs[1].create_signature(&first_spend_tx).into();
+        let first_spend_block: Block = Block::new(&[first_spend_tx.clone()], split_block.block_header.hash_block().unwrap(), 0);
+
+        let mut second_spend_tx: Tx = Tx::new(
+            vec![TxInput::new(initialize_empty_key_blocks(), split_tx.get_tx_id(), false, 1)],
+            vec![TxOutput::new(keypairs[1].pub_key.clone(), 60)],
+        );
+        second_spend_tx.inputs[0].signature = keypairs[0].create_signature(&second_spend_tx).into();
+        let second_spend_block: Block = Block::new(&[second_spend_tx.clone()], first_spend_block.block_header.hash_block().unwrap(), 0);
+
+        let mut utxo: UtxoSet = UtxoSet::new();
+        assert_eq!(utxo.len(), 0);
+        utxo.apply_block(&coinbase_block, 0).expect("Could not apply the coinbase block");
+        utxo.apply_block(&split_block, 1).expect("Could not apply the split block");
+        assert_eq!(utxo.len(), 2);
+
+        let first_spend_undo = utxo.apply_block(&first_spend_block, 2).expect("Could not spend the first output");
+
+        // spending the first output must not shift the index of the second one
+        assert!(second_spend_tx.verify_transaction(&utxo), "The second output is no longer spendable after the first one was spent");
+        let second_spend_undo = utxo.apply_block(&second_spend_block, 3).expect("Could not spend the second output");
+        assert!(utxo.get(&OutPoint::new(split_tx.get_tx_id(), 1)).is_none());
+
+        // an output cannot be spent twice
+        assert!(utxo.apply_block(&second_spend_block, 4).is_err());
+        assert_eq!(utxo.len(), 2);
+
+        // undoing the blocks puts the spent outputs back and removes the created ones
+        utxo.undo_block(&second_spend_block, &second_spend_undo);
+        utxo.undo_block(&first_spend_block, &first_spend_undo);
+        assert_eq!(utxo.len(), 2);
+        assert_eq!(utxo.get(&OutPoint::new(split_tx.get_tx_id(), 0)).unwrap().output.amount, 40);
+        assert_eq!(utxo.get(&OutPoint::new(split_tx.get_tx_id(), 1)).unwrap().output.amount, 60);
+        assert!(utxo.get(&OutPoint::new(first_spend_tx.get_tx_id(), 0)).is_none());
+    }
+}
@@ -0,0 +1,560 @@
+pub mod seed;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::classes::block::blockchain::Blockchain;
+use crate::classes::lamport_signature::key_pair::KeyPair;
+use crate::classes::signature::scheme::{PublicKey, Signature, SignatureScheme};
+use crate::classes::transaction::mempool::MempoolError;
+use crate::classes::transaction::tx::{Tx, TxInput, TxOutput};
+use crate::classes::transaction::utxo_set::{Balance, OutPoint, UtxoEntry};
+use crate::classes::winternitz_signature::key_pair::{is_supported_winternitz_parameter, WinternitzKeyPair, DEFAULT_WINTERNITZ_PARAMETER, WINTERNITZ_PARAMETERS};
+use crate::util::disk::save_wallet_to_file;
+
+// restoring the keys of an account stops after this many derived keys in a row that the valid chain has never seen
+pub const RESTORE_GAP_LIMIT: u32 = 20;
+// Winternitz keys make outputs and signatures far smaller than Lamport keys, so new wallets derive them unless they are set up otherwise
+pub const DEFAULT_KEY_SCHEME: SignatureScheme = SignatureScheme::Winternitz { winternitz_parameter: DEFAULT_WINTERNITZ_PARAMETER };
+
+#[derive(Debug)]
+pub enum WalletError {
+    UnknownAccount(usize),
+    InsufficientFunds { spendable: u64, amount: u64 },
+    Rejected(MempoolError),
+    NotSaved,
+    // the wallet can only derive one-time keys
+    UnsupportedKeyScheme(SignatureScheme),
+}
+
+impl fmt::Display for WalletError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WalletError::UnknownAccount(account_index) => write!(f, "Account #{} does not exist", account_index),
+            WalletError::InsufficientFunds { spendable, amount } => write!(f, "Not enough spendable money ({} can be spent, {} is needed -- immature coinbase outputs cannot be spent yet)", spendable, amount),
+            WalletError::Rejected(mempool_error) => write!(f, "The transaction was not added to the mempool: {}", mempool_error),
+            WalletError::NotSaved => write!(f, "Could not save the wallet, so the transaction was not sent"),
+            WalletError::UnsupportedKeyScheme(key_scheme) => write!(f, "The wallet cannot derive {} keys", key_scheme),
+        }
+    }
+}
+
+// who a transaction pays: a given key (of any signature scheme, e.g. the Merkle key of the node), or a fresh key generated for one of the accounts of this wallet
+#[allow(clippy::large_enum_variant)]
+pub enum Recipient {
+    Key(PublicKey),
+    Account(usize),
+}
+
+/* The key pair of a wallet key, in the one-time signature scheme it was generated with: the root keys of keypairs.bin are Lamport keys,
+and the keys derived from the master seed use the scheme the wallet is set up with. */
+#[derive(Clone, Serialize, Deserialize)]
+#[allow(clippy::large_enum_variant)]
+pub enum WalletKeyPair {
+    Lamport(KeyPair),
+    Winternitz(WinternitzKeyPair),
+}
+
+impl WalletKeyPair {
+    // derives the key pair at the path account_index/key_index from a master seed, or gives None for a scheme that is not a supported one-time scheme
+    pub fn from_seed(master_seed: &[u8; 32], account_index: u32, key_index: u32, key_scheme: SignatureScheme) -> Option<WalletKeyPair> {
+        match key_scheme {
+            SignatureScheme::Lamport => Some(WalletKeyPair::Lamport(KeyPair::from_seed(master_seed, account_index, key_index))),
+            SignatureScheme::Winternitz { winternitz_parameter } if is_supported_winternitz_parameter(winternitz_parameter) => {
+                Some(WalletKeyPair::Winternitz(WinternitzKeyPair::from_seed(master_seed, account_index, key_index, winternitz_parameter)))
+            },
+            _ => None,
+        }
+    }
+
+    pub fn pub_key(&self) -> PublicKey {
+        match self {
+            WalletKeyPair::Lamport(keypair) => keypair.pub_key.clone().into(),
+            WalletKeyPair::Winternitz(keypair) => keypair.pub_key.clone().into(),
+        }
+    }
+
+    pub fn create_signature(&self, tx: &Tx) -> Signature {
+        match self {
+            WalletKeyPair::Lamport(keypair) => keypair.create_signature(tx).into(),
+            WalletKeyPair::Winternitz(keypair) => keypair.create_signature(tx).into(),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WalletKey {
+    pub keypair: WalletKeyPair,
+    pub pub_key_hash: String,
+    pub account_index: usize,
+    // the index the key was derived at from the master seed, or None for a key imported from keypairs.bin
+    pub key_index: Option<u32>,
+    // a used key has already signed a transaction, so it must never sign again
+    pub is_used: bool,
+}
+
+/* Identifies the root keys of a wallet's accounts (the keys of keypairs.bin, in account order) without giving them away, so that a recovery can check
+that it is given the same keypairs.bin as the wallet it recovers. */
+pub fn root_keys_fingerprint(keypairs: &[KeyPair]) -> String {
+    let root_key_hashes: Vec<String> = keypairs.iter().map(|keypair| keypair.pub_key.hash_key()).collect();
+    fingerprint_key_hashes(&root_key_hashes)
+}
+
+fn fingerprint_key_hashes(pub_key_hashes: &[String]) -> String {
+    let mut hasher = Sha256::new();
+    for pub_key_hash in pub_key_hashes {
+        hasher.update(pub_key_hash.as_bytes());
+    }
+    hex::encode(&hasher.finalize()[..8])
+}
+
+/*
+    The one-time keys of every account of this node. An account starts with the Lamport key it has in keypairs.bin, and every output paid to the account
+    (change, payments from other accounts and mining payouts) goes to a fresh key derived for it from the master seed, at the next index of the account
+    and with the key scheme of the wallet, so that a key never receives money after it has signed. A key is fresh until it signs a transaction and used afterwards.
+    The wallet has to be saved to disk before a transaction signed by it is broadcast, so that a used key is never mistaken for a fresh one after a restart,
+    but every derived key can also be recovered from the master seed alone.
+    The root keys cannot be derived from the seed (the genesis block of every network pays the first key of keypairs.bin), so recovering a wallet takes
+    both its master seed and its keypairs.bin, which is checked against the root keys fingerprint exported along with the seed.
+*/
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Wallet {
+    seed: [u8; 32],
+    keys: Vec<WalletKey>,
+    account_count: usize,
+    // the index the next key of each account is derived at
+    next_key_indexes: Vec<u32>,
+    // the signature scheme new keys are derived with
+    key_scheme: SignatureScheme,
+}
+
+impl Wallet {
+    // a wallet with one account per key pair, deriving its new keys from the master seed
+    pub fn new(seed: [u8; 32], keypairs: &[KeyPair]) -> Wallet {
+        let mut wallet: Wallet = Wallet {
+            seed,
+            keys: vec![],
+            account_count: keypairs.len(),
+            next_key_indexes: vec![0; keypairs.len()],
+            key_scheme: DEFAULT_KEY_SCHEME,
+        };
+
+        for (account_index, keypair) in keypairs.iter().enumerate() {
+            wallet.add_key(WalletKeyPair::Lamport(keypair.clone()), account_index, None);
+        }
+
+        wallet
+    }
+
+    pub fn seed(&self) -> &[u8; 32] {
+        &self.seed
+    }
+
+    // the fingerprint of the keys the accounts of this wallet started with
+    pub fn root_keys_fingerprint(&self) -> String {
+        let root_key_hashes: Vec<String> = (0..self.account_count)
+            .filter_map(|account_index| self.keys.iter().find(|wallet_key| wallet_key.account_index == account_index && wallet_key.key_index.is_none()))
+            .map(|wallet_key| wallet_key.pub_key_hash.clone())
+            .collect();
+        fingerprint_key_hashes(&root_key_hashes)
+    }
+
+    pub fn key_scheme(&self) -> SignatureScheme {
+        self.key_scheme
+    }
+
+    // the keys derived from now on use the given scheme (the keys already derived keep theirs), which has to be a one-time scheme the wallet can derive keys with
+    pub fn set_key_scheme(&mut self, key_scheme: SignatureScheme) -> Result<(), WalletError> {
+        if WalletKeyPair::from_seed(&[0; 32], 0, 0, key_scheme).is_none() {
+            return Err(WalletError::UnsupportedKeyScheme(key_scheme));
+        }
+
+        self.key_scheme = key_scheme;
+        Ok(())
+    }
+
+    pub fn account_count(&self) -> usize {
+        self.account_count
+    }
+
+    pub fn account_keys(&self, account_index: usize) -> Vec<&WalletKey> {
+        self.keys.iter().filter(|wallet_key| wallet_key.account_index == account_index).collect()
+    }
+
+    pub fn find_key(&self, pub_key_hash: &str) -> Option<&WalletKey> {
+        self.keys.iter().find(|wallet_key| wallet_key.pub_key_hash == *pub_key_hash)
+    }
+
+    // derives the next fresh key of the account, with the key scheme of the wallet, and returns its public key
+    pub fn new_key(&mut self, account_index: usize) -> Result<PublicKey, WalletError> {
+        if account_index >= self.account_count {
+            return Err(WalletError::UnknownAccount(account_index));
+        }
+
+        let key_index: u32 = self.next_key_indexes[account_index];
+        let keypair: WalletKeyPair = WalletKeyPair::from_seed(&self.seed, account_index as u32, key_index, self.key_scheme)
+            .ok_or(WalletError::UnsupportedKeyScheme(self.key_scheme))?;
+        let pub_key: PublicKey = keypair.pub_key();
+        self.add_key(keypair, account_index, Some(key_index));
+        self.next_key_indexes[account_index] = key_index + 1;
+
+        Ok(pub_key)
+    }
+
+    /*
+        Derives the keys of every account past the last key the wallet knows about, and keeps the ones up to the last key the valid chain has seen
+        (a key that owns an unspent output or has signed a transaction). Since keys are derived in order, the search for an account stops after
+        RESTORE_GAP_LIMIT unseen keys in a row. Since the key scheme of the wallet may have changed along the way, the key of every one-time scheme is tried
+        at each index. This recovers a wallet from its master seed, along with any key derived but not saved before a crash. Returns how many keys were restored.
+    */
+    pub fn restore_keys(&mut self, blockchain: &Blockchain) -> usize {
+        let utxo_key_hashes: HashSet<String> = blockchain.utxo.iter().map(|(_outpoint, utxo_entry)| utxo_entry.output.pub_key.hash_key()).collect();
+        let mut restored_key_count: usize = 0;
+
+        // the key scheme of the wallet comes first, so that it is the one kept for an index the valid chain has not seen
+        let mut key_schemes: Vec<SignatureScheme> = vec![self.key_scheme];
+        let one_time_key_schemes = std::iter::once(SignatureScheme::Lamport)
+            .chain(WINTERNITZ_PARAMETERS.map(|winternitz_parameter| SignatureScheme::Winternitz { winternitz_parameter }));
+        key_schemes.extend(one_time_key_schemes.filter(|key_scheme| *key_scheme != self.key_scheme));
+
+        for account_index in 0..self.account_count {
+            let mut derived_keypairs: Vec<WalletKeyPair> = vec![];
+            let mut seen_key_count: usize = 0;
+
+            while derived_keypairs.len() - seen_key_count < RESTORE_GAP_LIMIT as usize {
+                let key_index: u32 = self.next_key_indexes[account_index] + derived_keypairs.len() as u32;
+                let mut candidate_keypairs: Vec<WalletKeyPair> = key_schemes.iter()
+                    .filter_map(|key_scheme| WalletKeyPair::from_seed(&self.seed, account_index as u32, key_index, *key_scheme))
+                    .collect();
+
+                let seen_candidate_index: Option<usize> = candidate_keypairs.iter().position(|keypair| {
+                    let pub_key_hash: String = keypair.pub_key().hash_key();
+                    utxo_key_hashes.contains(&pub_key_hash) || blockchain.utxo.signed_message(&pub_key_hash).is_some()
+                });
+                derived_keypairs.push(candidate_keypairs.swap_remove(seen_candidate_index.unwrap_or(0)));
+
+                if seen_candidate_index.is_some() {
+                    seen_key_count = derived_keypairs.len();
+                }
+            }
+
+            // the unseen keys before the last seen key are kept too, so that the next derived key is never one that was already handed out
+            for keypair in derived_keypairs.into_iter().take(seen_key_count) {
+                let key_index: u32 = self.next_key_indexes[account_index];
+                self.add_key(keypair, account_index, Some(key_index));
+                self.next_key_indexes[account_index] = key_index + 1;
+                restored_key_count += 1;
+            }
+        }
+
+        restored_key_count
+    }
+
+    // whether the key can still sign: neither this wallet nor the valid chain or the mempool has seen it sign
+    pub fn is_fresh(&self, wallet_key: &WalletKey, blockchain: &Blockchain) -> bool {
+        !wallet_key.is_used && !blockchain.has_key_signed(&wallet_key.pub_key_hash)
+    }
+
+    // the money the account owns, over all of its keys
+    pub fn balance(&self, account_index: usize, blockchain: &Blockchain) -> Balance {
+        let mut balance: Balance = Balance::default();
+        let next_block_height: u64 = blockchain.next_block_height();
+
+        for (_outpoint, utxo_entry) in self.account_utxos(account_index, blockchain) {
+            if utxo_entry.is_mature(next_block_height, blockchain.params.coinbase_maturity) {
+                balance.spendable += utxo_entry.output.amount;
+            } else {
+                balance.immature += utxo_entry.output.amount;
+            }
+        }
+
+        balance
+    }
+
+    /*
+        Builds and signs a transaction paying the amount from the account to the recipient. Since a key can only sign once, a key that is picked
+        spends every output it owns, so only fresh keys whose outputs are all spendable (mature and not spent in the mempool) are picked, oldest first.
+        Whatever is left over goes to a fresh change key. New keys are only generated once the transaction can be built. The keys that signed are marked as used,
+        so the wallet must be saved before the transaction is broadcast (which send_transaction does).
+    */
+    pub fn create_transaction(&mut self, account_index: usize, recipient: &Recipient, amount: u64, blockchain: &Blockchain) -> Result<Tx, WalletError> {
+        if account_index >= self.account_count {
+            return Err(WalletError::UnknownAccount(account_index));
+        }
+        if let Recipient::Account(recipient_account_index) = recipient {
+            if *recipient_account_index >= self.account_count {
+                return Err(WalletError::UnknownAccount(*recipient_account_index));
+            }
+        }
+
+        let next_block_height: u64 = blockchain.next_block_height();
+        let mut utxos_by_key: HashMap<String, Vec<(OutPoint, UtxoEntry)>> = HashMap::new();
+        for (outpoint, utxo_entry) in self.account_utxos(account_index, blockchain) {
+            utxos_by_key.entry(utxo_entry.output.pub_key.hash_key()).or_default().push((outpoint, utxo_entry));
+        }
+
+        let mut signing_key_indexes: Vec<usize> = vec![];
+        let mut tx_inputs: Vec<TxInput> = vec![];
+        let mut input_amount: u64 = 0;
+
+        for (key_index, wallet_key) in self.keys.iter().enumerate() {
+            if input_amount >= amount {
+                break;
+            }
+
+            if wallet_key.account_index != account_index || !self.is_fresh(wallet_key, blockchain) {
+                continue;
+            }
+
+            let key_utxos: &Vec<(OutPoint, UtxoEntry)> = match utxos_by_key.get(&wallet_key.pub_key_hash) {
+                Some(key_utxos) => key_utxos,
+                None => continue,
+            };
+
+            // an output that cannot be spent now would be left behind on a key that can no longer sign
+            let is_spendable: bool = key_utxos.iter().all(|(outpoint, utxo_entry)| {
+                utxo_entry.is_mature(next_block_height, blockchain.params.coinbase_maturity) && !blockchain.mempool.is_spent(outpoint)
+            });
+            if !is_spendable {
+                continue;
+            }
+
+            for (outpoint, utxo_entry) in key_utxos {
+                tx_inputs.push(TxInput::new(Signature::unsigned(utxo_entry.output.pub_key.scheme()), outpoint.tx_id.clone(), false, outpoint.index));
+                input_amount += utxo_entry.output.amount;
+            }
+            signing_key_indexes.push(key_index);
+        }
+
+        if input_amount < amount {
+            return Err(WalletError::InsufficientFunds { spendable: input_amount, amount });
+        }
+
+        let recipient_pub_key: PublicKey = match recipient {
+            Recipient::Key(pub_key) => pub_key.clone(),
+            Recipient::Account(recipient_account_index) => self.new_key(*recipient_account_index)?,
+        };
+
+        let mut tx_outputs: Vec<TxOutput> = vec![TxOutput::new(recipient_pub_key, amount)];
+        if input_amount > amount {
+            let change_pub_key: PublicKey = self.new_key(account_index)?;
+            tx_outputs.push(TxOutput::new(change_pub_key, input_amount - amount));
+        }
+
+        // each input is signed by the key that owns the output it spends, and every input signs the same message
+        let mut transaction: Tx = Tx::new(tx_inputs, tx_outputs);
+        let mut input_index: usize = 0;
+        for key_index in signing_key_indexes {
+            let wallet_key: &mut WalletKey = &mut self.keys[key_index];
+            let signature: Signature = wallet_key.keypair.create_signature(&transaction);
+            for _ in 0..utxos_by_key[&wallet_key.pub_key_hash].len() {
+                transaction.inputs[input_index].signature = signature.clone();
+                input_index += 1;
+            }
+            wallet_key.is_used = true;
+        }
+
+        Ok(transaction)
+    }
+
+    /*
+        Creates the transaction, saves the wallet and only then adds the transaction to the mempool, returning its id: the new keys must be on disk
+        before anyone can pay them, and the keys that signed must be marked as used on disk before their signature is out. If the wallet cannot be saved,
+        nothing is sent. If the mempool rejects the transaction, the wallet is put back as it was (and saved again), so that the keys that signed it
+        are still fresh and the keys generated for it are handed out again.
+    */
+    pub fn send_transaction(&mut self, account_index: usize, recipient: &Recipient, amount: u64, blockchain: &mut Blockchain, wallet_filename: &str) -> Result<String, WalletError> {
+        let previous_wallet: Wallet = self.clone();
+        let transaction: Tx = self.create_transaction(account_index, recipient, amount, blockchain)?;
+        if save_wallet_to_file(self, wallet_filename).is_err() {
+            *self = previous_wallet;
+            return Err(WalletError::NotSaved);
+        }
+
+        match blockchain.add_to_mempool(&transaction) {
+            Ok(tx_id) => Ok(tx_id),
+            Err(mempool_error) => {
+                // if the rollback cannot be saved, the wallet stays as it is on disk, where the keys that signed are only never picked again
+                let signed_wallet: Wallet = std::mem::replace(self, previous_wallet);
+                if save_wallet_to_file(self, wallet_filename).is_err() {
+                    *self = signed_wallet;
+                }
+                Err(WalletError::Rejected(mempool_error))
+            }
+        }
+    }
+
+    fn add_key(&mut self, keypair: WalletKeyPair, account_index: usize, key_index: Option<u32>) {
+        let pub_key_hash: String = keypair.pub_key().hash_key();
+        self.keys.push(WalletKey {
+            keypair,
+            pub_key_hash,
+            account_index,
+            key_index,
+            is_used: false,
+        });
+    }
+
+    // the unspent outputs of the valid chain that belong to one of the account's keys
+    fn account_utxos(&self, account_index: usize, blockchain: &Blockchain) -> Vec<(OutPoint, UtxoEntry)> {
+        let mut account_utxos: Vec<(OutPoint, UtxoEntry)> = vec![];
+        for (outpoint, utxo_entry) in blockchain.utxo.iter() {
+            let pub_key_hash: String = utxo_entry.output.pub_key.hash_key();
+            if self.find_key(&pub_key_hash).is_some_and(|wallet_key| wallet_key.account_index == account_index) {
+                account_utxos.push((outpoint.clone(), utxo_entry.clone()));
+            }
+        }
+
+        account_utxos
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::classes::lamport_signature::key_pair::{Key, KeyPair};
+    use crate::classes::signature::scheme::{PublicKey, SignatureScheme};
+    use crate::classes::transaction::mempool::{Mempool, MempoolError, MEMPOOL_EXPIRY};
+    use crate::classes::transaction::tx::Tx;
+    use crate::classes::transaction::utxo_set::OutPoint;
+    use crate::classes::wallet::seed::generate_seed;
+    use crate::util::disk::load_wallet_from_file;
+    use crate::util::test_util::{coinbase_tx, test_keypairs, TestChain};
+
+    use super::{root_keys_fingerprint, Recipient, Wallet, WalletError, WalletKey, DEFAULT_KEY_SCHEME};
+
+    // every key of the wallet, account by account
+    fn wallet_keys(wallet: &Wallet) -> Vec<&WalletKey> {
+        (0..wallet.account_count()).flat_map(|account_index| wallet.account_keys(account_index)).collect()
+    }
+
+    // mines a block with the given transactions on top of the valid chain, paying the reward to a fresh key of Account 0
+    fn mine_block(chain: &mut TestChain, wallet: &mut Wallet, txs: Vec<Tx>) {
+        let height: u64 = chain.blocks.len() as u64;
+        let payout_key: PublicKey = wallet.new_key(0).expect("Could not create a payout key");
+        let mut block_txs: Vec<Tx> = vec![coinbase_tx(height, 0, payout_key, 100)];
+        block_txs.extend(txs);
+        let tip_hash: String = chain.tip_hash();
+        chain.mine(block_txs, &tip_hash);
+        assert_eq!(chain.blocks.len() as u64, height + 1, "A block built by the wallet was rejected");
+    }
+
+    #[test]
+    fn change_keys() {
+        let keypairs: &[KeyPair] = test_keypairs();
+        let mut chain: TestChain = TestChain::with_params(&keypairs[0].pub_key, |params| {
+            params.coinbase_maturity = 0;
+        });
+        let mut wallet: Wallet = Wallet::new(generate_seed(), &keypairs[0..2]);
+        assert_eq!(wallet.account_count(), 2);
+        assert_eq!(wallet.balance(0, &chain).spendable, 100);
+
+        // the change of a payment goes to a fresh key, and the key that signed is used from now on
+        let tx_a: Tx = wallet.create_transaction(0, &Recipient::Account(1), 30, &chain).expect("Could not create a transaction");
+        assert_eq!(tx_a.outputs.len(), 2);
+        let change_key_hash: String = tx_a.outputs[1].pub_key.hash_key();
+        assert!(change_key_hash != keypairs[0].pub_key.hash_key(), "The change was sent back to the key that signed");
+        assert!(tx_a.outputs.iter().all(|tx_output| tx_output.pub_key.scheme() == DEFAULT_KEY_SCHEME), "The new keys were not derived with the key scheme of the wallet");
+        assert!(wallet.find_key(&change_key_hash).is_some_and(|wallet_key| wallet_key.account_index == 0 && !wallet_key.is_used));
+        assert!(wallet.find_key(&keypairs[0].pub_key.hash_key()).unwrap().is_used, "The key that signed was not marked as used");
+        assert!(chain.add_to_mempool(&tx_a).is_ok());
+        mine_block(&mut chain, &mut wallet, vec![tx_a]);
+        assert_eq!(wallet.balance(0, &chain).spendable, 170);
+        assert_eq!(wallet.balance(1, &chain).spendable, 30);
+
+        // the next payment is signed by fresh keys only (the change key and the payout key), so no key ever signs twice
+        let tx_b: Tx = wallet.create_transaction(0, &Recipient::Account(1), 150, &chain).expect("Could not create a second transaction");
+        assert!(chain.add_to_mempool(&tx_b).is_ok(), "A transaction signed by fresh keys was rejected");
+        mine_block(&mut chain, &mut wallet, vec![tx_b]);
+        assert_eq!(wallet.balance(0, &chain).spendable, 120);
+        let fresh_key_count: usize = wallet_keys(&wallet).iter().filter(|wallet_key| wallet.is_fresh(wallet_key, &chain)).count();
+        assert_eq!(fresh_key_count, wallet_keys(&wallet).len() - 3, "Only the three keys that signed should be used");
+
+        // a used key is never picked again, even if it is paid after signing
+        let used_key: Key = keypairs[0].pub_key.clone();
+        let tx_c: Tx = wallet.create_transaction(1, &Recipient::Key(used_key.clone().into()), 10, &chain).unwrap();
+        mine_block(&mut chain, &mut wallet, vec![tx_c]);
+        let tx_d: Tx = wallet.create_transaction(0, &Recipient::Account(0), 220, &chain).unwrap();
+        assert!(tx_d.inputs.iter().all(|tx_input| chain.utxo.get(&OutPoint::new(tx_input.prev_tx_id.clone(), tx_input.index)).unwrap().output.pub_key.hash_key() != used_key.hash_key()));
+
+        // no key is generated for a transaction that cannot be built
+        let key_count: usize = wallet_keys(&wallet).len();
+        assert!(matches!(wallet.create_transaction(0, &Recipient::Account(1), 1000, &chain), Err(WalletError::InsufficientFunds { .. })));
+        assert!(matches!(wallet.create_transaction(0, &Recipient::Account(5), 1, &chain), Err(WalletError::UnknownAccount(5))));
+        assert_eq!(wallet_keys(&wallet).len(), key_count);
+
+        // nothing is sent if the wallet cannot be saved first
+        let key_count: usize = wallet_keys(&wallet).len();
+        let fresh_key_hashes: Vec<String> = wallet_keys(&wallet).iter().filter(|wallet_key| !wallet_key.is_used).map(|wallet_key| wallet_key.pub_key_hash.clone()).collect();
+        let wallet_filename: String = chain.temp_dir.file("testwallet.bin");
+        let unwritable_filename: String = chain.temp_dir.file("missing_directory/testwallet.bin");
+        assert!(matches!(wallet.send_transaction(1, &Recipient::Account(0), 10, &mut chain, &unwritable_filename), Err(WalletError::NotSaved)));
+        assert!(wallet_keys(&wallet).len() == key_count && chain.mempool.len() == 0, "A transaction was sent without saving the wallet");
+
+        // a transaction the mempool rejects leaves the keys that signed it fresh, and hands out the same new keys again
+        let mempool: Mempool = std::mem::replace(&mut chain.mempool, Mempool::new(1, MEMPOOL_EXPIRY));
+        assert!(matches!(wallet.send_transaction(1, &Recipient::Account(0), 10, &mut chain, &wallet_filename), Err(WalletError::Rejected(MempoolError::MempoolFull))));
+        assert_eq!(wallet_keys(&wallet).len(), key_count, "The keys generated for a rejected transaction were kept");
+        assert!(fresh_key_hashes.iter().all(|pub_key_hash| !wallet.find_key(pub_key_hash).unwrap().is_used), "A key that signed a rejected transaction was marked as used");
+        assert_eq!(wallet_keys(&load_wallet_from_file(&wallet_filename).expect("Could not load the saved wallet")).len(), key_count, "The rolled back wallet was not saved");
+        chain.mempool = mempool;
+        assert!(wallet.send_transaction(1, &Recipient::Account(0), 10, &mut chain, &wallet_filename).is_ok());
+        assert_eq!(wallet_keys(&wallet).len(), key_count + 2);
+
+        // the wallet was saved before the transaction was sent, along with its new keys and which of them are used, so it survives a restart
+        let loaded_wallet: Wallet = load_wallet_from_file(&wallet_filename).expect("Could not load the saved wallet");
+        assert_eq!(wallet_keys(&loaded_wallet).len(), wallet_keys(&wallet).len());
+        for (loaded_key, wallet_key) in wallet_keys(&loaded_wallet).iter().zip(wallet_keys(&wallet)) {
+            assert!(loaded_key.pub_key_hash == wallet_key.pub_key_hash && loaded_key.is_used == wallet_key.is_used && loaded_key.account_index == wallet_key.account_index);
+        }
+    }
+
+    #[test]
+    fn restore_keys_from_seed() {
+        let keypairs: &[KeyPair] = test_keypairs();
+        let seed: [u8; 32] = generate_seed();
+
+        // a wallet that made a few payments
+        let mut chain: TestChain = TestChain::with_params(&keypairs[0].pub_key, |params| params.coinbase_maturity = 0);
+        let mut wallet: Wallet = Wallet::new(seed, &keypairs[0..2]);
+        assert!(matches!(wallet.set_key_scheme(SignatureScheme::Merkle { height: 4 }), Err(WalletError::UnsupportedKeyScheme(_))), "The wallet was set up to derive keys that are not one-time keys");
+        assert!(matches!(wallet.set_key_scheme(SignatureScheme::Winternitz { winternitz_parameter: 8 }), Err(WalletError::UnsupportedKeyScheme(_))));
+        for (sender_index, amount) in [(0, 60), (1, 25), (0, 30)] {
+            // the keys derived from the second payment on are Lamport keys, which the recovered wallet has to find as well
+            if sender_index == 1 {
+                assert!(wallet.set_key_scheme(SignatureScheme::from_name("Lamport").unwrap()).is_ok());
+            }
+            let tx: Tx = wallet.create_transaction(sender_index, &Recipient::Account(1 - sender_index), amount, &chain).expect("Could not create a transaction");
+            assert!(chain.add_to_mempool(&tx).is_ok());
+            mine_block(&mut chain, &mut wallet, vec![tx]);
+        }
+
+        // recovering the wallet takes the same keypairs.bin, which the fingerprint tells apart from any other one
+        assert_eq!(wallet.root_keys_fingerprint(), root_keys_fingerprint(&keypairs[0..2]));
+        assert!(root_keys_fingerprint(&keypairs[1..3]) != wallet.root_keys_fingerprint());
+        assert!(root_keys_fingerprint(&[keypairs[1].clone(), keypairs[0].clone()]) != wallet.root_keys_fingerprint(), "The fingerprint does not depend on the order of the accounts");
+
+        // a wallet recovered from the same seed finds every derived key that the chain has seen, and derives the same keys next
+        let mut recovered_wallet: Wallet = Wallet::new(seed, &keypairs[0..2]);
+        assert_eq!(recovered_wallet.balance(0, &chain).spendable, 0);
+        assert!(recovered_wallet.restore_keys(&chain) > 0, "No key was restored from the seed");
+        for account_index in 0..2 {
+            assert_eq!(recovered_wallet.balance(account_index, &chain).spendable, wallet.balance(account_index, &chain).spendable, "The recovered wallet does not have the same balance");
+        }
+        assert_eq!(recovered_wallet.restore_keys(&chain), 0);
+        assert!(recovered_wallet.set_key_scheme(wallet.key_scheme()).is_ok());
+        assert_eq!(recovered_wallet.new_key(0).unwrap().hash_key(), wallet.new_key(0).unwrap().hash_key(), "The recovered wallet does not derive the same next key");
+
+        // the key scheme of the wallet is picked by name
+        assert!(SignatureScheme::from_name("winternitz") == Some(DEFAULT_KEY_SCHEME));
+        assert!(SignatureScheme::from_name(" Winternitz 256 ") == Some(SignatureScheme::Winternitz { winternitz_parameter: 256 }));
+        assert!(SignatureScheme::from_name("winternitz 8").is_none() && SignatureScheme::from_name("lamport 16").is_none() && SignatureScheme::from_name("merkle").is_none());
+
+        // and can spend with them
+        let spendable: u64 = recovered_wallet.balance(0, &chain).spendable;
+        let tx: Tx = recovered_wallet.create_transaction(0, &Recipient::Account(1), spendable, &chain).expect("The recovered wallet could not create a transaction");
+        assert!(chain.add_to_mempool(&tx).is_ok(), "A transaction signed by recovered keys was rejected");
+    }
+}
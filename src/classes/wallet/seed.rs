@@ -0,0 +1,98 @@
+use rand::Rng;
+use sha2::{Digest, Sha256};
+
+// the BIP39 English word list, one word per line, in alphabetical order
+const WORD_LIST: &str = include_str!("english.txt");
+// 256 bits of seed plus 8 bits of checksum, 11 bits per word
+pub const MNEMONIC_WORD_COUNT: usize = 24;
+
+pub fn generate_seed() -> [u8; 32] {
+    let mut rng = rand::thread_rng();
+    rng.gen()
+}
+
+pub fn seed_to_hex(seed: &[u8; 32]) -> String {
+    hex::encode(seed)
+}
+
+pub fn seed_from_hex(seed_hex: &str) -> Option<[u8; 32]> {
+    let seed_bytes: Vec<u8> = hex::decode(seed_hex.trim()).ok()?;
+    seed_bytes.try_into().ok()
+}
+
+/* Encodes the seed as 24 words, the same way BIP39 encodes 256 bits of entropy: the first byte of the SHA256 hash of the seed is appended as a checksum,
+and every 11 bits pick a word of the word list. The words are easier to write down than the hex seed, and the checksum catches most typos. */
+pub fn seed_to_mnemonic(seed: &[u8; 32]) -> String {
+    let words: Vec<&str> = WORD_LIST.lines().collect();
+    let checksum: u8 = Sha256::digest(seed)[0];
+    let bytes: Vec<u8> = [seed.as_slice(), &[checksum]].concat();
+
+    let mut mnemonic_words: Vec<&str> = vec![];
+    for word_index in 0..MNEMONIC_WORD_COUNT {
+        let mut word_number: usize = 0;
+        for bit_index in word_index * 11..(word_index + 1) * 11 {
+            let bit: u8 = (bytes[bit_index / 8] >> (7 - bit_index % 8)) & 1;
+            word_number = (word_number << 1) | bit as usize;
+        }
+        mnemonic_words.push(words[word_number]);
+    }
+
+    mnemonic_words.join(" ")
+}
+
+// the seed encoded by the mnemonic, or None if a word is unknown, the number of words is wrong or the checksum does not match
+pub fn seed_from_mnemonic(mnemonic: &str) -> Option<[u8; 32]> {
+    let words: Vec<&str> = WORD_LIST.lines().collect();
+    let mnemonic_words: Vec<String> = mnemonic.split_whitespace().map(|word| word.to_lowercase()).collect();
+    if mnemonic_words.len() != MNEMONIC_WORD_COUNT {
+        return None;
+    }
+
+    let mut bytes: [u8; 33] = [0; 33];
+    for (word_index, mnemonic_word) in mnemonic_words.iter().enumerate() {
+        let word_number: usize = words.binary_search(&mnemonic_word.as_str()).ok()?;
+        for bit_offset in 0..11 {
+            let bit_index: usize = word_index * 11 + bit_offset;
+            if (word_number >> (10 - bit_offset)) & 1 == 1 {
+                bytes[bit_index / 8] |= 1 << (7 - bit_index % 8);
+            }
+        }
+    }
+
+    let seed: [u8; 32] = bytes[0..32].try_into().ok()?;
+    if Sha256::digest(seed)[0] != bytes[32] {
+        return None;
+    }
+
+    Some(seed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_seed, seed_from_hex, seed_from_mnemonic, seed_to_hex, seed_to_mnemonic};
+
+    #[test]
+    fn mnemonic_and_hex_encoding() {
+        // the mnemonic encoding matches the 256-bit test vectors of BIP39
+        let test_vectors: Vec<(u8, &str)> = vec![
+            (0x00, "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon art"),
+            (0x7f, "legal winner thank year wave sausage worth useful legal winner thank year wave sausage worth useful legal winner thank year wave sausage worth title"),
+            (0xff, "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo vote"),
+        ];
+        for (seed_byte, mnemonic) in test_vectors {
+            assert_eq!(seed_to_mnemonic(&[seed_byte; 32]), mnemonic, "The mnemonic does not match the test vector");
+            assert!(seed_from_mnemonic(mnemonic) == Some([seed_byte; 32]));
+        }
+
+        // a mnemonic with a wrong checksum, an unknown word or a missing word is rejected
+        let zero_seed_words: String = "abandon ".repeat(24);
+        assert!(seed_from_mnemonic(&zero_seed_words).is_none(), "A mnemonic with a wrong checksum was accepted");
+        assert!(seed_from_mnemonic(&zero_seed_words.replacen("abandon", "abandonment", 1)).is_none());
+        assert!(seed_from_mnemonic(&"abandon ".repeat(23)).is_none());
+
+        let seed: [u8; 32] = generate_seed();
+        assert!(seed_from_mnemonic(&seed_to_mnemonic(&seed)) == Some(seed));
+        assert!(seed_from_hex(&seed_to_hex(&seed)) == Some(seed));
+        assert!(seed_from_hex("abcd").is_none());
+    }
+}
@@ -0,0 +1,286 @@
+use sha2::{Digest, Sha256};
+
+use crate::Tx;
+use bincode;
+use serde::{Serialize, Deserialize};
+
+// the Winternitz parameters a key can be created with: a larger parameter makes signatures smaller but signing and verifying slower
+pub const WINTERNITZ_PARAMETERS: [u16; 3] = [4, 16, 256];
+pub const DEFAULT_WINTERNITZ_PARAMETER: u16 = 16;
+
+pub fn is_supported_winternitz_parameter(winternitz_parameter: u16) -> bool {
+    WINTERNITZ_PARAMETERS.contains(&winternitz_parameter)
+}
+
+/* The number of hash chains of a key with the given Winternitz parameter w: the 256 bits of the message are split into base-w digits,
+and a checksum of the message digits is appended in base w as well, so that an attacker cannot move any chain forward without moving another one back. */
+pub fn chain_counts(winternitz_parameter: u16) -> (usize, usize) {
+    let bits_per_digit: usize = winternitz_parameter.trailing_zeros() as usize;
+    let message_chain_count: usize = 256 / bits_per_digit;
+    let max_checksum: usize = message_chain_count * (winternitz_parameter as usize - 1);
+    let checksum_chain_count: usize = (usize::BITS - 1 - max_checksum.leading_zeros()) as usize / bits_per_digit + 1;
+    (message_chain_count, checksum_chain_count)
+}
+
+/*
+    A Winternitz one-time key pair (WOTS+). The private key is one secret value per hash chain, and the public key is the end of every chain,
+    w - 1 hashing steps further. Signing a base-w digit d reveals the value d steps into its chain, and the verifier hashes it the remaining w - 1 - d steps
+    to get back to the end of the chain. Each step is keyed with the public seed of the key, the index of the chain and the step, so that hashing for one key
+    does not help an attacker against any other key. Only the hash of all of the chain ends is published, and the signature is enough to recompute it.
+    Like a Lamport key, a Winternitz key must only ever sign once.
+*/
+#[derive(Serialize, Deserialize, Clone)]
+pub struct WinternitzKeyPair {
+    // the secret every private chain start is derived from
+    priv_seed: [u8; 32],
+    pub pub_key: WinternitzKey,
+}
+
+impl WinternitzKeyPair {
+    // a key pair that is not derived from a seed (the wallet derives its keys, so this is only used to test the scheme)
+    #[cfg(test)]
+    pub fn new(winternitz_parameter: u16) -> Self {
+        use rand::Rng;
+
+        let mut rng = rand::thread_rng();
+        let priv_seed: [u8; 32] = rng.gen();
+        WinternitzKeyPair::from_priv_seed(priv_seed, winternitz_parameter)
+    }
+
+    // derives the key pair at the path account_index/key_index from a master seed, the same way Lamport key pairs are derived but with a different domain
+    pub fn from_seed(master_seed: &[u8; 32], account_index: u32, key_index: u32, winternitz_parameter: u16) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(b"winternitz-key");
+        hasher.update(master_seed);
+        hasher.update(account_index.to_be_bytes());
+        hasher.update(key_index.to_be_bytes());
+        let priv_seed: [u8; 32] = hasher.finalize().into();
+        WinternitzKeyPair::from_priv_seed(priv_seed, winternitz_parameter)
+    }
+
+    fn from_priv_seed(priv_seed: [u8; 32], winternitz_parameter: u16) -> Self {
+        assert!(is_supported_winternitz_parameter(winternitz_parameter), "Unsupported Winternitz parameter {}", winternitz_parameter);
+
+        let mut public_seed_hasher = Sha256::new();
+        public_seed_hasher.update(b"winternitz-public-seed");
+        public_seed_hasher.update(priv_seed);
+        let public_seed: [u8; 32] = public_seed_hasher.finalize().into();
+
+        let mut keypair: WinternitzKeyPair = WinternitzKeyPair {
+            priv_seed,
+            pub_key: WinternitzKey {
+                winternitz_parameter,
+                public_seed,
+                chain_ends_hash: [0; 32],
+            },
+        };
+
+        let (message_chain_count, checksum_chain_count) = chain_counts(winternitz_parameter);
+        let chain_ends: Vec<[u8; 32]> = (0..message_chain_count + checksum_chain_count)
+            .map(|chain_index| keypair.pub_key.hash_chain(chain_index, keypair.chain_start(chain_index), 0, winternitz_parameter as usize - 1))
+            .collect();
+        keypair.pub_key.chain_ends_hash = keypair.pub_key.hash_chain_ends(&chain_ends);
+
+        keypair
+    }
+
+    pub fn create_signature(&self, tx: &Tx) -> WinternitzSignature {
+        let digits: Vec<usize> = self.pub_key.message_digits(&tx.get_tx_hash());
+
+        // each chain is revealed as far as the digit it signs
+        let chain_values: Vec<[u8; 32]> = digits.iter().enumerate()
+            .map(|(chain_index, digit)| self.pub_key.hash_chain(chain_index, self.chain_start(chain_index), 0, *digit))
+            .collect();
+
+        WinternitzSignature {
+            winternitz_parameter: self.pub_key.winternitz_parameter,
+            chain_values,
+        }
+    }
+
+    fn chain_start(&self, chain_index: usize) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(self.priv_seed);
+        hasher.update((chain_index as u16).to_be_bytes());
+        hasher.finalize().into()
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WinternitzKey {
+    pub winternitz_parameter: u16,
+    pub public_seed: [u8; 32],
+    // the hash of the public seed and the end of every chain
+    pub chain_ends_hash: [u8; 32],
+}
+
+impl WinternitzKey {
+    pub fn hash_key(&self) -> String {
+        let bytes: Vec<u8> = match bincode::serialize(self) {
+            Ok(val) => val,
+            Err(_e) => {
+                println!("Error! Could not convert key to bytes");
+                vec![]
+            }
+        };
+
+        let mut hasher = Sha256::new();
+        hasher.update(&bytes);
+        let result = hasher.finalize();
+        let hex_string: String = hex::encode(result);
+        hex_string
+    }
+
+    // whether the signature was made by this key over the given transaction hash
+    pub fn verify(&self, tx_hash: &str, signature: &WinternitzSignature) -> bool {
+        if !is_supported_winternitz_parameter(self.winternitz_parameter) || signature.winternitz_parameter != self.winternitz_parameter {
+            return false;
+        }
+
+        let digits: Vec<usize> = self.message_digits(tx_hash);
+        if signature.chain_values.len() != digits.len() {
+            return false;
+        }
+
+        // finish every chain the signature started, which only gives back the chain ends of this key if the digits were signed by it
+        let last_step: usize = self.winternitz_parameter as usize - 1;
+        let chain_ends: Vec<[u8; 32]> = digits.iter().zip(&signature.chain_values).enumerate()
+            .map(|(chain_index, (digit, chain_value))| self.hash_chain(chain_index, *chain_value, *digit, last_step - digit))
+            .collect();
+
+        self.hash_chain_ends(&chain_ends) == self.chain_ends_hash
+    }
+
+    // the base-w digits of the transaction hash, followed by the base-w digits of their checksum
+    fn message_digits(&self, tx_hash: &str) -> Vec<usize> {
+        let winternitz_parameter: usize = self.winternitz_parameter as usize;
+        let bits_per_digit: usize = winternitz_parameter.trailing_zeros() as usize;
+        let (_message_chain_count, checksum_chain_count) = chain_counts(self.winternitz_parameter);
+
+        let mut digits: Vec<usize> = vec![];
+        for byte in hex::decode(tx_hash).expect("Invalid hex string...") {
+            for digit_index in (0..8 / bits_per_digit).rev() {
+                digits.push((byte as usize >> (digit_index * bits_per_digit)) & (winternitz_parameter - 1));
+            }
+        }
+
+        let checksum: usize = digits.iter().map(|digit| winternitz_parameter - 1 - digit).sum();
+        for digit_index in (0..checksum_chain_count).rev() {
+            digits.push((checksum >> (digit_index * bits_per_digit)) & (winternitz_parameter - 1));
+        }
+
+        digits
+    }
+
+    // hashes the value from the given step of a chain for the given number of steps
+    fn hash_chain(&self, chain_index: usize, value: [u8; 32], start_step: usize, step_count: usize) -> [u8; 32] {
+        let mut chain_value: [u8; 32] = value;
+        for step in start_step..start_step + step_count {
+            let mut hasher = Sha256::new();
+            hasher.update(self.public_seed);
+            hasher.update((chain_index as u16).to_be_bytes());
+            hasher.update((step as u16).to_be_bytes());
+            hasher.update(chain_value);
+            chain_value = hasher.finalize().into();
+        }
+
+        chain_value
+    }
+
+    fn hash_chain_ends(&self, chain_ends: &Vec<[u8; 32]>) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(self.public_seed);
+        for chain_end in chain_ends {
+            hasher.update(chain_end);
+        }
+        hasher.finalize().into()
+    }
+}
+
+// one value per hash chain, revealed as far into the chain as the digit it signs
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WinternitzSignature {
+    pub winternitz_parameter: u16,
+    pub chain_values: Vec<[u8; 32]>,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::classes::lamport_signature::key_pair::KeyPair;
+    use crate::classes::signature::scheme::{PublicKey, Signature, SignatureScheme};
+    use crate::classes::transaction::tx::Tx;
+    use crate::util::test_util::{coinbase_tx, spend_tx, test_keypairs, unsigned_spend_tx, TestChain};
+
+    use super::{chain_counts, WinternitzKeyPair, WinternitzSignature, DEFAULT_WINTERNITZ_PARAMETER, WINTERNITZ_PARAMETERS};
+
+    #[test]
+    fn sign_and_verify() {
+        let keypairs: &[KeyPair] = test_keypairs();
+        assert!(chain_counts(4) == (128, 5) && chain_counts(16) == (64, 3) && chain_counts(256) == (32, 2), "Wrong number of hash chains");
+
+        let message_tx: Tx = coinbase_tx(1, 0, keypairs[0].pub_key.clone(), 100);
+        let other_message_tx: Tx = coinbase_tx(1, 0, keypairs[0].pub_key.clone(), 99);
+        let lamport_signature_size: usize = bincode::serialize(&Signature::from(keypairs[0].create_signature(&message_tx))).unwrap().len();
+        let lamport_key_size: usize = bincode::serialize(&PublicKey::from(keypairs[0].pub_key.clone())).unwrap().len();
+
+        for winternitz_parameter in WINTERNITZ_PARAMETERS {
+            let keypair: WinternitzKeyPair = WinternitzKeyPair::new(winternitz_parameter);
+            let signature: WinternitzSignature = keypair.create_signature(&message_tx);
+            assert!(keypair.pub_key.verify(&message_tx.get_tx_hash(), &signature), "A valid Winternitz signature was rejected with w = {}", winternitz_parameter);
+            assert!(!keypair.pub_key.verify(&other_message_tx.get_tx_hash(), &signature), "A Winternitz signature verified another message");
+            assert!(!WinternitzKeyPair::new(winternitz_parameter).pub_key.verify(&message_tx.get_tx_hash(), &signature), "A Winternitz signature verified with another key");
+
+            let mut tampered_signature: WinternitzSignature = signature.clone();
+            tampered_signature.chain_values[0][0] ^= 1;
+            assert!(!keypair.pub_key.verify(&message_tx.get_tx_hash(), &tampered_signature));
+            let mut truncated_signature: WinternitzSignature = signature.clone();
+            truncated_signature.chain_values.pop();
+            assert!(!keypair.pub_key.verify(&message_tx.get_tx_hash(), &truncated_signature));
+
+            // a Winternitz signature is smaller than a Lamport one (about 4 times smaller with w = 16), and so is its public key by far
+            assert!(bincode::serialize(&Signature::from(signature)).unwrap().len() < lamport_signature_size);
+            assert!(bincode::serialize(&PublicKey::from(keypair.pub_key.clone())).unwrap().len() * 100 < lamport_key_size);
+        }
+
+        // keys derived from a seed are the same every time
+        let seed: [u8; 32] = [7; 32];
+        assert_eq!(WinternitzKeyPair::from_seed(&seed, 0, 0, DEFAULT_WINTERNITZ_PARAMETER).pub_key.hash_key(), WinternitzKeyPair::from_seed(&seed, 0, 0, DEFAULT_WINTERNITZ_PARAMETER).pub_key.hash_key());
+        assert!(WinternitzKeyPair::from_seed(&seed, 0, 0, DEFAULT_WINTERNITZ_PARAMETER).pub_key.hash_key() != WinternitzKeyPair::from_seed(&seed, 0, 1, DEFAULT_WINTERNITZ_PARAMETER).pub_key.hash_key());
+    }
+
+    #[test]
+    fn spend_winternitz_output() {
+        let keypairs: &[KeyPair] = test_keypairs();
+        let mut chain: TestChain = TestChain::with_params(&keypairs[0].pub_key, |params| {
+            params.coinbase_maturity = 0;
+        });
+        let genesis_hash: String = chain.genesis_hash();
+        let genesis_coinbase_id: String = chain.blocks[0].txs.base[0].get_tx_id();
+
+        // a Lamport key pays a Winternitz key
+        let winternitz_keypair: WinternitzKeyPair = WinternitzKeyPair::new(DEFAULT_WINTERNITZ_PARAMETER);
+        let lamport_tx: Tx = spend_tx(&[(genesis_coinbase_id, 0)], &keypairs[0], winternitz_keypair.pub_key.clone(), &[100]);
+        assert!(lamport_tx.outputs[0].pub_key.scheme() == SignatureScheme::Winternitz { winternitz_parameter: DEFAULT_WINTERNITZ_PARAMETER });
+        let block_1_hash: String = chain.mine(vec![coinbase_tx(1, 0, keypairs[0].pub_key.clone(), 100), lamport_tx.clone()], &genesis_hash).block_header.hash_block().unwrap();
+        assert_eq!(chain.blocks.len(), 2);
+
+        // the Winternitz output can only be spent with a signature of its own scheme and parameter
+        let recipient_keypair: WinternitzKeyPair = WinternitzKeyPair::new(DEFAULT_WINTERNITZ_PARAMETER);
+        let unsigned_tx: Tx = unsigned_spend_tx(&[(lamport_tx.get_tx_id(), 0)], SignatureScheme::Winternitz { winternitz_parameter: DEFAULT_WINTERNITZ_PARAMETER }, recipient_keypair.pub_key.clone(), &[100]);
+        let mut lamport_signed_tx: Tx = unsigned_tx.clone();
+        lamport_signed_tx.inputs[0].signature = keypairs[0].create_signature(&unsigned_tx).into();
+        assert!(!lamport_signed_tx.verify_transaction(&chain.utxo), "A Lamport signature spent a Winternitz output");
+        let mut other_parameter_tx: Tx = unsigned_tx.clone();
+        other_parameter_tx.inputs[0].signature = WinternitzKeyPair::new(4).create_signature(&unsigned_tx).into();
+        assert!(!other_parameter_tx.verify_transaction(&chain.utxo), "A signature with another Winternitz parameter spent a Winternitz output");
+        let mut winternitz_tx: Tx = unsigned_tx.clone();
+        winternitz_tx.inputs[0].signature = winternitz_keypair.create_signature(&unsigned_tx).into();
+        assert!(chain.add_to_mempool(&winternitz_tx).is_ok(), "A valid Winternitz signature was rejected by the mempool");
+        assert!(winternitz_tx.convert_tx_to_bytes().len() * 3 < lamport_tx.convert_tx_to_bytes().len());
+
+        chain.mine(vec![coinbase_tx(2, 0, keypairs[0].pub_key.clone(), 100), winternitz_tx.clone()], &block_1_hash);
+        assert_eq!(chain.blocks.len(), 3, "A block spending a Winternitz output was rejected");
+        // a Winternitz key is a one-time key too
+        assert!(chain.utxo.signed_message(&winternitz_keypair.pub_key.hash_key()) == Some(&winternitz_tx.get_tx_hash()));
+    }
+}
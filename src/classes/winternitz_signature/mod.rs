@@ -0,0 +1 @@
+pub mod key_pair;
\ No newline at end of file
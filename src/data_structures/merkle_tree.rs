@@ -10,7 +10,7 @@ pub struct MerkleTree {
 }
 
 impl MerkleTree {
-    pub fn new(tx_data: &Vec<Tx>) -> MerkleTree{
+    pub fn new(tx_data: &[Tx]) -> MerkleTree{
         let mut merkle_data: Vec<Vec<[u8; 32]>> = Vec::new();
 
         let mut prev_is_pair: bool = false;
@@ -100,7 +100,7 @@ impl MerkleTree {
         return MerkleTree {
             merkle_root: merkle_root_hex_string,
             data: merkle_data.clone(),
-            base: tx_data.clone(),
+            base: tx_data.to_vec(),
         };
     }
 }
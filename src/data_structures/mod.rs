@@ -1 +1,2 @@
-pub mod merkle_tree;
\ No newline at end of file
+pub mod merkle_tree;
+pub mod uint256;
\ No newline at end of file
@@ -0,0 +1,207 @@
+use std::cmp::Ordering;
+
+/* An unsigned 256-bit integer, used to compare block hashes against targets and to compute the work of a target.
+It is stored as four u64 limbs, with the least significant limb first. */
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct U256 {
+    limbs: [u64; 4],
+}
+
+impl U256 {
+    pub fn zero() -> U256 {
+        U256 { limbs: [0; 4] }
+    }
+
+    pub fn one() -> U256 {
+        U256::from_u64(1)
+    }
+
+    pub fn from_u64(value: u64) -> U256 {
+        U256 { limbs: [value, 0, 0, 0] }
+    }
+
+    // interpret 32 bytes (such as a SHA-256 hash) as a big-endian number
+    pub fn from_be_bytes(bytes: &[u8; 32]) -> U256 {
+        let mut limbs: [u64; 4] = [0; 4];
+        for (limb_index, limb) in limbs.iter_mut().enumerate() {
+            let start: usize = 32 - (limb_index + 1) * 8;
+            *limb = u64::from_be_bytes(bytes[start..start + 8].try_into().expect("slice with incorrect length"));
+        }
+
+        U256 { limbs }
+    }
+
+    #[cfg(test)]
+    pub fn to_be_bytes(self) -> [u8; 32] {
+        let mut bytes: [u8; 32] = [0; 32];
+        for (limb_index, limb) in self.limbs.iter().enumerate() {
+            let start: usize = 32 - (limb_index + 1) * 8;
+            bytes[start..start + 8].copy_from_slice(&limb.to_be_bytes());
+        }
+
+        bytes
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.iter().all(|limb| *limb == 0)
+    }
+
+    // the number of bits needed to represent the number (0 for zero)
+    pub fn bits(&self) -> u32 {
+        for limb_index in (0..4).rev() {
+            if self.limbs[limb_index] != 0 {
+                return (limb_index as u32) * 64 + (64 - self.limbs[limb_index].leading_zeros());
+            }
+        }
+
+        0
+    }
+
+    pub fn low_u64(&self) -> u64 {
+        self.limbs[0]
+    }
+
+    // the value as a u128, or u128::MAX if it does not fit
+    pub fn saturating_to_u128(&self) -> u128 {
+        if self.limbs[2] != 0 || self.limbs[3] != 0 {
+            return u128::MAX;
+        }
+
+        ((self.limbs[1] as u128) << 64) | (self.limbs[0] as u128)
+    }
+
+    pub fn not(&self) -> U256 {
+        U256 { limbs: self.limbs.map(|limb| !limb) }
+    }
+
+    pub fn shl(&self, shift: u32) -> U256 {
+        let mut result: U256 = U256::zero();
+        if shift >= 256 {
+            return result;
+        }
+
+        let limb_shift: usize = (shift / 64) as usize;
+        let bit_shift: u32 = shift % 64;
+        for limb_index in (limb_shift..4).rev() {
+            let source_index: usize = limb_index - limb_shift;
+            result.limbs[limb_index] = self.limbs[source_index] << bit_shift;
+            if bit_shift > 0 && source_index > 0 {
+                result.limbs[limb_index] |= self.limbs[source_index - 1] >> (64 - bit_shift);
+            }
+        }
+
+        result
+    }
+
+    pub fn shr(&self, shift: u32) -> U256 {
+        let mut result: U256 = U256::zero();
+        if shift >= 256 {
+            return result;
+        }
+
+        let limb_shift: usize = (shift / 64) as usize;
+        let bit_shift: u32 = shift % 64;
+        for limb_index in 0..(4 - limb_shift) {
+            let source_index: usize = limb_index + limb_shift;
+            result.limbs[limb_index] = self.limbs[source_index] >> bit_shift;
+            if bit_shift > 0 && source_index < 3 {
+                result.limbs[limb_index] |= self.limbs[source_index + 1] << (64 - bit_shift);
+            }
+        }
+
+        result
+    }
+
+    // addition that wraps around on overflow
+    pub fn wrapping_add(&self, other: &U256) -> U256 {
+        let mut result: U256 = U256::zero();
+        let mut carry: bool = false;
+        for limb_index in 0..4 {
+            let (sum, overflow_1) = self.limbs[limb_index].overflowing_add(other.limbs[limb_index]);
+            let (sum, overflow_2) = sum.overflowing_add(carry as u64);
+            result.limbs[limb_index] = sum;
+            carry = overflow_1 || overflow_2;
+        }
+
+        result
+    }
+
+    // subtraction that wraps around on underflow
+    pub fn wrapping_sub(&self, other: &U256) -> U256 {
+        let mut result: U256 = U256::zero();
+        let mut borrow: bool = false;
+        for limb_index in 0..4 {
+            let (difference, underflow_1) = self.limbs[limb_index].overflowing_sub(other.limbs[limb_index]);
+            let (difference, underflow_2) = difference.overflowing_sub(borrow as u64);
+            result.limbs[limb_index] = difference;
+            borrow = underflow_1 || underflow_2;
+        }
+
+        result
+    }
+
+    // multiplication by a u64, returning None on overflow
+    pub fn checked_mul_u64(&self, multiplier: u64) -> Option<U256> {
+        let mut result: U256 = U256::zero();
+        let mut carry: u128 = 0;
+        for limb_index in 0..4 {
+            let product: u128 = (self.limbs[limb_index] as u128) * (multiplier as u128) + carry;
+            result.limbs[limb_index] = product as u64;
+            carry = product >> 64;
+        }
+
+        if carry != 0 {
+            return None;
+        }
+
+        Some(result)
+    }
+
+    // long division (shift and subtract), returning None when dividing by zero
+    pub fn checked_div(&self, divisor: &U256) -> Option<U256> {
+        if divisor.is_zero() {
+            return None;
+        }
+
+        let mut quotient: U256 = U256::zero();
+        let mut remainder: U256 = U256::zero();
+        for bit_index in (0..self.bits()).rev() {
+            // if the top bit is about to be shifted out, the remainder is certainly bigger than the divisor
+            let remainder_overflowed: bool = remainder.bit(255);
+            remainder = remainder.shl(1);
+            if self.bit(bit_index) {
+                remainder.limbs[0] |= 1;
+            }
+
+            if remainder_overflowed || remainder >= *divisor {
+                remainder = remainder.wrapping_sub(divisor);
+                quotient.limbs[(bit_index / 64) as usize] |= 1 << (bit_index % 64);
+            }
+        }
+
+        Some(quotient)
+    }
+
+    fn bit(&self, bit_index: u32) -> bool {
+        (self.limbs[(bit_index / 64) as usize] >> (bit_index % 64)) & 1 == 1
+    }
+}
+
+impl Ord for U256 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        for limb_index in (0..4).rev() {
+            match self.limbs[limb_index].cmp(&other.limbs[limb_index]) {
+                Ordering::Equal => continue,
+                ordering => return ordering,
+            }
+        }
+
+        Ordering::Equal
+    }
+}
+
+impl PartialOrd for U256 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
@@ -6,21 +6,57 @@ mod misc;
 use std::io;
 use std::io::{Read, Write};
 use std::net::{TcpListener, TcpStream};
-use std::sync::{Arc, RwLock};
+use std::path::Path;
+use std::sync::{mpsc::Receiver, Arc, RwLock};
 
 use chrono::{NaiveDateTime, TimeZone, Utc};
 use classes::block::block::Block;
-use classes::block::blockchain::Blockchain;
-use classes::lamport_signature::key_pair::{KeyPair, initialize_empty_key_blocks};
-use classes::transaction::tx::{Tx, TxInput, TxOutput};
+use classes::block::blockchain::{BlockAcceptance, Blockchain};
+use classes::block::orphan_pool::MAX_ORPHAN_BLOCKS;
+use classes::block::chain_events::ChainEvent;
+use classes::consensus::chain_params::{mine_genesis, ChainParams, GenesisParams, Network};
+use classes::consensus::subsidy::{block_subsidy, total_supply};
+use classes::mining::block_assembler::{BlockAssembler, BlockTemplate};
+use classes::mining::miner::{Miner, MiningResult};
+use classes::lamport_signature::key_pair::KeyPair;
+use classes::merkle_signature::key_pair::{MerkleKeyPair, NODE_MERKLE_TREE_HEIGHT};
+use classes::signature::scheme::{PublicKey, SignatureScheme};
+use classes::transaction::tx::{Tx, TxOutput};
+use classes::transaction::utxo_set::Balance;
 use misc::playground::{test_blockchain_fork_detection};
 
-use rand::Rng;
-use util::disk::{load_branches_from_file, load_keypairs_from_file};
+use classes::wallet::seed::{generate_seed, seed_from_hex, seed_from_mnemonic, seed_to_hex, seed_to_mnemonic, MNEMONIC_WORD_COUNT};
+use classes::wallet::{root_keys_fingerprint, Recipient, Wallet, DEFAULT_KEY_SCHEME};
+use util::disk::{load_branches_from_file, load_keypairs_from_file, load_merkle_keypair_from_file, load_wallet_from_file, save_merkle_keypair_to_file, save_merkle_leaf_index_to_file, save_wallet_to_file, MERKLE_KEYPAIR_FILENAME, WALLET_FILENAME};
 
 fn main() {
+    // cargo run -- mine-genesis <network> [timestamp]
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() > 1 && args[1] == "mine-genesis" {
+        mine_genesis_tool(&args[2..]);
+        return;
+    }
+
+    let mut network_name: String = String::new();
+    println!("Please enter the network this node should join (main, test or regtest -- leave empty for main):");
+    io::stdin().read_line(&mut network_name).expect("Failed to read line...");
+    let network: Network = if network_name.trim().is_empty() {
+        Network::Main
+    } else {
+        match Network::from_name(&network_name) {
+            Some(network) => network,
+            None => {
+                let network_names: Vec<String> = Network::ALL.iter().map(|network| network.to_string()).collect();
+                eprintln!("Unknown network {} (the valid networks are {})...", network_name.trim(), network_names.join(", "));
+                std::process::exit(1);
+            }
+        }
+    };
+    let chain_params: ChainParams = ChainParams::for_network(network);
+    let magic_bytes: [u8; 4] = chain_params.magic_bytes;
+
     let mut url: String = String::new();
-    println!("Please enter the URL where you would like to host this node instance (localhost URLs are the norm):");
+    println!("\nPlease enter the URL where you would like to host this node instance (localhost URLs are the norm -- leave empty for 127.0.0.1:{}):", chain_params.default_port);
     io::stdin().read_line(&mut url).expect("Failed to read line...");
 
     let mut peer_url: String = String::new();
@@ -32,6 +68,9 @@ fn main() {
     io::stdin().read_line(&mut branches_filename).expect("Failed to read line...");
 
     url = url.trim().to_string();
+    if url.is_empty() {
+        url = format!("127.0.0.1:{}", chain_params.default_port);
+    }
     peer_url = peer_url.trim().to_string();
     branches_filename = branches_filename.trim().to_string();
 
@@ -47,6 +86,29 @@ fn main() {
         } 
     };
 
+    // the first time the node starts, every key pair becomes an account of the wallet, whose new keys are derived from a new or a recovered master seed
+    let wallet_filename: String = WALLET_FILENAME.to_string();
+    let mut wallet: Wallet = if Path::new(&wallet_filename).exists() {
+        match load_wallet_from_file(&wallet_filename) {
+            // the accounts of the wallet can only spend with the root keys they started with
+            Ok(val) if val.root_keys_fingerprint() != root_keys_fingerprint(&keypairs) => panic!("The wallet was created with another keypairs.bin..."),
+            Ok(val) => {
+                println!("Loaded Wallet...");
+                val
+            },
+            // never overwrite a wallet that could not be read, since it may hold the only copy of which keys are used
+            Err(()) => panic!("Failed to load the wallet..."),
+        }
+    } else {
+        let mut new_wallet: Wallet = Wallet::new(read_master_seed(&keypairs), &keypairs);
+        new_wallet.set_key_scheme(read_key_scheme()).expect("Only a supported one-time key scheme can be read");
+        if save_wallet_to_file(&new_wallet, &wallet_filename).is_err() {
+            panic!("Failed to create the wallet...");
+        }
+        println!("Created Wallet with {} keys (use the Export Wallet Seed option to back up its master seed)...", new_wallet.key_scheme());
+        new_wallet
+    };
+
     // uncomment to test fork detection:
     // test_blockchain_fork_detection(&keypairs[0].pub_key);
 
@@ -59,9 +121,10 @@ fn main() {
         }
     };
 
-    let mut blockchain: Blockchain = Blockchain::new();
+    let mut blockchain: Blockchain = Blockchain::with_chain_params(&chain_params);
+    println!("Joined the {} network...", chain_params.network);
     if blockchains.len() == 0 {
-        let blocks_result: Result<(), io::Error> = get_blocks(&mut blockchain, &peer_url, &branches_filename);
+        let blocks_result: Result<(), io::Error> = get_blocks(&mut blockchain, &peer_url, &magic_bytes, &branches_filename);
         match blocks_result {
             Ok(()) => {
                 println!("Retrieved blocks from node...");
@@ -72,19 +135,38 @@ fn main() {
             }
         }
     } else {
-        let mut biggest_chain_height: usize = 0;
-        let mut biggest_chain_index: usize = 0;
-        for chain_index in 0..blockchains.len() {
-            if blockchains[chain_index].blocks.len() > biggest_chain_height {
-                biggest_chain_height = blockchains[chain_index].blocks.len();
-                biggest_chain_index = chain_index;
-            }
+        // the branches are left on disk as they are, rather than overwritten by a chain of this network
+        if let Err(genesis_mismatch) = blockchain.insert_branches(&blockchains) {
+            eprintln!("Error: {} -- {} was saved by a node of another network or by an older version of the node, so use another file name or delete it to retrieve the chain from the peer again...", genesis_mismatch, branches_filename);
+            std::process::exit(1);
+        }
+        blockchain.choose_valid_chain_and_update_utxo(&branches_filename);
+    }
+
+    // picks up the keys of a recovered wallet, or keys that were derived but not saved before the node stopped
+    let restored_key_count: usize = wallet.restore_keys(&blockchain);
+    if restored_key_count > 0 {
+        println!("Restored {} wallet key(s) from the master seed...", restored_key_count);
+        if save_wallet_to_file(&wallet, &wallet_filename).is_err() {
+            println!("Could not save the restored wallet keys...");
         }
-    
-        blockchain = blockchains[biggest_chain_index].clone();
-        blockchain.update_utxo();
     }
 
+    let miner: Arc<Miner> = Arc::new(Miner::with_available_parallelism());
+    let miner_events_copy: Arc<Miner> = Arc::clone(&miner);
+
+    let chain_events: Receiver<ChainEvent> = blockchain.subscribe();
+    std::thread::spawn(move || {
+        for chain_event in chain_events {
+            log_chain_event(&chain_event);
+
+            // a block being mined on top of the previous tip would be stale, so the miner restarts on top of the new tip
+            if let ChainEvent::TipChanged { tip_hash, .. } = &chain_event {
+                miner_events_copy.notify_new_tip(tip_hash);
+            }
+        }
+    });
+
     let blockchain_arc: Arc<RwLock<Blockchain>> = Arc::new(RwLock::new(blockchain));
 
     let blockchain_copy: Arc<RwLock<Blockchain>> =  Arc::clone(&blockchain_arc);
@@ -92,11 +174,12 @@ fn main() {
 
     let branches_filename_input_arc = Arc::new(RwLock::new(branches_filename.clone()));
     let branches_filename_tcp_arc = Arc::new(RwLock::new(branches_filename));
+    let peer_url_tcp_arc = Arc::new(peer_url.clone());
 
     std::thread::spawn(move || {
         loop {
             let mut choice: String = String::new();
-            println!("\nWhat can I do for you?\n1. Get Blockchain\n2. Compute Balance\n3. Send Money\n4. Get UTXO\n(Q to Exit)");
+            println!("\nWhat can I do for you?\n1. Get Blockchain\n2. Compute Balance\n3. Send Money\n4. Get UTXO\n5. Get Money Supply\n6. Mine Block\n7. Get Mempool\n8. Stop Mining\n9. Get Deployments\n10. Get Wallet Keys\n11. Export Wallet Seed\n12. Use Merkle Key\n(Q to Exit)");
             io::stdin().read_line(&mut choice).expect("Failed to read line...");
             choice = choice.trim().to_string();
 
@@ -110,15 +193,48 @@ fn main() {
                 }
                 "2" => {
                     let blockchain = blockchain_copy.read().unwrap();
-                    compute_balance(&blockchain, &keypairs);
+                    compute_balance(&blockchain, &wallet);
                 }
                 "3" => {
-                    let mut blockchain = blockchain_copy.write().unwrap();
-                    send_money(&mut blockchain, &keypairs, &peer_url, &branches_filename_copy_r);
+                    let added_to_mempool: bool = send_money(&mut blockchain_copy.write().unwrap(), &mut wallet, &wallet_filename);
+                    if added_to_mempool {
+                        start_mining(&blockchain_copy, &miner, &mut wallet, &wallet_filename, &peer_url, &magic_bytes, &branches_filename_copy_r);
+                    }
                 }
                 "4" => {
                     let blockchain = blockchain_copy.read().unwrap();
-                    get_utxo(&blockchain, &keypairs);
+                    get_utxo(&blockchain, &wallet);
+                }
+                "5" => {
+                    let blockchain = blockchain_copy.read().unwrap();
+                    get_money_supply(&blockchain);
+                }
+                "6" => {
+                    start_mining(&blockchain_copy, &miner, &mut wallet, &wallet_filename, &peer_url, &magic_bytes, &branches_filename_copy_r);
+                }
+                "7" => {
+                    let blockchain = blockchain_copy.read().unwrap();
+                    get_mempool(&blockchain);
+                }
+                "8" => {
+                    miner.stop();
+                }
+                "9" => {
+                    let blockchain = blockchain_copy.read().unwrap();
+                    get_deployments(&blockchain);
+                }
+                "10" => {
+                    let blockchain = blockchain_copy.read().unwrap();
+                    get_wallet_keys(&blockchain, &wallet);
+                }
+                "11" => {
+                    export_wallet_seed(&wallet);
+                }
+                "12" => {
+                    let added_to_mempool: bool = use_merkle_key(&mut blockchain_copy.write().unwrap(), &mut wallet, &wallet_filename);
+                    if added_to_mempool {
+                        start_mining(&blockchain_copy, &miner, &mut wallet, &wallet_filename, &peer_url, &magic_bytes, &branches_filename_copy_r);
+                    }
                 }
                 "Q" | "q" => break,
                 _ => println!("Invalid choice! Please try again."),
@@ -136,11 +252,11 @@ fn main() {
                 print!("New TCP Stream Detected");
                 let blockchain_copy = Arc::clone(&blockchain_arc);
                 let branches_filename_copy = Arc::clone(&branches_filename_tcp_arc);
+                let peer_url_copy = Arc::clone(&peer_url_tcp_arc);
 
                 std::thread::spawn(move || {
-                    let mut blockchain_copy_w = blockchain_copy.write().unwrap();
                     let branches_filename_copy_r = branches_filename_copy.read().unwrap();
-                    handle_client(stream, &mut blockchain_copy_w, &branches_filename_copy_r);
+                    handle_client(stream, &blockchain_copy, &branches_filename_copy_r, &peer_url_copy, &magic_bytes);
                 });
             },
             Err(e) => {
@@ -150,8 +266,15 @@ fn main() {
     }
 }
 
-fn get_blocks(blockchain: &mut Blockchain, peer_url: &String, branches_filename: &String) -> io::Result<()> {
+// connects to the peer and sends the magic bytes of the network, which every message starts with
+fn connect_to_peer(peer_url: &String, magic_bytes: &[u8; 4]) -> io::Result<TcpStream> {
     let mut stream: TcpStream = TcpStream::connect(peer_url)?;
+    stream.write_all(magic_bytes)?;
+    Ok(stream)
+}
+
+fn get_blocks(blockchain: &mut Blockchain, peer_url: &String, magic_bytes: &[u8; 4], branches_filename: &str) -> io::Result<()> {
+    let mut stream: TcpStream = connect_to_peer(peer_url, magic_bytes)?;
     println!("Connected to peer!");
 
     let handshake_number: [u8; 1] = [1];
@@ -165,14 +288,14 @@ fn get_blocks(blockchain: &mut Blockchain, peer_url: &String, branches_filename:
     let mut buffer: Vec<u8> = vec![0u8; msg_len];
     stream.read_exact(&mut buffer)?;
     let blocks: Vec<Block> = bincode::deserialize(&buffer).expect("Error: Could not deserialize blocks");
-    blockchain.blocks = blocks;
+    blockchain.insert_blocks(&blocks);
     blockchain.choose_valid_chain_and_update_utxo(branches_filename);
 
     Ok(())
 }
 
-fn propagate_block(block: &Block, peer_url: &String) -> io::Result<()> {
-    let mut stream: TcpStream = TcpStream::connect(peer_url)?;
+fn propagate_block(block: &Block, peer_url: &String, magic_bytes: &[u8; 4]) -> io::Result<()> {
+    let mut stream: TcpStream = connect_to_peer(peer_url, magic_bytes)?;
     println!("Connected to peer!");
 
     let handshake_number: [u8; 1] = [2];
@@ -188,7 +311,15 @@ fn propagate_block(block: &Block, peer_url: &String) -> io::Result<()> {
 }
 
 // node server
-fn handle_client(mut stream: TcpStream, blockchain: &mut Blockchain, branches_filename: &String) {
+fn handle_client(mut stream: TcpStream, blockchain_arc: &Arc<RwLock<Blockchain>>, branches_filename: &str, peer_url: &String, magic_bytes: &[u8; 4]) {
+    // every message starts with the magic bytes of the network, so that nodes of different networks do not exchange blocks
+    let mut magic_buffer: [u8; 4] = [0; 4];
+    stream.read_exact(&mut magic_buffer).expect("Failed to read the magic bytes");
+    if magic_buffer != *magic_bytes {
+        println!("Network error: The peer is on a different network...");
+        return;
+    }
+
     let mut buffer: [u8; 1] = [0; 1];
     stream.read_exact(&mut buffer).expect("Failed to read request buffer");
 
@@ -196,7 +327,7 @@ fn handle_client(mut stream: TcpStream, blockchain: &mut Blockchain, branches_fi
 
     // get blocks request
     if handshake_number == 1 {
-        let blocks: Vec<Block> = blockchain.blocks.clone();
+        let blocks: Vec<Block> = blockchain_arc.read().unwrap().blocks.clone();
         let blocks_bytes_result: Result<Vec<u8>, Box<bincode::ErrorKind>> = bincode::serialize(&blocks);
         let blocks_bytes: Vec<u8> = match blocks_bytes_result {
             Ok(val) => val,
@@ -224,7 +355,25 @@ fn handle_client(mut stream: TcpStream, blockchain: &mut Blockchain, branches_fi
         stream.read_exact(&mut buffer).expect("Error: Could not read block buffer");
         let block: Block = bincode::deserialize(&buffer).expect("Error: Could not deserialize blocks");
 
-        blockchain.accept_new_block(&block, branches_filename);
+        // the lock is released before asking the peer for a missing parent, so that the peer can ask this node for blocks in the meantime
+        let acceptance: BlockAcceptance = blockchain_arc.write().unwrap().accept_new_block(&block, branches_filename);
+        if let BlockAcceptance::Orphaned { missing_parent_hash } = acceptance {
+            request_missing_parents(missing_parent_hash, blockchain_arc, peer_url, magic_bytes, branches_filename);
+        }
+    } else if handshake_number == 3 { // get block by hash request
+        let mut hash_buf: [u8; 64] = [0u8; 64];
+        stream.read_exact(&mut hash_buf).expect("Error: Could not read block hash");
+        let block_hash: String = String::from_utf8_lossy(&hash_buf).to_string();
+
+        let block_bytes: Vec<u8> = match blockchain_arc.read().unwrap().block_index.get(&block_hash) {
+            Some(entry) => bincode::serialize(&entry.block).expect("Error: Could not serialize block"),
+            None => vec![], // an empty message means that this node does not know the block
+        };
+
+        let len = (block_bytes.len() as u32).to_be_bytes();
+        stream.write_all(&len).expect("Error: Could not send message length delimiter");
+        stream.write_all(block_bytes.as_slice()).expect("Error: Could not send block to tcp client");
+        return;
     } else {
         println!("Network error: Received an invalid TCP handshake");
     }
@@ -234,24 +383,75 @@ fn handle_client(mut stream: TcpStream, blockchain: &mut Blockchain, branches_fi
 
 }
 
-fn compute_balance(blockchain: &Blockchain, keypairs: &Vec<KeyPair>) {
-    let utxo: &Vec<Tx> = &blockchain.utxo;
+fn request_block(block_hash: &String, peer_url: &String, magic_bytes: &[u8; 4]) -> io::Result<Option<Block>> {
+    let mut stream: TcpStream = connect_to_peer(peer_url, magic_bytes)?;
+
+    let handshake_number: [u8; 1] = [3];
+    stream.write_all(&handshake_number)?;
+    stream.write_all(block_hash.as_bytes())?;
+
+    let mut len_buf: [u8; 4] = [0u8; 4];
+    stream.read_exact(&mut len_buf)?;
+    let msg_len: usize = u32::from_be_bytes(len_buf) as usize;
+    if msg_len == 0 {
+        return Ok(None);
+    }
+
+    let mut buffer: Vec<u8> = vec![0u8; msg_len];
+    stream.read_exact(&mut buffer)?;
+    let block: Block = bincode::deserialize(&buffer).expect("Error: Could not deserialize block");
+
+    Ok(Some(block))
+}
+
+// ask the peer that sent an orphan block for its missing parent, and keep going back until the orphan connects to a known block
+fn request_missing_parents(mut missing_parent_hash: String, blockchain_arc: &Arc<RwLock<Blockchain>>, peer_url: &String, magic_bytes: &[u8; 4], branches_filename: &str) {
+    // the orphan pool cannot hold a longer chain of orphans than this anyways
+    for _ in 0..MAX_ORPHAN_BLOCKS {
+        if missing_parent_hash.len() != 64 {
+            return;
+        }
+
+        println!("Requesting missing parent block {} from peer...", missing_parent_hash);
+        let parent_block: Block = match request_block(&missing_parent_hash, peer_url, magic_bytes) {
+            Ok(Some(block)) => block,
+            Ok(None) => {
+                println!("Peer does not know the missing parent block...");
+                return;
+            },
+            Err(e) => {
+                eprint!("Failed to request the missing parent block from peer: {}", e);
+                return;
+            }
+        };
+
+        let acceptance: BlockAcceptance = blockchain_arc.write().unwrap().accept_new_block(&parent_block, branches_filename);
+        match acceptance {
+            BlockAcceptance::Orphaned { missing_parent_hash: next_missing_parent_hash } => missing_parent_hash = next_missing_parent_hash,
+            _ => return,
+        }
+    }
+}
+
+fn log_chain_event(chain_event: &ChainEvent) {
+    match chain_event {
+        ChainEvent::BlockConnected { block_hash, height, block } => println!("Connected block {} at height {} ({} transactions)", block_hash, height, block.txs.base.len()),
+        ChainEvent::BlockDisconnected { block_hash, height, block } => println!("Disconnected block {} at height {} ({} transactions)", block_hash, height, block.txs.base.len()),
+        ChainEvent::TipChanged { tip_hash, height } => println!("New valid chain tip {} at height {}", tip_hash, height),
+    }
+}
+
+fn compute_balance(blockchain: &Blockchain, wallet: &Wallet) {
     let mut account_index_str: String = String::new();
     println!("\nAccount Index: ");
     io::stdin().read_line(&mut account_index_str).expect("Error: Failed to read line...");
     let account_index: usize = account_index_str.trim().parse().unwrap();
-    let mut computed_balance: u64 = 0;
 
-    let keypair: &KeyPair = &keypairs[account_index];
-    for tx in utxo {
-        for tx_output in &tx.outputs {
-            if tx_output.pub_key.hash_key() == keypair.pub_key.hash_key() {
-                computed_balance += tx_output.amount.clone();
-            }
-        }
-    }
+    // coinbase outputs that have not matured yet cannot be spent by the next block, so they are shown separately
+    let balance: Balance = wallet.balance(account_index, blockchain);
 
-    println!("Balance: ${}\n", computed_balance);
+    println!("Balance: ${}", balance.spendable);
+    println!("Immature Coinbase Balance: ${}\n", balance.immature);
 }
 
 fn get_blockchain(blockchain: &Blockchain) {
@@ -259,8 +459,14 @@ fn get_blockchain(blockchain: &Blockchain) {
     println!("");
     for block_in_chain in &blockchain.blocks {
         println!("-Block Height: {}-", block_height);
-        println!("Block hash: {}", block_in_chain.block_header.hash_block());
+        // every block of the valid chain was hashed when it was indexed
+        let block_hash: String = block_in_chain.block_header.hash_block().unwrap_or_default();
+        println!("Block hash: {}", block_hash);
+        println!("Version: {:#010x}", block_in_chain.block_header.version);
         println!("TX Length: {}", block_in_chain.txs.base.len());
+        if let Some(block_fees) = blockchain.block_fees(&block_hash) {
+            println!("Fees: {}", block_fees);
+        }
         let naive_datetime = NaiveDateTime::from_timestamp(block_in_chain.block_header.timestamp.clone(), 0);
         println!("Timestamp: {}", Utc.from_utc_datetime(&naive_datetime).to_rfc3339());
         println!("");
@@ -268,8 +474,8 @@ fn get_blockchain(blockchain: &Blockchain) {
     }
 }
 
-fn send_money(blockchain: &mut Blockchain, keypairs: &Vec<KeyPair>, peer_url: &String, branches_filename: &String) {
-    let utxo: &Vec<Tx> = &blockchain.utxo;
+// returns whether the new transaction was added to the mempool
+fn send_money(blockchain: &mut Blockchain, wallet: &mut Wallet, wallet_filename: &str) -> bool {
     let mut sender_account_index_str: String = String::new();
     let mut recipient_account_index_str: String = String::new();
     let mut amount_str: String = String::new();
@@ -285,85 +491,354 @@ fn send_money(blockchain: &mut Blockchain, keypairs: &Vec<KeyPair>, peer_url: &S
     let recipient_account_index: usize = recipient_account_index_str.trim().parse().unwrap();
     let amount: u64 = amount_str.trim().parse().unwrap();
 
-    let mut possible_tx_inputs: Vec<TxInput> = vec![];
-    let mut possible_amount: u64 = 0;
+    // the payment and the change both go to fresh keys, and the wallet is saved before the transaction is added to the mempool
+    let tx_id: String = match wallet.send_transaction(sender_account_index, &Recipient::Account(recipient_account_index), amount, blockchain, wallet_filename) {
+        Ok(tx_id) => tx_id,
+        Err(wallet_error) => {
+            println!("{}...", wallet_error);
+            return false;
+        }
+    };
+    println!("Added transaction {} to the mempool...", tx_id);
+
+    true
+}
+
+/* The Merkle key of the node can be paid any number of times, so the accounts can keep paying it, and it can pay them back (up to 1024 times).
+Returns whether the new transaction was added to the mempool. */
+fn use_merkle_key(blockchain: &mut Blockchain, wallet: &mut Wallet, wallet_filename: &str) -> bool {
+    let mut merkle_keypair: MerkleKeyPair = match load_node_merkle_keypair(blockchain, wallet) {
+        Some(merkle_keypair) => merkle_keypair,
+        None => return false,
+    };
+    let merkle_pub_key: PublicKey = merkle_keypair.pub_key.clone().into();
+    let key_balance: Balance = blockchain.utxo.balance(&merkle_pub_key.hash_key(), blockchain.next_block_height(), blockchain.params.coinbase_maturity);
+    println!("\nMerkle Key {}: {} of {} signatures left (${} spendable, ${} immature)", merkle_pub_key.hash_key(), merkle_keypair.remaining_signatures(), merkle_keypair.leaf_count(), key_balance.spendable, key_balance.immature);
+
+    let mut choice: String = String::new();
+    println!("\n1. Pay the Merkle Key from an Account\n2. Pay an Account from the Merkle Key\n(leave empty to go back)");
+    io::stdin().read_line(&mut choice).expect("Error: Failed to read line");
+
+    match choice.trim() {
+        "1" => {
+            let mut sender_account_index_str: String = String::new();
+            let mut amount_str: String = String::new();
+            println!("\nSender Account Index: ");
+            io::stdin().read_line(&mut sender_account_index_str).expect("Error: Failed to read line");
+            println!("\nAmount of Money: ");
+            io::stdin().read_line(&mut amount_str).expect("Error: Failed to read line");
+            let sender_account_index: usize = sender_account_index_str.trim().parse().unwrap();
+            let amount: u64 = amount_str.trim().parse().unwrap();
+
+            match wallet.send_transaction(sender_account_index, &Recipient::Key(merkle_pub_key), amount, blockchain, wallet_filename) {
+                Ok(tx_id) => {
+                    println!("Added transaction {} to the mempool...", tx_id);
+                    true
+                },
+                Err(wallet_error) => {
+                    println!("{}...", wallet_error);
+                    false
+                }
+            }
+        }
+        "2" => {
+            let mut recipient_account_index_str: String = String::new();
+            let mut amount_str: String = String::new();
+            println!("\nRecipient Account Index: ");
+            io::stdin().read_line(&mut recipient_account_index_str).expect("Error: Failed to read line");
+            println!("\nAmount of Money: ");
+            io::stdin().read_line(&mut amount_str).expect("Error: Failed to read line");
+            let recipient_account_index: usize = recipient_account_index_str.trim().parse().unwrap();
+            let amount: u64 = amount_str.trim().parse().unwrap();
+
+            // the payment goes to a fresh key of the account, which has to be saved before the transaction can pay it
+            let recipient_pub_key: PublicKey = match wallet.new_key(recipient_account_index) {
+                Ok(recipient_pub_key) => recipient_pub_key,
+                Err(wallet_error) => {
+                    println!("{}...", wallet_error);
+                    return false;
+                }
+            };
+            if save_wallet_to_file(wallet, wallet_filename).is_err() {
+                println!("Could not save the wallet, so the transaction was not sent...");
+                return false;
+            }
 
-    let keypair: &KeyPair = &keypairs[sender_account_index];
+            let tx: Tx = match merkle_keypair.create_transaction(recipient_pub_key, amount, blockchain, MERKLE_KEYPAIR_FILENAME) {
+                Ok(tx) => tx,
+                Err(merkle_key_error) => {
+                    println!("{}...", merkle_key_error);
+                    return false;
+                }
+            };
+            match blockchain.add_to_mempool(&tx) {
+                Ok(tx_id) => {
+                    println!("Added transaction {} to the mempool...", tx_id);
+                    true
+                },
+                Err(mempool_error) => {
+                    println!("The transaction was not added to the mempool: {}...", mempool_error);
+                    false
+                }
+            }
+        }
+        _ => false,
+    }
+}
 
-    for tx in utxo {
-        let mut output_index: usize = 0; 
-        for tx_output in &tx.outputs {
-            if tx_output.pub_key.hash_key() == keypair.pub_key.hash_key() {
-                possible_tx_inputs.push(TxInput::new(initialize_empty_key_blocks(), tx.get_tx_id(), false, output_index));
-                possible_amount += tx_output.amount.clone();
+/* Loads the Merkle key pair of the node, or derives it from the master seed of the wallet the first time. Either way, it skips the leaves the valid chain
+or the mempool has seen sign, since its saved state may be behind them (e.g. the key pair was derived again for a recovered wallet). */
+fn load_node_merkle_keypair(blockchain: &Blockchain, wallet: &Wallet) -> Option<MerkleKeyPair> {
+    let mut merkle_keypair: MerkleKeyPair = if Path::new(MERKLE_KEYPAIR_FILENAME).exists() {
+        match load_merkle_keypair_from_file(MERKLE_KEYPAIR_FILENAME) {
+            Ok(val) => val,
+            // never overwrite a key pair that could not be read, since its state is what keeps its leaves from signing twice
+            Err(()) => {
+                println!("Failed to load the Merkle key pair...");
+                return None;
             }
-            output_index += 1;
         }
+    } else {
+        println!("Deriving the Merkle key of the wallet (this takes a moment)...");
+        let new_merkle_keypair: MerkleKeyPair = MerkleKeyPair::from_seed(wallet.seed(), 0, 0, NODE_MERKLE_TREE_HEIGHT);
+        if save_merkle_keypair_to_file(&new_merkle_keypair, MERKLE_KEYPAIR_FILENAME).is_err() {
+            println!("Could not save the Merkle key pair...");
+            return None;
+        }
+        new_merkle_keypair
+    };
 
-        if possible_amount >= amount {
-            break;
+    let skipped_leaf_count: u32 = merkle_keypair.skip_signed_leaves(blockchain);
+    if skipped_leaf_count > 0 {
+        println!("Skipped {} leaves of the Merkle key that have already signed...", skipped_leaf_count);
+        if save_merkle_leaf_index_to_file(merkle_keypair.next_leaf_index(), MERKLE_KEYPAIR_FILENAME).is_err() {
+            println!("Could not save the state of the Merkle key...");
+            return None;
         }
     }
 
-    let recipient_pubkey = keypairs[recipient_account_index].pub_key.clone();
-    let tx_output: TxOutput = TxOutput::new(recipient_pubkey, amount);
-    let mut tx_outputs: Vec<TxOutput> = vec![tx_output];
+    Some(merkle_keypair)
+}
+
+// mine a block in the background, so that the menu stays available (e.g. to stop mining)
+fn start_mining(blockchain_arc: &Arc<RwLock<Blockchain>>, miner: &Arc<Miner>, wallet: &mut Wallet, wallet_filename: &str, peer_url: &str, magic_bytes: &[u8; 4], branches_filename: &str) {
+    if miner.is_mining() {
+        println!("Already mining a block...");
+        return;
+    }
 
-    if possible_amount > amount {
-        let self_tx_output: TxOutput = TxOutput::new(keypair.pub_key.clone(), possible_amount-amount);
-        tx_outputs.push(self_tx_output);
+    // the block reward goes to a fresh key of Account 0, which has to be saved before the block can pay it
+    let payout_key: PublicKey = match wallet.new_key(0) {
+        Ok(payout_key) => payout_key,
+        Err(wallet_error) => {
+            println!("{}...", wallet_error);
+            return;
+        }
+    };
+    if save_wallet_to_file(wallet, wallet_filename).is_err() {
+        println!("Could not save the wallet, so mining was not started...");
+        return;
     }
 
-    let mut transaction: Tx = Tx::new(possible_tx_inputs, tx_outputs);
-    for tx_input_index in 0..transaction.inputs.len() {
-        transaction.inputs[tx_input_index].signature = keypair.create_signature(&transaction);
+    let blockchain_arc_copy: Arc<RwLock<Blockchain>> = Arc::clone(blockchain_arc);
+    let miner_copy: Arc<Miner> = Arc::clone(miner);
+    let peer_url_copy: String = peer_url.to_string();
+    let magic_bytes_copy: [u8; 4] = *magic_bytes;
+    let branches_filename_copy: String = branches_filename.to_string();
+
+    std::thread::spawn(move || {
+        mine_and_propagate_block(&blockchain_arc_copy, &miner_copy, &payout_key, &peer_url_copy, &magic_bytes_copy, &branches_filename_copy);
+    });
+}
+
+/* Mine a block with the transactions waiting in the mempool (if any), paying the subsidy and the fees to the payout key.
+The blockchain is only locked to build the block template and to add the mined block, not while hashing. If the valid chain gets a new tip in the meantime,
+a new template is built on top of it. */
+fn mine_and_propagate_block(blockchain_arc: &Arc<RwLock<Blockchain>>, miner: &Miner, payout_key: &PublicKey, peer_url: &String, magic_bytes: &[u8; 4], branches_filename: &str) {
+    let block_assembler: BlockAssembler = BlockAssembler::new(payout_key.clone());
+
+    loop {
+        let block_template_option: Option<BlockTemplate> = block_assembler.create_template(&blockchain_arc.read().unwrap());
+        let block_template: BlockTemplate = match block_template_option {
+            Some(block_template) => block_template,
+            None => {
+                println!("Could not create a block template...");
+                return;
+            }
+        };
+        println!("Mining block at height {} with {} transaction(s) and ${} in fees...", block_template.height, block_template.block.txs.base.len() - 1, block_template.total_fees);
+
+        match miner.mine(&block_template.block, || blockchain_arc.read().unwrap().active_tip_hash()) {
+            MiningResult::Found(block) => {
+                println!("\n--- Mined the block! ---\nBlock hash: {0}\nNonce: {1}\nTimestamp: {2}", block.block_header.hash_block().unwrap_or_default(), block.block_header.nonce, block.block_header.timestamp);
+                blockchain_arc.write().unwrap().accept_new_block(&block, branches_filename);
+                let _ = propagate_block(&block, peer_url, magic_bytes);
+                return;
+            },
+            MiningResult::NewTip => println!("The valid chain has a new tip, mining on top of it instead..."),
+            MiningResult::Stopped => {
+                println!("Stopped mining...");
+                return;
+            },
+            MiningResult::Busy => {
+                println!("Already mining a block...");
+                return;
+            },
+        }
     }
+}
 
-    let mut rng = rand::thread_rng();
+fn get_money_supply(blockchain: &Blockchain) {
+    let tip_height: u64 = match (blockchain.blocks.len() as u64).checked_sub(1) {
+        Some(tip_height) => tip_height,
+        None => {
+            println!("\nThere are no blocks yet, so there is no money supply...");
+            return;
+        }
+    };
+    println!("\nBlock Height: {}", tip_height);
+    println!("Total Supply: ${}", total_supply(tip_height, &blockchain.params));
+    println!("Subsidy of the Next Block: ${}", block_subsidy(tip_height + 1, &blockchain.params));
+}
 
-    // Generate a random number (e.g., an integer between 1 and 100)
-    let random_number = rng.gen_range(1..=1000000000);
+fn get_mempool(blockchain: &Blockchain) {
+    println!("\nMempool Length: {}", blockchain.mempool.len());
+    println!("Mempool Size: {} bytes", blockchain.mempool.total_size());
+    for mempool_entry in blockchain.mempool.entries_by_fee_rate() {
+        println!("Transaction {}: ${} fee, {} bytes", mempool_entry.tx_id, mempool_entry.fee, mempool_entry.size);
+    }
+    println!();
+}
 
-    // Convert the number to a string
-    let random_number_string: String = random_number.to_string();
+// mines a new genesis block for the given network (paying Account 0), and prints what has to go into the network's chain parameters to use it
+fn mine_genesis_tool(args: &[String]) {
+    let network: Network = match args.first().and_then(|network_name| Network::from_name(network_name)) {
+        Some(network) => network,
+        None => {
+            println!("Usage: mine-genesis <main|test|regtest> [timestamp]");
+            return;
+        }
+    };
+    let timestamp: i64 = match args.get(1) {
+        Some(timestamp) => timestamp.parse().expect("Error: The timestamp must be a number of seconds"),
+        None => Utc::now().timestamp(),
+    };
 
-    let miner_transaction: Tx = 
-    Tx::new(vec![TxInput::new(initialize_empty_key_blocks(), random_number_string, true, 0)], vec![TxOutput::new(keypairs[0].pub_key.clone(), 100)]);
+    let keypairs: Vec<KeyPair> = load_keypairs_from_file().expect("Failed to import key pairs...");
+    let chain_params: ChainParams = ChainParams::for_network(network);
+    println!("Mining a genesis block for the {} network with timestamp {}...", network, timestamp);
+    let genesis: GenesisParams = mine_genesis(&chain_params, &keypairs[0].pub_key, timestamp);
 
-    let mut block: Block = Block::new(&vec![miner_transaction, transaction], blockchain.blocks[blockchain.blocks.len()-1].block_header.hash_block());
-    block.mine_block();
-    blockchain.accept_new_block(&block, branches_filename);
+    println!("\n--- Mined the genesis block! ---");
+    println!("GenesisParams {{\n    timestamp: {},\n    nonce: {},\n    hash: \"{}\".to_string(),\n}}", genesis.timestamp, genesis.nonce, genesis.hash);
+}
 
-    let _ = propagate_block(&block, &peer_url);
+fn get_deployments(blockchain: &Blockchain) {
+    println!("\nDeployments (for the next block):");
+    for (deployment_name, deployment_state) in blockchain.deployment_states() {
+        println!("{}: {}", deployment_name, deployment_state);
+    }
+    println!();
 }
 
-fn get_utxo(blockchain: &Blockchain, keypairs: &Vec<KeyPair>) {
-    let mut utxo_length: usize = 0;
+fn get_utxo(blockchain: &Blockchain, wallet: &Wallet) {
+    let utxo_length: usize = blockchain.utxo.len();
     let mut tx_outputs: Vec<TxOutput> = vec![];
 
-    for tx in &blockchain.utxo {
-        utxo_length += tx.outputs.len();
-        for tx_output in &tx.outputs {
-            tx_outputs.push(tx_output.clone());
-        }
+    let next_block_height: u64 = blockchain.next_block_height();
+    let mut immature_flags: Vec<bool> = vec![];
+
+    for (_outpoint, utxo_entry) in blockchain.utxo.iter() {
+        tx_outputs.push(utxo_entry.output.clone());
+        immature_flags.push(!utxo_entry.is_mature(next_block_height, blockchain.params.coinbase_maturity));
     }
 
     println!("\nUTXO Length: {}", utxo_length);
-    for output in &tx_outputs {
+    for (output, is_immature) in tx_outputs.iter().zip(immature_flags) {
         let amount = output.amount;
-        let mut account_index: usize = 0;
-        
-        let mut key_pair_index: usize = 0; 
-        for keypair in keypairs {
-            if output.pub_key.hash_key() == keypair.pub_key.hash_key() {
-                account_index = key_pair_index;
-                break;
+        // an account owns every key the wallet generated for it
+        let owner: String = match wallet.find_key(&output.pub_key.hash_key()) {
+            Some(wallet_key) => format!("Account #{}", wallet_key.account_index),
+            None => "an unknown key".to_string(),
+        };
+
+        if is_immature {
+            println!("${0} for {1} (immature coinbase)", amount, owner);
+        } else {
+            println!("${0} for {1}", amount, owner);
+        }
+    }
+    println!();
+}
+
+/* Asks for the master seed of a wallet to recover (as a mnemonic or in hex), or generates a new one. The root keys of the accounts cannot be recovered from the seed,
+so a recovery only goes ahead once the keypairs.bin of the recovered wallet is in place, which is checked against the root keys fingerprint exported along with the seed. */
+fn read_master_seed(keypairs: &[KeyPair]) -> [u8; 32] {
+    loop {
+        let mut seed_str: String = String::new();
+        println!("\nNo wallet found. Please enter the master seed of the wallet to recover ({} words or 64 hex characters -- leave empty to create a new wallet):", MNEMONIC_WORD_COUNT);
+        io::stdin().read_line(&mut seed_str).expect("Failed to read line...");
+
+        if seed_str.trim().is_empty() {
+            return generate_seed();
+        }
+
+        let seed: [u8; 32] = match seed_from_mnemonic(&seed_str).or_else(|| seed_from_hex(&seed_str)) {
+            Some(seed) => seed,
+            None => {
+                println!("Invalid master seed (unknown word, wrong number of words or wrong checksum)...");
+                continue;
             }
-            key_pair_index += 1;
+        };
+
+        let mut fingerprint_str: String = String::new();
+        println!("\nPlease enter the root keys fingerprint exported along with the master seed (the keypairs.bin backed up with the seed must replace the current one first):");
+        io::stdin().read_line(&mut fingerprint_str).expect("Failed to read line...");
+        if fingerprint_str.trim() != root_keys_fingerprint(keypairs) {
+            panic!("The current keypairs.bin does not hold the root keys of the wallet to recover, restore its backup and restart the node...");
+        }
+
+        return seed;
+    }
+}
+
+// asks for the signature scheme the new keys of the wallet are derived with
+fn read_key_scheme() -> SignatureScheme {
+    loop {
+        let mut key_scheme_str: String = String::new();
+        println!("\nPlease enter the signature scheme of the wallet's new keys (lamport, or winternitz followed by 4, 16 or 256 -- leave empty for {}):", DEFAULT_KEY_SCHEME);
+        io::stdin().read_line(&mut key_scheme_str).expect("Failed to read line...");
+
+        if key_scheme_str.trim().is_empty() {
+            return DEFAULT_KEY_SCHEME;
+        }
+
+        match SignatureScheme::from_name(&key_scheme_str) {
+            Some(key_scheme) => return key_scheme,
+            None => println!("Unknown signature scheme..."),
         }
+    }
+}
 
+// the master seed every derived key of the wallet can be recovered from, and the fingerprint of the keys of keypairs.bin, which have to be backed up separately
+fn export_wallet_seed(wallet: &Wallet) {
+    println!("\nMaster Seed (Hex): {}", seed_to_hex(wallet.seed()));
+    println!("Master Seed (Mnemonic): {}", seed_to_mnemonic(wallet.seed()));
+    println!("Root Keys Fingerprint: {}", wallet.root_keys_fingerprint());
+    println!("The root keys of the accounts cannot be derived from the seed: back up keypairs.bin along with it, since recovering the wallet takes both...\n");
+}
 
-        println!("${0} for Account #{1}", amount, account_index);
+// every key of every account, and whether it is still fresh (it can sign once) or used (it already signed a transaction)
+fn get_wallet_keys(blockchain: &Blockchain, wallet: &Wallet) {
+    let next_block_height: u64 = blockchain.next_block_height();
+
+    for account_index in 0..wallet.account_count() {
+        println!("\n-Account #{}-", account_index);
+        for wallet_key in wallet.account_keys(account_index) {
+            let key_balance: Balance = blockchain.utxo.balance(&wallet_key.pub_key_hash, next_block_height, blockchain.params.coinbase_maturity);
+            let key_state: &str = if wallet.is_fresh(wallet_key, blockchain) { "fresh" } else { "used" };
+            println!("{} Key {}: {} (${} spendable, ${} immature)", wallet_key.keypair.pub_key().scheme(), wallet_key.pub_key_hash, key_state, key_balance.spendable, key_balance.immature);
+        }
     }
     println!("");
 }
@@ -1,55 +1,55 @@
-use crate::{classes::{block::{block::Block, blockchain::Blockchain}, lamport_signature::key_pair::{initialize_empty_key_blocks, Key}, transaction::tx::{Tx, TxInput, TxOutput}}, util::disk::load_branches_from_file};
+use crate::{classes::{block::{block::Block, blockchain::Blockchain}, lamport_signature::key_pair::Key, transaction::tx::{Tx, TxInput, TxOutput}}, util::disk::load_branches_from_file};
 
 pub fn test_blockchain_fork_detection(pub_key: &Key){
-    let tx_inputs: Vec<TxInput> = vec![TxInput::new(initialize_empty_key_blocks(), "".to_string(), true, 0)];
-    let tx_outputs: Vec<TxOutput> = vec![TxOutput::new(pub_key.clone(), 100)];
-    let tx: Tx = Tx::new(tx_inputs, tx_outputs);
-    let tx_vec = vec![tx];
+    // the coinbase transaction has to commit to the height of its block
+    let coinbase_txs = |height: u64| -> Vec<Tx> {
+        vec![Tx::new(vec![TxInput::new_coinbase(height, 0)], vec![TxOutput::new(pub_key.clone(), 100)])]
+    };
 
     let mut blockchain = Blockchain::new();
     blockchain.load_genesis_block(&pub_key, &"testbranches.bin".to_string());
 
     let block_1: Block = blockchain.blocks[0].clone();
-    let block_1_hash = block_1.block_header.hash_block();
+    let block_1_hash = block_1.block_header.hash_block().unwrap();
 
-    let mut block_2: Block = Block::new(&tx_vec, block_1_hash.clone());
+    let mut block_2: Block = blockchain.new_block(&coinbase_txs(1), &block_1_hash).unwrap();
     block_2.mine_block();
-    let block_2_hash: String = block_2.block_header.hash_block();
+    let block_2_hash: String = block_2.block_header.hash_block().unwrap();
     blockchain.accept_new_block(&block_2, &"testbranches.bin".to_string());
 
-    let mut block_3: Block = Block::new(&tx_vec, block_1_hash.clone());
+    let mut block_3: Block = blockchain.new_block(&coinbase_txs(1), &block_1_hash).unwrap();
     block_3.mine_block();
-    let block_3_hash: String = block_3.block_header.hash_block();
+    let block_3_hash: String = block_3.block_header.hash_block().unwrap();
     blockchain.accept_new_block(&block_3, &"testbranches.bin".to_string());
 
-    let mut block_4: Block = Block::new(&tx_vec, block_3_hash.clone());
+    let mut block_4: Block = blockchain.new_block(&coinbase_txs(2), &block_3_hash).unwrap();
     block_4.mine_block();
-    let block_4_hash: String = block_4.block_header.hash_block();
+    let block_4_hash: String = block_4.block_header.hash_block().unwrap();
     blockchain.accept_new_block(&block_4, &"testbranches.bin".to_string());
 
-    let mut block_5: Block = Block::new(&tx_vec, block_2_hash.clone());
+    let mut block_5: Block = blockchain.new_block(&coinbase_txs(2), &block_2_hash).unwrap();
     block_5.mine_block();
-    let block_5_hash: String = block_5.block_header.hash_block();
+    let block_5_hash: String = block_5.block_header.hash_block().unwrap();
     blockchain.accept_new_block(&block_5, &"testbranches.bin".to_string());
 
-    let mut block_6: Block = Block::new(&tx_vec, block_4_hash.clone());
+    let mut block_6: Block = blockchain.new_block(&coinbase_txs(3), &block_4_hash).unwrap();
     block_6.mine_block();
-    let block_6_hash: String = block_6.block_header.hash_block();
+    let block_6_hash: String = block_6.block_header.hash_block().unwrap();
     blockchain.accept_new_block(&block_6, &"testbranches.bin".to_string());
 
-    let mut block_7: Block = Block::new(&tx_vec, block_3_hash.clone());
+    let mut block_7: Block = blockchain.new_block(&coinbase_txs(2), &block_3_hash).unwrap();
     block_7.mine_block();
-    let block_7_hash: String = block_7.block_header.hash_block();
+    let block_7_hash: String = block_7.block_header.hash_block().unwrap();
     blockchain.accept_new_block(&block_7, &"testbranches.bin".to_string());
 
-    let mut block_8: Block = Block::new(&tx_vec, block_7_hash.clone());
+    let mut block_8: Block = blockchain.new_block(&coinbase_txs(3), &block_7_hash).unwrap();
     block_8.mine_block();
-    let block_8_hash: String = block_8.block_header.hash_block();
+    let block_8_hash: String = block_8.block_header.hash_block().unwrap();
     blockchain.accept_new_block(&block_8, &"testbranches.bin".to_string());
 
     println!("\n--- Blockchain ---");
     for block in &blockchain.blocks {
-        println!("Block hash: {}", block.block_header.hash_block());
+        println!("Block hash: {}", block.block_header.hash_block().unwrap());
     }
 
     println!("");
@@ -65,7 +65,7 @@ pub fn test_blockchain_fork_detection(pub_key: &Key){
     for loaded_chain in &loaded_chains {
         println!("-- Blockchain #{} --", i+1);
         for loaded_chain_block in &loaded_chain.blocks {
-            println!("Block hash: {}", loaded_chain_block.block_header.hash_block());
+            println!("Block hash: {}", loaded_chain_block.block_header.hash_block().unwrap());
         }
         println!("");
         i += 1;
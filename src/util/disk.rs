@@ -1,8 +1,11 @@
-use std::{fs::File, io::{self, Read, Write}};
+use std::{fs::{self, File}, io::{self, Read, Write}, path::Path};
 
-use crate::classes::{block::blockchain::Blockchain, lamport_signature::key_pair::KeyPair};
+use crate::classes::{block::blockchain::Blockchain, lamport_signature::key_pair::KeyPair, merkle_signature::key_pair::MerkleKeyPair, wallet::Wallet};
 
-pub fn save_chain_branches_to_file(chains: &Vec<Blockchain>, branches_filename: &String) -> Result<(), ()> {
+pub const WALLET_FILENAME: &str = "wallet.bin";
+pub const MERKLE_KEYPAIR_FILENAME: &str = "merklekey.bin";
+
+pub fn save_chain_branches_to_file(chains: &Vec<Blockchain>, branches_filename: &str) -> Result<(), ()> {
     let file_result = File::create(branches_filename);
     let mut file: File = match file_result {
         Ok(val) => val,
@@ -87,4 +90,130 @@ pub fn load_keypairs_from_file() -> Result<Vec<KeyPair>, ()> {
     }
 
     Ok(decoded)
+}
+
+/* The wallet is written to a temporary file that then replaces the previous wallet file, so that a crash while saving never leaves a half written wallet behind
+(which could lose a key that already received money). */
+pub fn save_wallet_to_file(wallet: &Wallet, wallet_filename: &str) -> Result<(), ()> {
+    let encoded: Vec<u8> = match bincode::serialize(wallet) {
+        Ok(val) => val,
+        Err(_e) => {
+            println!("Could not serialize the wallet...");
+            return Err(());
+        }
+    };
+
+    write_file_atomically(&encoded, wallet_filename, "wallet")
+}
+
+pub fn load_wallet_from_file(wallet_filename: &str) -> Result<Wallet, ()> {
+    let mut file: File = match File::open(wallet_filename) {
+        Ok(val) => val,
+        Err(_err) => return Err(()),
+    };
+
+    let mut encoded: Vec<u8> = Vec::new();
+    if file.read_to_end(&mut encoded).is_err() {
+        println!("Could not load the wallet...");
+        return Err(());
+    }
+
+    match bincode::deserialize(&encoded) {
+        Ok(wallet) => Ok(wallet),
+        Err(_e) => {
+            println!("Failed to decode the wallet saved to disk...");
+            Err(())
+        }
+    }
+}
+
+/* The tree of a Merkle key pair never changes, so it is saved once, when the key pair is created. Only the index of the next leaf changes: it is saved
+on its own, in a small file next to the tree, every time a leaf is used and before the signature of the leaf is made, so that a leaf can never sign twice,
+even if the node crashes right after signing. The wallet does not hold Merkle key pairs, so the node keeps its own in merklekey.bin, next to the wallet. */
+pub fn save_merkle_keypair_to_file(keypair: &MerkleKeyPair, keypair_filename: &str) -> Result<(), ()> {
+    let encoded: Vec<u8> = match bincode::serialize(keypair) {
+        Ok(val) => val,
+        Err(_e) => {
+            println!("Could not serialize the Merkle key pair...");
+            return Err(());
+        }
+    };
+
+    write_file_atomically(&encoded, keypair_filename, "Merkle key pair")?;
+    save_merkle_leaf_index_to_file(keypair.next_leaf_index(), keypair_filename)
+}
+
+pub fn save_merkle_leaf_index_to_file(next_leaf_index: u32, keypair_filename: &str) -> Result<(), ()> {
+    write_file_atomically(&next_leaf_index.to_be_bytes(), &merkle_leaf_index_filename(keypair_filename), "Merkle key state")
+}
+
+pub fn load_merkle_keypair_from_file(keypair_filename: &str) -> Result<MerkleKeyPair, ()> {
+    let mut file: File = match File::open(keypair_filename) {
+        Ok(val) => val,
+        Err(_err) => return Err(()),
+    };
+
+    let mut encoded: Vec<u8> = Vec::new();
+    if file.read_to_end(&mut encoded).is_err() {
+        println!("Could not load the Merkle key pair...");
+        return Err(());
+    }
+
+    let mut keypair: MerkleKeyPair = match bincode::deserialize(&encoded) {
+        Ok(keypair) => keypair,
+        Err(_e) => {
+            println!("Failed to decode the Merkle key pair saved to disk...");
+            return Err(());
+        }
+    };
+
+    // without its state, the key pair could sign again with leaves that were already used, so it is not loaded at all
+    let leaf_index_bytes: [u8; 4] = match fs::read(merkle_leaf_index_filename(keypair_filename)).ok().and_then(|bytes| bytes.try_into().ok()) {
+        Some(val) => val,
+        None => {
+            println!("Could not load the state of the Merkle key pair...");
+            return Err(());
+        }
+    };
+    keypair.restore_next_leaf_index(u32::from_be_bytes(leaf_index_bytes));
+
+    Ok(keypair)
+}
+
+fn merkle_leaf_index_filename(keypair_filename: &str) -> String {
+    format!("{}.leaf", keypair_filename)
+}
+
+/* Writes the bytes to a temporary file that then replaces the given file, so that the file is either entirely the old version or entirely the new one,
+whenever a crash happens. The directory is synced as well, so that the new version is still there after a power loss. */
+fn write_file_atomically(encoded: &[u8], filename: &str, description: &str) -> Result<(), ()> {
+    let temp_filename: String = format!("{}.tmp", filename);
+    let mut file: File = match File::create(&temp_filename) {
+        Ok(val) => val,
+        Err(_err) => {
+            println!("Could not create the {} file...", description);
+            return Err(());
+        }
+    };
+
+    if let Err(write_error) = file.write_all(encoded).and_then(|_| file.sync_all()) {
+        println!("Failed to write the {} to disk: {}", description, write_error);
+        return Err(());
+    }
+
+    if let Err(rename_error) = fs::rename(&temp_filename, filename) {
+        println!("Failed to replace the {} file: {}", description, rename_error);
+        return Err(());
+    }
+
+    let directory: &Path = match Path::new(filename).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    if let Err(sync_error) = File::open(directory).and_then(|directory_file| directory_file.sync_all()) {
+        println!("Failed to sync the directory of the {} file: {}", description, sync_error);
+        return Err(());
+    }
+
+    Ok(())
 }
\ No newline at end of file
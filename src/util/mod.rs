@@ -1,2 +1,4 @@
 pub mod conversions;
-pub mod disk;
\ No newline at end of file
+pub mod disk;
+#[cfg(test)]
+pub mod test_util;
\ No newline at end of file
@@ -0,0 +1,131 @@
+use std::ops::{Deref, DerefMut};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::fs;
+
+use crate::classes::block::block::Block;
+use crate::classes::block::blockchain::{BlockAcceptance, Blockchain};
+use crate::classes::consensus::params::ConsensusParams;
+use crate::classes::lamport_signature::key_pair::{Key, KeyPair};
+use crate::classes::signature::scheme::{PublicKey, Signature, SignatureScheme};
+use crate::classes::transaction::tx::{Tx, TxInput, TxOutput};
+use crate::util::disk::load_keypairs_from_file;
+
+static NEXT_TEMP_DIR_INDEX: AtomicUsize = AtomicUsize::new(0);
+
+// a directory of its own under the system's temporary directory, removed once the test is done, so that tests never write into the working directory or into each other's files
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new() -> TempDir {
+        let temp_dir_index: usize = NEXT_TEMP_DIR_INDEX.fetch_add(1, Ordering::SeqCst);
+        let path: PathBuf = std::env::temp_dir().join(format!("rust-blockchain-test-{}-{}", std::process::id(), temp_dir_index));
+        fs::create_dir_all(&path).expect("Could not create a temporary directory");
+        TempDir { path }
+    }
+
+    pub fn file(&self, filename: &str) -> String {
+        self.path.join(filename).to_string_lossy().to_string()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+// the key pairs of keypairs.bin, which the genesis block of every network pays (cargo runs the tests from the directory it is in)
+pub fn test_keypairs() -> &'static [KeyPair] {
+    static KEYPAIRS: OnceLock<Vec<KeyPair>> = OnceLock::new();
+    KEYPAIRS.get_or_init(|| load_keypairs_from_file().expect("Could not load keypairs.bin"))
+}
+
+// the coinbase transaction of the block at the given height
+pub fn coinbase_tx(height: u64, extra_nonce: u64, pub_key: impl Into<PublicKey>, amount: u64) -> Tx {
+    Tx::new(vec![TxInput::new_coinbase(height, extra_nonce)], vec![TxOutput::new(pub_key, amount)])
+}
+
+// a transaction spending the given outputs (transaction id and output index) and paying the amounts to the recipient, signed by the Lamport key pair that owns the outputs
+pub fn spend_tx(spent_outpoints: &[(String, usize)], owner: &KeyPair, recipient: impl Into<PublicKey>, amounts: &[u64]) -> Tx {
+    let mut tx: Tx = unsigned_spend_tx(spent_outpoints, SignatureScheme::Lamport, recipient, amounts);
+    // every input signs the same message, so one signature is enough for all of them
+    let signature: Signature = owner.create_signature(&tx).into();
+    for tx_input in tx.inputs.iter_mut() {
+        tx_input.signature = signature.clone();
+    }
+    tx
+}
+
+// the same transaction before it is signed, spending outputs paid to keys of the given scheme
+pub fn unsigned_spend_tx(spent_outpoints: &[(String, usize)], scheme: SignatureScheme, recipient: impl Into<PublicKey>, amounts: &[u64]) -> Tx {
+    let recipient: PublicKey = recipient.into();
+    let tx_inputs: Vec<TxInput> = spent_outpoints.iter().map(|(tx_id, index)| TxInput::new(Signature::unsigned(scheme), tx_id.clone(), false, *index)).collect();
+    let tx_outputs: Vec<TxOutput> = amounts.iter().map(|amount| TxOutput::new(recipient.clone(), *amount)).collect();
+    Tx::new(tx_inputs, tx_outputs)
+}
+
+// a main network blockchain with its genesis block loaded, which saves its branches into a temporary directory
+pub struct TestChain {
+    pub blockchain: Blockchain,
+    pub temp_dir: TempDir,
+    pub branches_filename: String,
+}
+
+impl TestChain {
+    pub fn new(genesis_pub_key: &Key) -> TestChain {
+        TestChain::with_params(genesis_pub_key, |_params| {})
+    }
+
+    // the consensus parameters are changed before the genesis block is loaded
+    pub fn with_params(genesis_pub_key: &Key, configure: impl FnOnce(&mut ConsensusParams)) -> TestChain {
+        let temp_dir: TempDir = TempDir::new();
+        let branches_filename: String = temp_dir.file("testbranches.bin");
+        let mut blockchain: Blockchain = Blockchain::new();
+        configure(&mut blockchain.params);
+        blockchain.load_genesis_block(genesis_pub_key, &branches_filename);
+
+        TestChain {
+            blockchain,
+            temp_dir,
+            branches_filename,
+        }
+    }
+
+    pub fn genesis_hash(&self) -> String {
+        self.blockchain.blocks[0].block_header.hash_block().unwrap()
+    }
+
+    pub fn tip_hash(&self) -> String {
+        self.blockchain.blocks.last().unwrap().block_header.hash_block().unwrap()
+    }
+
+    pub fn accept(&mut self, block: &Block) -> BlockAcceptance {
+        self.blockchain.accept_new_block(block, &self.branches_filename)
+    }
+
+    // mines a block with the given transactions on top of the given block and hands it to the blockchain
+    pub fn mine(&mut self, txs: Vec<Tx>, prev_block_hash: &str) -> Block {
+        let mut block: Block = self.blockchain.new_block(&txs, prev_block_hash).expect("The parent block is not known");
+        block.mine_block();
+        self.accept(&block);
+        block
+    }
+}
+
+impl Deref for TestChain {
+    type Target = Blockchain;
+
+    fn deref(&self) -> &Blockchain {
+        &self.blockchain
+    }
+}
+
+impl DerefMut for TestChain {
+    fn deref_mut(&mut self) -> &mut Blockchain {
+        &mut self.blockchain
+    }
+}